@@ -0,0 +1,42 @@
+/// Benchmarks searching a lot of items, which updates the matches after every key press.
+use criterion::{criterion_group, criterion_main, Criterion};
+use fuzzy_finder::item::{Field, Item};
+use fuzzy_finder::FuzzyFinder;
+use std::io;
+use termion::event::Key;
+
+/// Items like lk's, for `scripts` scripts with `functions` functions each.
+fn items(scripts: usize, functions: usize) -> Vec<Item<usize>> {
+    (0..scripts * functions)
+        .map(|i| {
+            let script = format!("scripts_{}/deploy_{}.sh", i % 7, i / functions);
+            let function = format!("step_{}", i % functions);
+            let fields = vec![
+                Field::new(&script, 100),
+                Field::separator(" - "),
+                Field::new(&function, 100),
+            ];
+            Item::with_fields(fields, i)
+        })
+        .collect()
+}
+
+fn update_matches(c: &mut Criterion) {
+    let items = items(100, 100);
+    // Each key press updates the matches, and then esc cancels.
+    let keys: Vec<Key> = "dep 4 st"
+        .chars()
+        .map(Key::Char)
+        .chain([Key::Esc])
+        .collect();
+    c.bench_function("typing a search over 10,000 items", |b| {
+        b.iter(|| {
+            FuzzyFinder::builder(items.clone())
+                .run_with(io::sink(), keys.iter().cloned().map(Ok))
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, update_matches);
+criterion_main!(benches);
@@ -0,0 +1,214 @@
+/// Configures and launches a `FuzzyFinder`.
+use super::colours::Colours;
+use super::item::Item;
+use super::keys::Plain;
+use super::{FuzzyFinder, Outcome};
+use anyhow::Result;
+use std::io::{self, Write};
+use termion::event::Key;
+
+/// How the search term's case is treated when matching.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CaseMode {
+    /// Case-insensitive, unless the search term contains an upper case character.
+    #[default]
+    Smart,
+    /// Always match case.
+    Respect,
+    /// Never match case.
+    Ignore,
+}
+
+/// How many matches are kept by default. See `FuzzyFinderBuilder::max_matches`.
+pub const DEFAULT_MAX_MATCHES: usize = 500;
+
+/// Produces the lines to show in the preview for the selected item.
+pub type Preview<T> = Box<dyn Fn(&T) -> Vec<String>>;
+
+pub struct FuzzyFinderBuilder<T>
+where
+    T: Clone,
+{
+    pub(crate) items: Vec<Item<T>>,
+    pub(crate) lines_to_show: i8,
+    pub(crate) initial_query: String,
+    pub(crate) initial_selection: usize,
+    pub(crate) case_mode: CaseMode,
+    pub(crate) preview: Option<Preview<T>>,
+    pub(crate) preview_height: u16,
+    pub(crate) colours: Colours,
+    pub(crate) prompt_symbol: String,
+    pub(crate) max_matches: usize,
+    pub(crate) multi_select: bool,
+}
+
+impl<T> FuzzyFinderBuilder<T>
+where
+    T: Clone,
+{
+    pub fn new(items: Vec<Item<T>>) -> Self {
+        FuzzyFinderBuilder {
+            items,
+            lines_to_show: 8,
+            initial_query: String::from(""),
+            initial_selection: 0,
+            case_mode: CaseMode::default(),
+            preview: None,
+            preview_height: 5,
+            colours: Colours::default(),
+            prompt_symbol: String::from("$"),
+            max_matches: DEFAULT_MAX_MATCHES,
+            multi_select: false,
+        }
+    }
+
+    /// The number of lines of results to show above the prompt.
+    pub fn lines_to_show(mut self, lines_to_show: i8) -> Self {
+        self.lines_to_show = lines_to_show;
+        self
+    }
+
+    /// The search term to start with, as if the user had already typed it.
+    pub fn initial_query(mut self, initial_query: &str) -> Self {
+        self.initial_query = initial_query.to_string();
+        self
+    }
+
+    /// The match to select to start with, where 0 is the best match. If there aren't that many
+    /// matches then the last one is selected.
+    pub fn initial_selection(mut self, initial_selection: usize) -> Self {
+        self.initial_selection = initial_selection;
+        self
+    }
+
+    pub fn case_mode(mut self, case_mode: CaseMode) -> Self {
+        self.case_mode = case_mode;
+        self
+    }
+
+    /// Shows the lines returned by `preview` for the selected item, below the prompt.
+    pub fn preview<F>(mut self, preview: F) -> Self
+    where
+        F: Fn(&T) -> Vec<String> + 'static,
+    {
+        self.preview = Some(Box::new(preview));
+        self
+    }
+
+    /// The maximum number of preview lines to show. Longer previews are truncated.
+    pub fn preview_height(mut self, preview_height: u16) -> Self {
+        self.preview_height = preview_height;
+        self
+    }
+
+    /// How many of the best matches to keep after each key press. The rest can't be scrolled
+    /// to, but there's less to sort and render with a lot of items. At least `lines_to_show`
+    /// are kept.
+    pub fn max_matches(mut self, max_matches: usize) -> Self {
+        self.max_matches = max_matches;
+        self
+    }
+
+    pub fn colours(mut self, colours: Colours) -> Self {
+        self.colours = colours;
+        self
+    }
+
+    /// What's shown before the search term, in the prompt colour. Defaults to `$`.
+    pub fn prompt_symbol(mut self, prompt_symbol: &str) -> Self {
+        self.prompt_symbol = prompt_symbol.to_string();
+        self
+    }
+
+    /// Runs the fuzzy finder in the terminal, returning the item the user selected, if any.
+    pub fn run(self) -> Result<Option<T>> {
+        self.run_outcome().map(Outcome::picked)
+    }
+
+    /// Runs the fuzzy finder in the terminal, like `run`, but says how it ended. If nothing
+    /// matched then that's what the user typed.
+    pub fn run_outcome(self) -> Result<Outcome<T>> {
+        Ok(first(
+            FuzzyFinder::with_terminal(self)?.find_catching_panics()?,
+        ))
+    }
+
+    /// Runs the fuzzy finder in the terminal, letting the user mark several items with tab,
+    /// rather than completing with it. Returns the marked items, in the order they were marked,
+    /// or the selected item if none were, or nothing if the user cancelled.
+    pub fn run_multi(mut self) -> Result<Vec<T>> {
+        self.multi_select = true;
+        let outcome = FuzzyFinder::with_terminal(self)?.find_catching_panics()?;
+        Ok(outcome.picked().unwrap_or_default())
+    }
+
+    /// Runs the fuzzy finder, rendering to `output` and reading key presses from `keys`,
+    /// rather than the terminal. Rendering starts from the top of `output`. The fuzzy finder
+    /// is cancelled if `keys` runs out.
+    pub fn run_with<W, K>(self, output: W, keys: K) -> Result<Option<T>>
+    where
+        W: Write,
+        K: Iterator<Item = io::Result<Key>>,
+    {
+        self.run_outcome_with(output, keys).map(Outcome::picked)
+    }
+
+    /// Runs the fuzzy finder like `run_with`, but says how it ended, like `run_outcome`.
+    pub fn run_outcome_with<W, K>(self, output: W, keys: K) -> Result<Outcome<T>>
+    where
+        W: Write,
+        K: Iterator<Item = io::Result<Key>>,
+    {
+        Ok(first(
+            FuzzyFinder::new(self, Plain(output), keys, 1, 0).find_catching_panics()?,
+        ))
+    }
+
+    /// Runs the fuzzy finder like `run_with`, but lets the user mark several items, like
+    /// `run_multi`.
+    pub fn run_multi_with<W, K>(mut self, output: W, keys: K) -> Result<Vec<T>>
+    where
+        W: Write,
+        K: Iterator<Item = io::Result<Key>>,
+    {
+        self.multi_select = true;
+        let outcome = FuzzyFinder::new(self, Plain(output), keys, 1, 0).find_catching_panics()?;
+        Ok(outcome.picked().unwrap_or_default())
+    }
+
+    /// The number of rows the fuzzy finder takes up: the results, a blank line, the prompt and
+    /// the preview.
+    pub(crate) fn rows(&self) -> u16 {
+        self.lines_to_show.max(0) as u16 + 2 + self.preview_rows()
+    }
+
+    /// Shows fewer results, and then a shorter preview, so that everything fits in a terminal
+    /// `terminal_height` rows high. We always show at least one result.
+    pub(crate) fn fit_to(mut self, terminal_height: u16) -> Self {
+        // The blank line and the prompt.
+        let available = terminal_height.saturating_sub(2);
+        let preview = self.preview_rows();
+        let lines = (self.lines_to_show.max(1) as u16)
+            .min(available.saturating_sub(preview))
+            .max(1);
+        self.lines_to_show = lines as i8;
+        if self.preview.is_some() {
+            self.preview_height = preview.min(available.saturating_sub(lines));
+        }
+        self
+    }
+
+    /// The number of rows the preview takes up.
+    pub(crate) fn preview_rows(&self) -> u16 {
+        if self.preview.is_some() {
+            self.preview_height
+        } else {
+            0
+        }
+    }
+}
+
+/// Only one item is picked when the user can't mark several.
+fn first<T>(outcome: Outcome<Vec<T>>) -> Outcome<T> {
+    outcome.map(|items| items.into_iter().next().unwrap())
+}
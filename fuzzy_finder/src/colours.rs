@@ -0,0 +1,26 @@
+/// The colours used to render the fuzzy finder.
+use pastel_colours::{BLUE_FG, DARK_BLUE_BG, DARK_GREY_BG, GREEN_FG};
+
+/// Each colour is the escape code that sets it, e.g. `pastel_colours::Colour::bg`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Colours {
+    /// The background of characters that match the search term.
+    pub matched: String,
+    /// The background of the selected line, and of the gutter.
+    pub selected: String,
+    /// The `>` that points at the selected line.
+    pub pointer: String,
+    /// The prompt before the search term.
+    pub prompt: String,
+}
+
+impl Default for Colours {
+    fn default() -> Self {
+        Colours {
+            matched: DARK_BLUE_BG.to_string(),
+            selected: DARK_GREY_BG.to_string(),
+            pointer: GREEN_FG.to_string(),
+            prompt: BLUE_FG.to_string(),
+        }
+    }
+}
@@ -9,6 +9,12 @@ where
     pub is_blank: bool,
     pub name: String,
     pub score: Option<(i64, Vec<usize>)>,
+    /// Added to the score when sorting matches, so this item ranks higher.
+    pub boost: i64,
+    /// Typing this followed by `>` narrows the search to items with the same scope.
+    pub scope: Option<String>,
+    /// The parts of the name, if they're matched separately. Otherwise the whole name is.
+    pub fields: Vec<Field>,
     pub item: Option<T>,
 }
 
@@ -24,6 +30,46 @@ where
             name,
             item: Some(item),
             score: None, // It won't be scored yet.
+            boost: 0,
+            scope: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// An item named by joining `fields`. Each word of the search has to match within one of
+    /// them, and matches count for as much as the field's weight.
+    pub fn with_fields(fields: Vec<Field>, item: T) -> Self {
+        let name = fields.iter().map(|field| field.text.as_str()).collect();
+        Item::<T> {
+            fields,
+            ..Item::new(name, item)
+        }
+    }
+
+    /// Ranks this item higher than other items that match just as well.
+    pub fn with_boost(mut self, boost: i64) -> Self {
+        self.boost = boost;
+        self
+    }
+
+    /// Puts this item in `scope`, e.g. the file it comes from, so the user can search
+    /// within just that scope by typing `scope>`.
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope = Some(scope.to_string());
+        self
+    }
+
+    /// The same item, holding `f` of what this one holds, e.g. to pair it with the item's name
+    /// so the name's returned when it's picked.
+    pub fn map<U: Clone>(self, f: impl FnOnce(T) -> U) -> Item<U> {
+        Item::<U> {
+            is_blank: self.is_blank,
+            name: self.name,
+            score: self.score,
+            boost: self.boost,
+            scope: self.scope,
+            fields: self.fields,
+            item: self.item.map(f),
         }
     }
 
@@ -34,7 +80,33 @@ where
             is_blank: true,
             name: "".to_string(),
             score: None,
+            boost: 0,
+            scope: None,
+            fields: Vec::new(),
             item: None,
         }
     }
 }
+
+/// Part of an item's name that's matched on its own, e.g. a script's directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub text: String,
+    /// How much a match in this field counts, as a percentage of its score. Fields weighted 0,
+    /// like separators, are never matched.
+    pub weight: i64,
+}
+
+impl Field {
+    pub fn new(text: &str, weight: i64) -> Self {
+        Field {
+            text: text.to_string(),
+            weight,
+        }
+    }
+
+    /// Text that's shown but never matched, like the ` - ` between a script and a function.
+    pub fn separator(text: &str) -> Self {
+        Field::new(text, 0)
+    }
+}
@@ -0,0 +1,302 @@
+/// Turns the keys read from an async terminal into the keys the user actually pressed.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Instant;
+use termion::event::Key;
+
+/// The key presses we're interested in, e.g. the arrows, are all preceded by escape, ^[.
+/// E.g. up is ^[[A and down is ^[[B. Reading asynchronously we get these as separate keys,
+/// so the question is how do we identify an escape key by itself? If it's ^[[A then that's
+/// ^[ followed almost instantly by [A. If we have ^[ followed by a pause then we know it's
+/// not an escape for some other key, but an escape by itself. That's what `ESCAPE_MICROS` is.
+/// NB: some terminals might send these bytes too slowly and escape might not be caught.
+/// NB: some terminals might use different escape keys entirely.
+/// Run 'sed -n l' to explore escape codes.
+const ESCAPE_MICROS: u128 = 100;
+
+/// The escape sequences we turn into keys. Terminals don't agree on home and end, or on page up
+/// and page down in some cases, so there's more than one for those.
+const SEQUENCES: [(&str, Key); 14] = [
+    ("^[[A", Key::Up),
+    ("^[[B", Key::Down),
+    ("^[[5~", Key::PageUp),
+    ("^[[6~", Key::PageDown),
+    ("^[[H", Key::Home),
+    ("^[[F", Key::End),
+    ("^[OH", Key::Home),
+    ("^[OF", Key::End),
+    ("^[[1~", Key::Home),
+    ("^[[4~", Key::End),
+    ("^[[7~", Key::Home),
+    ("^[[8~", Key::End),
+    ("^[OA", Key::Up),
+    ("^[OB", Key::Down),
+];
+
+/// How long to wait for a key press before reporting that there's nothing to read.
+const POLL_MILLIS: i32 = 10;
+
+/// Reads from the tty without blocking, and without a background thread. termion's
+/// `async_stdin` reads on a thread that outlives the fuzzy finder and swallows the next
+/// key press. That might have been meant for whatever runs after us, e.g. a confirmation.
+pub struct PolledTty {
+    tty: File,
+}
+
+impl PolledTty {
+    pub fn new() -> io::Result<Self> {
+        Ok(PolledTty {
+            tty: File::open("/dev/tty")?,
+        })
+    }
+}
+
+impl Read for PolledTty {
+    /// Reads whatever's waiting, or nothing at all if no key is pressed soon.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut poll_fd, 1, POLL_MILLIS) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Ok(0),
+            _ => self.tty.read(buf),
+        }
+    }
+}
+
+/// The tty in raw mode, for rendering to. It's put back how it was when dropped. termion's
+/// `RawTerminal` only works on stdout, which might be piped somewhere else.
+pub struct RawTty {
+    tty: File,
+    original: libc::termios,
+}
+
+impl RawTty {
+    pub fn new() -> io::Result<Self> {
+        let tty = termion::get_tty()?;
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut original) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &raw) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawTty { tty, original })
+    }
+
+    /// The number of rows in the terminal, like `termion::terminal_size` but for the tty.
+    pub fn height(&self) -> io::Result<u16> {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(size.ws_row)
+    }
+}
+
+impl Write for RawTty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tty.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tty.flush()
+    }
+}
+
+/// Somewhere the fuzzy finder renders to. It's restored as soon as the fuzzy finder's done,
+/// however it ends.
+pub trait Terminal: Write {
+    /// Puts the terminal back how it was before the fuzzy finder started.
+    fn restore(&mut self) -> io::Result<()>;
+}
+
+impl Terminal for RawTty {
+    /// Turns raw mode off again, rather than waiting for us to be dropped.
+    fn restore(&mut self) -> io::Result<()> {
+        self.tty.flush()?;
+        if unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Output that isn't a terminal, like what `run_with` renders to, so there's nothing to
+/// restore but what's been written.
+pub(crate) struct Plain<W>(pub W);
+
+impl<W: Write> Write for Plain<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Terminal for Plain<W> {
+    fn restore(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for RawTty {
+    /// The fuzzy finder normally restores the terminal itself, but this runs if it panics too,
+    /// so the terminal's never left without echo or a cursor. The panic leaves whatever we'd
+    /// rendered behind, so that's cleared away first.
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let _ = write!(
+                self.tty,
+                "{}{}{}",
+                termion::cursor::Restore,
+                termion::clear::AfterCursor,
+                termion::cursor::Show
+            );
+            let _ = self.tty.flush();
+        }
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original) };
+    }
+}
+
+pub struct EscapeSequences<I>
+where
+    I: Iterator<Item = io::Result<Key>>,
+{
+    /// Keys as they're read. `None` means there's nothing to read yet.
+    keys: I,
+    escaped: String,
+    instant: Instant,
+}
+
+impl<I> EscapeSequences<I>
+where
+    I: Iterator<Item = io::Result<Key>>,
+{
+    pub fn new(keys: I) -> Self {
+        EscapeSequences {
+            keys,
+            escaped: String::from(""),
+            instant: Instant::now(),
+        }
+    }
+}
+
+impl<I> Iterator for EscapeSequences<I>
+where
+    I: Iterator<Item = io::Result<Key>>,
+{
+    type Item = io::Result<Key>;
+
+    /// Waits for the next key. Never returns `None`.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.escaped == "^[" && self.instant.elapsed().as_micros() > ESCAPE_MICROS {
+                self.escaped = String::from("");
+                return Some(Ok(Key::Esc));
+            }
+
+            match self.keys.next() {
+                Some(Ok(Key::Esc)) if self.escaped.is_empty() => {
+                    // All we're doing here is recording that we've entered an escape sequence.
+                    // It's actually handled when we handle chars.
+                    self.escaped = String::from("^[");
+                    self.instant = Instant::now();
+                }
+                Some(Ok(Key::Char(c))) if !self.escaped.is_empty() => {
+                    self.escaped = format!("{}{}", self.escaped, c);
+                    if let Some((_, key)) = SEQUENCES
+                        .iter()
+                        .find(|(sequence, _)| *sequence == self.escaped)
+                    {
+                        self.escaped = String::from("");
+                        return Some(Ok(*key));
+                    }
+                    if !SEQUENCES
+                        .iter()
+                        .any(|(sequence, _)| sequence.starts_with(&self.escaped))
+                    {
+                        // This is nothing we recognise so let's abandon the escape sequence.
+                        self.escaped = String::from("");
+                    }
+                }
+                Some(key) => return Some(key),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: Vec<Key>) -> EscapeSequences<std::vec::IntoIter<io::Result<Key>>> {
+        EscapeSequences::new(keys.into_iter().map(Ok).collect::<Vec<_>>().into_iter())
+    }
+
+    #[test]
+    fn test_arrows() {
+        let mut keys = keys(vec![
+            Key::Esc,
+            Key::Char('['),
+            Key::Char('A'),
+            Key::Esc,
+            Key::Char('['),
+            Key::Char('B'),
+        ]);
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Up);
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Down);
+    }
+
+    #[test]
+    fn test_paging_and_home_and_end() {
+        let typed = |sequence: &str| {
+            std::iter::once(Key::Esc)
+                .chain(sequence.chars().map(Key::Char))
+                .collect::<Vec<Key>>()
+        };
+        let mut keys = keys(
+            ["[5~", "[6~", "[H", "[F", "OH", "[4~"]
+                .iter()
+                .flat_map(|sequence| typed(sequence))
+                .collect(),
+        );
+        for key in [
+            Key::PageUp,
+            Key::PageDown,
+            Key::Home,
+            Key::End,
+            Key::Home,
+            Key::End,
+        ] {
+            assert_eq!(keys.next().unwrap().unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn test_escape_by_itself() {
+        let mut keys = keys(vec![Key::Char('a'), Key::Esc]);
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Char('a'));
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Esc);
+    }
+
+    #[test]
+    fn test_unknown_sequence_is_abandoned() {
+        let mut keys = keys(vec![
+            Key::Esc,
+            Key::Char('['),
+            Key::Char('Z'),
+            Key::Char('a'),
+        ]);
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Char('a'));
+    }
+}
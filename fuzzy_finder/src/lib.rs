@@ -1,76 +1,213 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use builder::{CaseMode, FuzzyFinderBuilder, Preview};
+use colours::Colours;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use item::Item;
+use item::{Field, Item};
+use keys::{EscapeSequences, PolledTty, RawTty};
 use list::List;
-use pastel_colours::{
-    BLUE_FG, DARK_BLUE_BG, DARK_GREY_BG, DARK_GREY_FG, GREEN_FG, RESET_BG, RESET_FG,
-};
-use std::io::{stdout, Stdout, Write};
-use std::time::Instant;
+use pastel_colours::{DARK_GREY_FG, GREY_FG, RESET_BG, RESET_FG};
+use std::io::{self, Write};
+use std::panic::AssertUnwindSafe;
 use termion::clear::CurrentLine;
 use termion::cursor::DetectCursorPos;
 use termion::cursor::Show;
 use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::{IntoRawMode, RawTerminal};
+use termion::input::{Keys, TermRead};
+use unicode_width::UnicodeWidthStr;
 
+pub mod builder;
+pub mod colours;
 pub mod item;
+mod keys;
 mod list;
+
+pub use keys::Terminal;
 // TODO: search for ui_state and rename the stupid thing. Same with View.
 
-pub struct FuzzyFinder<T>
+/// Shown in place of the results when nothing matches the search.
+const NO_MATCHES: &str = "No matches";
+
+/// Shown next to the items that are marked, when several can be picked.
+const MARK: &str = "+";
+
+/// Shown before the search while matches are sorted by name.
+const SORTED_BY_NAME: &str = "a-z ";
+
+/// How matches are ordered. Ctrl-s switches between them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sort {
+    /// Best match first.
+    Score,
+    /// Alphabetically, ignoring case.
+    Name,
+}
+
+/// How a fuzzy find ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<T> {
+    /// The user selected this item.
+    Picked(T),
+    /// The user pressed enter, but nothing matched what they'd typed.
+    NoMatch(String),
+    /// The user cancelled, e.g. with esc or ctrl-c.
+    Cancelled,
+}
+
+impl<T> Outcome<T> {
+    /// The item the user selected, if they selected one.
+    pub fn picked(self) -> Option<T> {
+        match self {
+            Outcome::Picked(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    /// Changes what was picked, if anything was.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Outcome<U> {
+        match self {
+            Outcome::Picked(item) => Outcome::Picked(f(item)),
+            Outcome::NoMatch(search) => Outcome::NoMatch(search),
+            Outcome::Cancelled => Outcome::Cancelled,
+        }
+    }
+}
+
+pub struct FuzzyFinder<T, W, K>
 where
     T: Clone,
+    W: Terminal,
+    K: Iterator<Item = io::Result<Key>>,
 {
     search_term: String,
+    /// Only items in this scope are searched.
+    scope: Option<String>,
+    sort: Sort,
+    initial_selection: usize,
     all_items: Vec<Item<T>>,
     matches: Vec<Item<T>>,
+    /// Where each of the matches is in `all_items`.
+    match_indices: Vec<usize>,
+    /// How many items match, including the ones past `max_matches` that weren't kept.
+    total_matches: usize,
+    /// Whether tab marks items, so several can be picked at once, rather than completing.
+    multi_select: bool,
+    /// The `all_items` the user's marked, in the order they marked them.
+    marked: Vec<usize>,
     console_offset: u16,
-    stdout: RawTerminal<Stdout>,
+    stdout: W,
+    keys: K,
     first: bool,
     list: List<T>,
     positive_space_remaining: u16,
+    matcher: SkimMatcherV2,
+    preview: Option<Preview<T>>,
+    preview_height: u16,
+    colours: Colours,
+    prompt_symbol: String,
+    /// Only this many of the best matches are kept in `matches`.
+    max_matches: usize,
 }
 
-impl<T> FuzzyFinder<T>
+impl<T> FuzzyFinder<T, RawTty, EscapeSequences<Keys<PolledTty>>>
 where
     T: Clone,
 {
-    fn new(functions: Vec<Item<T>>, lines_to_show: i8) -> Self {
+    /// Starts configuring a fuzzy finder over `items`.
+    pub fn builder(items: Vec<Item<T>>) -> FuzzyFinderBuilder<T> {
+        FuzzyFinderBuilder::new(items)
+    }
+
+    /// Creates a fuzzy finder that renders to, and reads keys from, the terminal. That's the
+    /// tty rather than stdout, so stdout can be piped somewhere else.
+    fn with_terminal(mut builder: FuzzyFinderBuilder<T>) -> Result<Self> {
         // We need to know where to start rendering from. We can't do this later because
         // we overwrite the cursor. Maybe we shouldn't do this? (TODO)
-        let mut stdout = stdout().into_raw_mode().unwrap();
-
-        write!(stdout, "{}", termion::cursor::Save).unwrap();
-        let mut positive_space_remaining = 0;
-        let console_offset = if stdout.cursor_pos().is_ok() {
-            let cursor_pos_y = stdout.cursor_pos().unwrap().1;
-
-            let terminal_height = termion::terminal_size().unwrap().1;
-            let starting_y = cursor_pos_y;
-            let ending_y = starting_y + lines_to_show as u16;
-            let space_remaining: i16 = terminal_height as i16 - ending_y as i16;
-            positive_space_remaining = if space_remaining < 0 {
-                space_remaining.abs().try_into().unwrap()
-            } else {
-                0
-            };
-            cursor_pos_y
-        } else {
-            log::error!("Cannot get cursor!");
-            0
+        let mut tty = RawTty::new()?;
+
+        let terminal_height = tty.height()?;
+        let (console_offset, to_start) = start(tty.cursor_pos(), terminal_height);
+        write!(tty, "{to_start}{}", termion::cursor::Save)?;
+        // Show fewer lines if they won't all fit, so a small terminal is still usable.
+        builder = builder.fit_to(terminal_height);
+        let ending_y = console_offset + builder.rows() - 1;
+        // How far the terminal has to scroll to fit us below the cursor.
+        let positive_space_remaining = ending_y.saturating_sub(terminal_height);
+
+        let keys = EscapeSequences::new(PolledTty::new()?.keys());
+        Ok(FuzzyFinder::new(
+            builder,
+            tty,
+            keys,
+            console_offset,
+            positive_space_remaining,
+        ))
+    }
+}
+
+/// Where the finder starts, given where the cursor is: the row it renders its first line on,
+/// and what to write to get there. That's the next line if the cursor's part way along one,
+/// e.g. after a prompt without a newline, so we don't draw over what's on it. If we can't
+/// tell where the cursor is then we start at the bottom of the terminal, which scrolls to
+/// make space for us, rather than drawing over whatever's at the top.
+fn start(cursor: io::Result<(u16, u16)>, terminal_height: u16) -> (u16, String) {
+    match cursor {
+        Ok((1, y)) => (y, String::new()),
+        // At the bottom the terminal scrolls, so the next line has the same row.
+        Ok((_, y)) => ((y + 1).min(terminal_height), "\r\n".to_string()),
+        Err(err) => {
+            log::error!("Cannot get cursor! Starting at the bottom of the terminal. {err}");
+            let bottom = terminal_height.max(1);
+            (bottom, format!("\r\n{}", termion::cursor::Goto(1, bottom)))
+        }
+    }
+}
+
+impl<T, W, K> FuzzyFinder<T, W, K>
+where
+    T: Clone,
+    W: Terminal,
+    K: Iterator<Item = io::Result<Key>>,
+{
+    fn new(
+        builder: FuzzyFinderBuilder<T>,
+        stdout: W,
+        keys: K,
+        console_offset: u16,
+        positive_space_remaining: u16,
+    ) -> Self {
+        let preview_height = builder.preview_rows();
+        let matcher = match builder.case_mode {
+            CaseMode::Smart => SkimMatcherV2::default().smart_case(),
+            CaseMode::Respect => SkimMatcherV2::default().respect_case(),
+            CaseMode::Ignore => SkimMatcherV2::default().ignore_case(),
         };
 
         FuzzyFinder {
-            search_term: String::from(""),
-            all_items: functions,
+            search_term: builder.initial_query,
+            scope: None,
+            sort: Sort::Score,
+            initial_selection: builder.initial_selection,
+            all_items: builder.items,
             matches: vec![],
+            match_indices: vec![],
+            total_matches: 0,
+            multi_select: builder.multi_select,
+            marked: vec![],
             console_offset,
             stdout,
+            keys,
             first: true,
-            list: List::new(lines_to_show),
+            list: List::new(builder.lines_to_show),
             positive_space_remaining,
+            matcher,
+            preview: builder.preview,
+            preview_height,
+            colours: builder.colours,
+            prompt_symbol: builder.prompt_symbol,
+            max_matches: builder
+                .max_matches
+                .max(builder.lines_to_show.max(1) as usize),
         }
     }
 
@@ -86,23 +223,97 @@ where
         self.render()
     }
 
+    /// Moves the selection up a page.
+    pub fn page_up(&mut self) -> Result<()> {
+        self.list.page_up(&self.matches);
+        self.update_matches();
+        self.render()
+    }
+
+    /// Moves the selection down a page.
+    pub fn page_down(&mut self) -> Result<()> {
+        self.list.page_down(&self.matches);
+        self.update_matches();
+        self.render()
+    }
+
+    /// Selects the match at `position`, where 0 is the best match and the bottom of the list.
+    /// Positions past the last match select the last match, at the top.
+    pub fn jump_to(&mut self, position: usize) -> Result<()> {
+        self.list.jump_to(position, &self.matches);
+        self.update_matches();
+        self.render()
+    }
+
     pub fn append(&mut self, c: char) -> Result<()> {
-        // This is a normal key that we want to add to the search.
-        self.search_term = format!("{}{}", self.search_term, c);
+        if c == '>' && self.scope.is_none() && self.is_scope(&self.search_term) {
+            // The user typed `scope>`, so search within that scope from now on.
+            self.scope = Some(std::mem::take(&mut self.search_term));
+        } else {
+            // This is a normal key that we want to add to the search.
+            self.search_term = format!("{}{}", self.search_term, c);
+        }
 
         self.update_matches();
         self.render()
     }
 
-    pub fn backspace(&mut self) -> Result<()> {
-        if self.search_term.chars().count() > 0 {
-            self.search_term =
-                String::from(&self.search_term[..self.search_term.chars().count() - 1]);
-            let matcher = SkimMatcherV2::default();
-            for f in &mut self.all_items {
-                f.score = matcher.fuzzy_indices(&f.name, &self.search_term);
+    /// Replaces the search term with the selected item's name, like shell completion.
+    pub fn complete(&mut self) -> Result<()> {
+        if self.matches.is_empty() {
+            return Ok(());
+        }
+        self.search_term = self.list.get_selected().name.clone();
+
+        self.update_matches();
+        self.render()
+    }
+
+    /// Marks the selected item, or unmarks it if it's already marked, and selects the next one.
+    pub fn toggle_mark(&mut self) -> Result<()> {
+        if self.matches.is_empty() {
+            return Ok(());
+        }
+        let index = self.match_indices[self.list.selected_position()];
+        match self.marked.iter().position(|&marked| marked == index) {
+            Some(position) => {
+                self.marked.remove(position);
             }
+            None => self.marked.push(index),
         }
+        self.up()
+    }
+
+    /// Whether any item is in `scope`.
+    fn is_scope(&self, scope: &str) -> bool {
+        self.all_items
+            .iter()
+            .any(|item| item.scope.as_deref() == Some(scope))
+    }
+
+    /// Switches between sorting matches by score and by name.
+    pub fn toggle_sort(&mut self) -> Result<()> {
+        self.sort = match self.sort {
+            Sort::Score => Sort::Name,
+            Sort::Name => Sort::Score,
+        };
+        self.update_matches();
+        self.render()
+    }
+
+    /// Stops searching within a scope, if we were.
+    pub fn clear_scope(&mut self) -> Result<()> {
+        self.scope = None;
+        self.update_matches();
+        self.render()
+    }
+
+    pub fn backspace(&mut self) -> Result<()> {
+        if self.search_term.is_empty() && self.scope.is_some() {
+            // Backspacing past the scope clears it.
+            return self.clear_scope();
+        }
+        self.search_term.pop();
         self.update_matches();
         self.render()
     }
@@ -112,7 +323,8 @@ where
         // this run of lk.
         write!(self.stdout, "{}", termion::cursor::Save).unwrap();
         if self.first {
-            for _ in 0..self.list.lines_to_show {
+            // One line less than we need, because we start on the first one.
+            for _ in 0..self.rows() - 1 {
                 writeln!(self.stdout, " ")?;
             }
             self.first = false
@@ -122,28 +334,59 @@ where
         Ok(())
     }
 
+    /// The rows we render: the list, a blank line, the prompt and the preview.
+    fn rows(&self) -> u16 {
+        self.list.lines_to_show as u16 + 2 + self.preview_height
+    }
+
+    /// The row the list starts on, once the terminal has scrolled to make space for us.
+    fn top(&self) -> u16 {
+        self.console_offset
+            .saturating_sub(self.positive_space_remaining)
+            .max(1)
+    }
+
     fn goto_start(&mut self) -> Result<()> {
-        write!(
-            self.stdout,
-            "{}",
-            termion::cursor::Goto(1, self.console_offset - self.positive_space_remaining)
-        )?;
+        write!(self.stdout, "{}", termion::cursor::Goto(1, self.top()))?;
+        Ok(())
+    }
+
+    /// Clears away everything we've rendered, leaving the cursor where we started, so the
+    /// shell's prompt, or what runs next, carries on from there.
+    fn clear(&mut self) -> Result<()> {
+        self.goto_start()?;
+        write!(self.stdout, "{}{Show}", termion::clear::AfterCursor)?;
+        self.stdout.flush()?;
         Ok(())
     }
 
     fn render_items(&mut self) -> Result<()> {
         self.goto_start()?;
+        let placeholder_index = self.list.items.len().saturating_sub(1);
         for (index, item) in self.list.items.iter().enumerate() {
-            if item.is_blank {
+            if item.is_blank && self.matches.is_empty() && index == placeholder_index {
+                // Say so when nothing matches, nearest the prompt, or the finder looks broken.
+                writeln!(
+                    self.stdout,
+                    "{}{}{DARK_GREY_FG}  {NO_MATCHES}{RESET_FG}",
+                    termion::clear::CurrentLine,
+                    termion::cursor::Left(1000),
+                )?;
+            } else if item.is_blank {
                 writeln!(self.stdout, "{}", termion::clear::CurrentLine)?;
             } else {
                 let fuzzy_indecies = &item.score.as_ref().unwrap().1;
 
                 // Do some string manipulation to colourise the indexed parts
+                let is_marked = self
+                    .marked
+                    .contains(&self.match_indices[self.list.position_of(index)]);
                 let coloured_line = get_coloured_line(
                     fuzzy_indecies,
                     &item.name,
                     index == self.list.selected_index as usize,
+                    is_marked,
+                    &self.colours,
                 );
 
                 writeln!(
@@ -162,36 +405,63 @@ where
     fn render_prompt(&mut self) -> Result<()> {
         // Render the prompt
         let prompt_y = self.list.lines_to_show as u16 + 1;
-        let current_x = self.search_term.chars().count() + 2;
+        let prompt = match &self.scope {
+            Some(scope) => format!("{scope}> {}", self.search_term),
+            None => self.search_term.clone(),
+        };
+        let (indicator, indicator_width) = match self.sort {
+            Sort::Score => (String::new(), 0),
+            Sort::Name => (
+                format!("{GREY_FG}{SORTED_BY_NAME}{RESET_FG}"),
+                SORTED_BY_NAME.width(),
+            ),
+        };
+        // Columns, not chars, because the symbol or search might be wide, e.g. an emoji.
+        let current_x = self.prompt_symbol.width() + 1 + indicator_width + prompt.width() + 1;
 
         // Go to the bottom line, where we'll render the prompt
         write!(
             self.stdout,
             "{CurrentLine}{}{CurrentLine}",
-            termion::cursor::Goto(current_x as u16, prompt_y + self.console_offset),
+            termion::cursor::Goto(current_x as u16, prompt_y + self.top()),
         )?;
+        // How far through the matches the selection is, e.g. [3/27].
+        let position = match self.list.match_count() {
+            0 => 0,
+            _ => self.list.selected_position() + 1,
+        };
         write!(
             self.stdout,
-            "{Show}{}{BLUE_FG}${RESET_FG} {}",
-            termion::cursor::Goto(1, prompt_y + self.console_offset),
-            self.search_term
+            "{Show}{}{}{}{RESET_FG} {indicator}{}  {GREY_FG}[{position}/{}]{RESET_FG}{}",
+            termion::cursor::Goto(1, prompt_y + self.top()),
+            self.colours.prompt,
+            self.prompt_symbol,
+            prompt,
+            self.total_matches,
+            // Back to the end of the search, where the user's typing.
+            termion::cursor::Goto(current_x as u16, prompt_y + self.top()),
         )?;
         self.stdout.flush()?;
         Ok(())
     }
 
-    /// Gets functions that match our current criteria, sorted by score.
+    /// Gets functions that match our current criteria, sorted by score or by name. Only items
+    /// in the current scope, if there is one, can match.
     pub fn update_matches(&mut self) {
-        let matcher = SkimMatcherV2::default();
         for f in &mut self.all_items {
-            f.score = matcher.fuzzy_indices(&f.name, &self.search_term);
+            f.score = match &self.scope {
+                Some(scope) if f.scope.as_ref() != Some(scope) => None,
+                _ if f.fields.is_empty() => self.matcher.fuzzy_indices(&f.name, &self.search_term),
+                _ => score_fields(&self.matcher, &f.fields, &self.search_term),
+            };
         }
+        // Only the matches we keep are cloned, because that's slow for a lot of items.
         let mut matches = self
             .all_items
             .iter()
-            .filter(|f| f.score.is_some())
-            .cloned()
-            .collect::<Vec<Item<T>>>();
+            .enumerate()
+            .filter(|(_, f)| f.score.is_some())
+            .collect::<Vec<(usize, &Item<T>)>>();
 
         log::info!(
             "There are a total of {} item(s) and {} match(es)",
@@ -199,152 +469,796 @@ where
             matches.len()
         );
 
-        // We want these in the order of their fuzzy matched score, i.e. closed matches
-        matches.sort_by(|a, b| b.score.cmp(&a.score));
-        self.matches = matches;
+        match self.sort {
+            // We want these in the order of their fuzzy matched score, i.e. closed matches
+            Sort::Score => matches.sort_by_key(|(_, item)| {
+                std::cmp::Reverse(item.score.as_ref().map(|score| score.0 + item.boost))
+            }),
+            Sort::Name => matches.sort_by_key(|(_, item)| item.name.to_lowercase()),
+        }
+        self.total_matches = matches.len();
+        matches.truncate(self.max_matches);
+        self.match_indices = matches.iter().map(|(index, _)| *index).collect();
+        self.matches = matches.into_iter().map(|(_, item)| item.clone()).collect();
         self.list.update(&self.matches);
     }
 
+    /// Renders the preview of the selected item, below the prompt.
+    fn render_preview(&mut self) -> Result<()> {
+        let preview = match &self.preview {
+            Some(preview) => preview,
+            None => return Ok(()),
+        };
+        let mut lines = match self.list.get_selected().item.as_ref() {
+            Some(item) => preview(item),
+            None => vec![],
+        };
+        lines.truncate(self.preview_height as usize);
+
+        let preview_y = self.list.lines_to_show as u16 + 2 + self.top();
+        for i in 0..self.preview_height {
+            let line = lines.get(i as usize).map(String::as_str).unwrap_or("");
+            write!(
+                self.stdout,
+                "{}{CurrentLine}{GREY_FG}{line}{RESET_FG}",
+                termion::cursor::Goto(1, preview_y + i),
+            )?;
+        }
+        Ok(())
+    }
+
     /// Renders the current result set
     pub fn render(&mut self) -> Result<()> {
         self.render_space()?;
         self.render_items()?;
+        self.render_preview()?;
         self.render_prompt()?;
         Ok(())
     }
 
-    /// The main entry point for the fuzzy finder.
-    pub fn find(items: Vec<Item<T>>, lines_to_show: i8) -> Result<Option<T>> {
-        let mut state = FuzzyFinder::new(items, lines_to_show);
+    /// Runs the main loop, turning a panic into an error. The finder, and the raw terminal with
+    /// it, is dropped before we return, so the error can be shown in a working terminal.
+    fn find_catching_panics(self) -> Result<Outcome<Vec<T>>> {
+        // The default hook would print the panic to the raw terminal, where it'd be cleared
+        // away, so it's just logged.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|info| {
+            log::error!("The fuzzy finder panicked: {info}");
+        }));
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| self.find()));
+        std::panic::set_hook(hook);
+        result.unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(anyhow!("The fuzzy finder crashed: {message}"))
+        })
+    }
+
+    /// Runs the fuzzy finder until the user picks something or cancels. However it ends, even
+    /// with an error, what we rendered is cleared away and the terminal's restored before we
+    /// return, so whatever runs next, like the function the user picked, has a normal terminal.
+    fn find(self) -> Result<Outcome<Vec<T>>> {
+        let mut state = self;
+        let outcome = state.search();
+        let tidied = state.clear().and_then(|_| Ok(state.stdout.restore()?));
+        let outcome = outcome?;
+        tidied?;
+        Ok(outcome)
+    }
+
+    /// The main loop for the fuzzy finder. What's picked is the selected item or, if any are
+    /// marked, the marked ones.
+    fn search(&mut self) -> Result<Outcome<Vec<T>>> {
+        let state = self;
 
         state.update_matches();
+        state.list.select(state.initial_selection, &state.matches);
 
         state.render()?;
 
-        let mut stdin = termion::async_stdin().keys();
-
-        // Run 'sed -n l' to explore escape codes
-        let mut escaped = String::from("");
-        let mut instant = Instant::now();
-
-        loop {
-            // What's going on here? The problem is how we detect escape.
-            // The key presses we're interested in, e.g. the arrows, are all preceded by escape, ^[.
-            // E.g. up is ^[[A and down is ^[[B. So the question is how do we identify an escape
-            // key by itself? If it's ^[[A then that's ^[ followed almost instantly by [A. If we have
-            // ^[ followed by a pause then we know it's not an escape for some other key, but an
-            // escape by itself. That's what the 100 136His below.
-            // NB: some terminals might send these bytes too slowly and escape might not be caught.
-            // NB: some terminals might use different escape keys entirely.
-            if escaped == "^[" && instant.elapsed().as_micros() > 100 {
-                write!(state.stdout, "{}", termion::cursor::Restore)?;
-                break;
+        while let Some(key) = state.keys.next() {
+            match key? {
+                // ctrl-c and ctrl-d are two ways to exit.
+                Key::Ctrl('c') => break,
+                Key::Ctrl('d') => break,
+                // Esc clears the scope first, if there is one.
+                Key::Esc if state.scope.is_some() => state.clear_scope()?,
+                Key::Esc => break,
+
+                // This captures the enter key
+                Key::Char('\n') => {
+                    return if !state.marked.is_empty() {
+                        Ok(Outcome::Picked(
+                            state
+                                .marked
+                                .iter()
+                                .map(|&index| state.all_items[index].item.clone().unwrap())
+                                .collect(),
+                        ))
+                    } else if !state.matches.is_empty() {
+                        Ok(Outcome::Picked(vec![state
+                            .list
+                            .get_selected()
+                            .item
+                            .as_ref()
+                            .unwrap()
+                            .to_owned()]))
+                    } else {
+                        Ok(Outcome::NoMatch(std::mem::take(&mut state.search_term)))
+                    };
+                }
+                Key::Char('\t') if state.multi_select => state.toggle_mark()?,
+                Key::Char('\t') => state.complete()?,
+                Key::Ctrl('s') => state.toggle_sort()?,
+                // ctrl-p and ctrl-n move too, like they do in emacs.
+                Key::Up | Key::Ctrl('p') => state.up()?,
+                Key::Down | Key::Ctrl('n') => state.down()?,
+                Key::PageUp => state.page_up()?,
+                Key::PageDown => state.page_down()?,
+                // The best match is at the bottom, so that's where the list starts.
+                Key::Home => state.jump_to(0)?,
+                Key::End => state.jump_to(usize::MAX)?,
+                Key::Char(c) => state.append(c)?,
+                Key::Backspace => state.backspace()?,
+                _ => {}
             }
+            state.stdout.flush()?;
+        }
+        Ok(Outcome::Cancelled)
+    }
+}
 
-            if let Some(Ok(key)) = stdin.next() {
-                match key {
-                    // ctrl-c and ctrl-d are two ways to exit.
-                    Key::Ctrl('c') => break,
-                    Key::Ctrl('d') => break,
-
-                    // NB: It'd be neat if we could use Key::Up and Key::Down but they don't
-                    // work in raw mode. So we've got to deal with the escape codes manually.
-
-                    // This captures the enter key
-                    Key::Char('\n') => {
-                        return if !state.matches.is_empty() {
-                            // Tidy up the console lines we've been writing
-                            for _ in state.console_offset
-                                ..state.console_offset + state.list.lines_to_show as u16 + 4
-                            {
-                                write!(state.stdout, "{}", termion::clear::CurrentLine,)?;
-                            }
-                            Ok(Some(
-                                state.list.get_selected().item.as_ref().unwrap().to_owned(),
-                            ))
-                        } else {
-                            Ok(None)
-                        };
-                    }
-                    Key::Char(c) => {
-                        if !escaped.is_empty() {
-                            escaped = format!("{}{}", escaped, c);
-                            match escaped.as_str() {
-                                "^[" => continue,
-                                "^[[" => continue,
-                                "^[[A" => {
-                                    escaped = String::from("");
-                                    state.up()?;
-                                }
-                                "^[[B" => {
-                                    escaped = String::from("");
-                                    state.down()?;
-                                }
-                                _ => {
-                                    // This is nothing we recognise so let's abandon the escape sequence.
-                                    escaped = String::from("");
-                                }
-                            }
-                        } else {
-                            state.append(c)?;
-                        }
+/// Scores an item by its fields. Each word of `search_term` has to match within a single
+/// field, so matches never land on separators, and its score is scaled by that field's weight.
+/// The item's score is the total for all the words.
+fn score_fields(
+    matcher: &SkimMatcherV2,
+    fields: &[Field],
+    search_term: &str,
+) -> Option<(i64, Vec<usize>)> {
+    let mut total = 0;
+    let mut indices = Vec::new();
+    for word in search_term.split_whitespace() {
+        let mut best: Option<(i64, Vec<usize>)> = None;
+        let mut offset = 0;
+        for field in fields {
+            if field.weight > 0 {
+                if let Some((score, field_indices)) = matcher.fuzzy_indices(&field.text, word) {
+                    let score = score * field.weight / 100;
+                    if best.as_ref().is_none_or(|(best, _)| score > *best) {
+                        let field_indices = field_indices.iter().map(|i| i + offset).collect();
+                        best = Some((score, field_indices));
                     }
-                    Key::Esc => {
-                        // All we're doing here is recording that we've entered an escape sequence.
-                        // It's actually handled when we handle chars.
-                        if escaped.is_empty() {
-                            escaped = String::from("^[");
-                            instant = Instant::now();
-                        }
-                    }
-                    Key::Backspace => {
-                        state.backspace()?;
-                    }
-                    _ => {}
                 }
-                state.stdout.flush().unwrap();
             }
+            offset += field.text.chars().count();
         }
-        Ok(None)
+        let (score, word_indices) = best?;
+        total += score;
+        indices.extend(word_indices);
     }
+    indices.sort_unstable();
+    indices.dedup();
+    Some((total, indices))
 }
 
 /// Highlights the line. Will highlight matching search items, and also indicate
 /// if it's a selected item.
-fn get_coloured_line(fuzzy_indecies: &[usize], text: &str, is_selected: bool) -> String {
-    // Do some string manipulation to colourise the indexed parts
+fn get_coloured_line(
+    fuzzy_indecies: &[usize],
+    text: &str,
+    is_selected: bool,
+    is_marked: bool,
+    colours: &Colours,
+) -> String {
+    let Colours {
+        matched,
+        selected,
+        pointer,
+        ..
+    } = colours;
+    // Do some string manipulation to colourise the indexed parts. The indices are of chars,
+    // so they're turned into byte offsets to slice the text by.
+    let offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .collect();
     let mut coloured_line = String::from("");
     let mut start = 0;
 
     for i in fuzzy_indecies {
-        let part = &text[start..*i];
-        let matching_char = &text[*i..*i + 1];
+        let part = &text[start..offsets[*i]];
+        let matching_char = &text[offsets[*i]..offsets[*i + 1]];
         if is_selected {
             coloured_line = format!(
-                "{coloured_line}{DARK_GREY_BG}{part}{RESET_BG}{DARK_BLUE_BG}{matching_char}{RESET_BG}"
+                "{coloured_line}{selected}{part}{RESET_BG}{matched}{matching_char}{RESET_BG}"
             );
         } else {
-            coloured_line = format!("{coloured_line}{part}{DARK_BLUE_BG}{matching_char}{RESET_BG}");
+            coloured_line = format!("{coloured_line}{part}{matched}{matching_char}{RESET_BG}");
         }
-        start = i + 1;
+        start = offsets[*i + 1];
     }
-    let remaining_chars = &text[start..text.chars().count()];
+    let remaining_chars = &text[start..];
+    // Marked items have a mark just after where the pointer goes.
+    let spacer: String = if is_marked {
+        format!("{pointer}{MARK}{RESET_FG} ")
+    } else {
+        format!("{DARK_GREY_FG}  {RESET_FG}")
+    };
     if is_selected {
-        let prompt: String = format!("{DARK_GREY_BG}{GREEN_FG}>{RESET_FG}{RESET_BG}",);
-        let spacer: String = format!("{DARK_GREY_FG}  {RESET_FG}");
-        let remaining: String = format!("{DARK_GREY_BG}{remaining_chars}{RESET_BG}");
+        let prompt: String = format!("{selected}{pointer}>{RESET_FG}{RESET_BG}",);
+        let remaining: String = format!("{selected}{remaining_chars}{RESET_BG}");
         coloured_line = format!("{prompt}{spacer}{coloured_line}{remaining}");
     } else {
-        coloured_line = format!("{DARK_GREY_BG} {RESET_BG}  {coloured_line}{remaining_chars}");
+        coloured_line = format!("{selected} {RESET_BG}{spacer}{coloured_line}{remaining_chars}");
     }
     coloured_line
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn items() -> Vec<Item<String>> {
+        ["alpha", "bravo", "charlie"]
+            .iter()
+            .map(|name| Item::new(name.to_string(), name.to_string()))
+            .collect()
+    }
+
+    fn keys(keys: &[Key]) -> impl Iterator<Item = io::Result<Key>> + '_ {
+        keys.iter().cloned().map(Ok)
+    }
+
+    fn run(keys_pressed: &[Key]) -> (Option<String>, String) {
+        let mut output: Vec<u8> = Vec::new();
+        let result = FuzzyFinder::builder(items())
+            .lines_to_show(5)
+            .run_with(&mut output, keys(keys_pressed))
+            .unwrap();
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_panic_is_an_error() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut pressed = 0;
+        let keys = std::iter::from_fn(|| {
+            pressed += 1;
+            if pressed > 1 {
+                panic!("no more keys");
+            }
+            Some(Ok(Key::Char('a')))
+        });
+        let err = FuzzyFinder::builder(items())
+            .run_with(&mut output, keys)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "The fuzzy finder crashed: no more keys");
+    }
+
+    #[test]
+    fn test_max_matches() {
+        let mut output: Vec<u8> = Vec::new();
+        // Only the two best matches are kept, so we can't scroll to the third.
+        let result = FuzzyFinder::builder(items())
+            .lines_to_show(1)
+            .max_matches(2)
+            .run_with(&mut output, keys(&[Key::Up, Key::Up, Key::Char('\n')]))
+            .unwrap();
+        assert_eq!(result, Some("bravo".to_string()));
+    }
+
     #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+    fn test_position_counts_the_matches_that_were_not_kept() {
+        let mut output: Vec<u8> = Vec::new();
+        FuzzyFinder::builder(items())
+            .lines_to_show(1)
+            .max_matches(2)
+            .run_with(&mut output, keys(&[Key::Up, Key::Ctrl('c')]))
+            .unwrap();
+        let rendered = String::from_utf8_lossy(&output);
+        assert!(rendered.contains("[1/3]"));
+        assert!(rendered.contains("[2/3]"));
+    }
+
+    #[test]
+    fn test_enter_selects_first_item() {
+        let (result, _) = run(&[Key::Char('\n')]);
+        assert_eq!(result, Some("alpha".to_string()));
+    }
+
+    #[test]
+    fn test_initial_selection() {
+        let select = |index| {
+            FuzzyFinder::builder(items())
+                .lines_to_show(5)
+                .initial_selection(index)
+                .run_with(Vec::new(), keys(&[Key::Char('\n')]))
+                .unwrap()
+        };
+        assert_eq!(select(1), Some("bravo".to_string()));
+        // Out of range selects the last match.
+        assert_eq!(select(10), Some("charlie".to_string()));
+    }
+
+    #[test]
+    fn test_typing_narrows_the_selection() {
+        let (result, _) = run(&[Key::Char('c'), Key::Char('h'), Key::Char('\n')]);
+        assert_eq!(result, Some("charlie".to_string()));
+    }
+
+    #[test]
+    fn test_up_and_down() {
+        let (result, _) = run(&[Key::Up, Key::Up, Key::Down, Key::Char('\n')]);
+        assert_eq!(result, Some("bravo".to_string()));
+    }
+
+    #[test]
+    fn test_ctrl_p_and_ctrl_n() {
+        let (result, _) = run(&[
+            Key::Ctrl('p'),
+            Key::Ctrl('p'),
+            Key::Ctrl('n'),
+            Key::Char('\n'),
+        ]);
+        assert_eq!(result, Some("bravo".to_string()));
+        // They move the selection, rather than being typed into the search.
+        let (result, _) = run(&[Key::Ctrl('p'), Key::Char('\n')]);
+        assert_eq!(result, Some("bravo".to_string()));
+    }
+
+    #[test]
+    fn test_home_end_and_paging() {
+        assert_eq!(
+            run(&[Key::End, Key::Char('\n')]).0,
+            Some("charlie".to_string())
+        );
+        assert_eq!(
+            run(&[Key::End, Key::Home, Key::Char('\n')]).0,
+            Some("alpha".to_string())
+        );
+        // A page is more than there are matches, so they go all the way.
+        assert_eq!(
+            run(&[Key::PageUp, Key::Char('\n')]).0,
+            Some("charlie".to_string())
+        );
+        assert_eq!(
+            run(&[Key::PageUp, Key::PageDown, Key::Char('\n')]).0,
+            Some("alpha".to_string())
+        );
+    }
+
+    #[test]
+    fn test_backspace() {
+        let (result, _) = run(&[
+            Key::Char('x'),
+            Key::Backspace,
+            Key::Char('b'),
+            Key::Char('\n'),
+        ]);
+        assert_eq!(result, Some("bravo".to_string()));
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let mut output: Vec<u8> = Vec::new();
+        let items = vec![
+            Item::new("café".to_string(), 1),
+            Item::new("naïve é".to_string(), 2),
+        ];
+        // Highlighting what's matched, and backspacing over it, go by chars, not bytes.
+        let result = FuzzyFinder::builder(items)
+            .run_with(
+                &mut output,
+                keys(&[
+                    Key::Char('é'),
+                    Key::Char('ï'),
+                    Key::Backspace,
+                    Key::Backspace,
+                    Key::Char('ï'),
+                    Key::Char('\n'),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(result, Some(2));
+        assert!(String::from_utf8(output).unwrap().contains("café"));
+    }
+
+    #[test]
+    fn test_non_ascii_highlighting() {
+        let line = get_coloured_line(&[1, 3], "çaféx", false, false, &Colours::default());
+        assert!(line.ends_with("x"));
+        assert!(line.contains("ç"));
+        assert!(line.contains("é"));
+    }
+
+    #[test]
+    fn test_tab_completes_the_selection() {
+        let mut output: Vec<u8> = Vec::new();
+        let items = vec![
+            Item::new("deploy.sh - release".to_string(), 1),
+            Item::new("deploy.sh - rollback".to_string(), 2),
+        ];
+        let result = FuzzyFinder::builder(items)
+            .run_with(
+                &mut output,
+                keys(&[Key::Char('r'), Key::Up, Key::Char('\t'), Key::Char('\n')]),
+            )
+            .unwrap();
+        assert_eq!(result, Some(2));
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("$\u{1b}[39m deploy.sh - rollback"));
+    }
+
+    #[test]
+    fn test_tab_without_matches_does_nothing() {
+        let (result, _) = run(&[
+            Key::Char('z'),
+            Key::Char('\t'),
+            Key::Backspace,
+            Key::Char('\n'),
+        ]);
+        assert_eq!(result, Some("alpha".to_string()));
+    }
+
+    #[test]
+    fn test_boost() {
+        let items = vec![
+            Item::new("release".to_string(), 1),
+            Item::new("rollback".to_string(), 2).with_boost(5),
+        ];
+        let result = FuzzyFinder::builder(items)
+            .run_with(Vec::new(), keys(&[Key::Char('\n')]))
+            .unwrap();
+        assert_eq!(result, Some(2));
+    }
+
+    #[test]
+    fn test_cancel() {
+        assert_eq!(run(&[Key::Up, Key::Ctrl('c')]).0, None);
+        assert_eq!(run(&[Key::Esc]).0, None);
+        // Running out of keys is the same as cancelling.
+        assert_eq!(run(&[Key::Up]).0, None);
+    }
+
+    #[test]
+    fn test_no_matches_returns_none() {
+        let (result, _) = run(&[Key::Char('z'), Key::Char('\n')]);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_outcome() {
+        let outcome = |keys_pressed: &[Key]| {
+            FuzzyFinder::builder(items())
+                .run_outcome_with(Vec::new(), keys(keys_pressed))
+                .unwrap()
+        };
+        assert_eq!(
+            outcome(&[Key::Char('b'), Key::Char('\n')]),
+            Outcome::Picked("bravo".to_string())
+        );
+        assert_eq!(
+            outcome(&[Key::Char('z'), Key::Char('q'), Key::Char('\n')]),
+            Outcome::NoMatch("zq".to_string())
+        );
+        assert_eq!(outcome(&[Key::Char('z'), Key::Esc]), Outcome::Cancelled);
+    }
+
+    fn run_multi(keys_pressed: &[Key]) -> (Vec<String>, String) {
+        let mut output: Vec<u8> = Vec::new();
+        let result = FuzzyFinder::builder(items())
+            .run_multi_with(&mut output, keys(keys_pressed))
+            .unwrap();
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_multi_select() {
+        // Tab marks an item and moves on to the next. Marks are kept while the search changes.
+        let (result, rendered) = run_multi(&[
+            Key::Up,
+            Key::Up,
+            Key::Char('\t'),
+            Key::Char('l'),
+            Key::Char('p'),
+            Key::Char('\t'),
+            Key::Char('\n'),
+        ]);
+        assert_eq!(result, vec!["charlie".to_string(), "alpha".to_string()]);
+        assert!(rendered.contains(MARK));
+    }
+
+    #[test]
+    fn test_multi_select_unmarks() {
+        // Marking twice unmarks, and with nothing marked enter picks the selected item.
+        let (result, _) = run_multi(&[
+            Key::Char('\t'),
+            Key::Down,
+            Key::Char('\t'),
+            Key::Down,
+            Key::Char('\n'),
+        ]);
+        assert_eq!(result, vec!["alpha".to_string()]);
+        assert!(run_multi(&[Key::Char('\t'), Key::Esc]).0.is_empty());
+    }
+
+    #[test]
+    fn test_renders_no_matches() {
+        let (_, rendered) = run(&[Key::Ctrl('c')]);
+        assert!(!rendered.contains(NO_MATCHES));
+        let (_, rendered) = run(&[Key::Char('z'), Key::Ctrl('c')]);
+        assert!(rendered.contains(NO_MATCHES));
+    }
+
+    #[test]
+    fn test_renders_items_and_prompt() {
+        let (_, rendered) = run(&[Key::Char('b'), Key::Ctrl('c')]);
+        assert!(rendered.contains("alpha"));
+        assert!(rendered.contains("charlie"));
+        assert!(rendered.contains("$\u{1b}[39m b"));
+    }
+
+    #[test]
+    fn test_renders_position() {
+        let (_, rendered) = run(&[Key::Up, Key::Ctrl('c')]);
+        assert!(rendered.contains("[1/3]"));
+        assert!(rendered.contains("[2/3]"));
+        let (_, rendered) = run(&[Key::Char('z'), Key::Ctrl('c')]);
+        assert!(rendered.contains("[0/0]"));
+    }
+
+    #[test]
+    fn test_prompt_symbol() {
+        let mut output: Vec<u8> = Vec::new();
+        FuzzyFinder::builder(items())
+            .prompt_symbol("🦀")
+            .run_with(&mut output, keys(&[Key::Char('b'), Key::Ctrl('c')]))
+            .unwrap();
+        let rendered = String::from_utf8(output).unwrap();
+        assert!(rendered.contains("🦀\u{1b}[39m b"));
+        // The crab is two columns wide, so the cursor goes after the space and the 'b'.
+        assert!(rendered.contains(&termion::cursor::Goto(5, 10).to_string()));
+    }
+
+    fn scoped_items() -> Vec<Item<i32>> {
+        vec![
+            Item::new("a.sh - deploy".to_string(), 1).with_scope("a.sh"),
+            Item::new("b.sh - deploy".to_string(), 2).with_scope("b.sh"),
+            Item::new("b.sh - destroy".to_string(), 3).with_scope("b.sh"),
+        ]
+    }
+
+    fn typed(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    fn run_scoped(keys_pressed: &[Key]) -> (Option<i32>, String) {
+        let mut output: Vec<u8> = Vec::new();
+        let result = FuzzyFinder::builder(scoped_items())
+            .run_with(&mut output, keys(keys_pressed))
+            .unwrap();
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_scope() {
+        let (result, rendered) = run_scoped(&[typed("b.sh>dep"), vec![Key::Char('\n')]].concat());
+        assert_eq!(result, Some(2));
+        assert!(rendered.contains("$\u{1b}[39m b.sh> dep"));
+        // Without a scope `a.sh - deploy` is the better match.
+        let (result, _) = run_scoped(&[typed("dep"), vec![Key::Char('\n')]].concat());
+        assert_eq!(result, Some(1));
+        // Only a known scope is a scope.
+        let (result, _) = run_scoped(&[typed("c.sh>"), vec![Key::Char('\n')]].concat());
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_clear_scope() {
+        // Backspacing past the scope clears it.
+        let keys = [
+            typed("b.sh>"),
+            vec![Key::Backspace],
+            typed("a.sh"),
+            vec![Key::Char('\n')],
+        ];
+        assert_eq!(run_scoped(&keys.concat()).0, Some(1));
+        // So does escape, which only cancels once the scope's gone.
+        let keys = [typed("b.sh>"), vec![Key::Esc, Key::Char('\n')]];
+        assert_eq!(run_scoped(&keys.concat()).0, Some(1));
+        let keys = [typed("b.sh>"), vec![Key::Esc, Key::Esc, Key::Char('\n')]];
+        assert_eq!(run_scoped(&keys.concat()).0, None);
+    }
+
+    #[test]
+    fn test_toggle_sort() {
+        let run_unsorted = |keys_pressed: &[Key]| {
+            let mut output: Vec<u8> = Vec::new();
+            let items = ["charlie", "Bravo", "alpha"]
+                .iter()
+                .map(|name| Item::new(name.to_string(), name.to_string()))
+                .collect();
+            let result = FuzzyFinder::builder(items)
+                .run_with(&mut output, keys(keys_pressed))
+                .unwrap();
+            (result, String::from_utf8(output).unwrap())
+        };
+        let (result, rendered) = run_unsorted(&[Key::Char('\n')]);
+        assert_eq!(result, Some("charlie".to_string()));
+        assert!(!rendered.contains(SORTED_BY_NAME));
+        let (result, rendered) = run_unsorted(&[Key::Ctrl('s'), Key::Up, Key::Char('\n')]);
+        assert_eq!(result, Some("Bravo".to_string()));
+        assert!(rendered.contains(SORTED_BY_NAME));
+        let (result, _) = run_unsorted(&[Key::Ctrl('s'), Key::Ctrl('s'), Key::Char('\n')]);
+        assert_eq!(result, Some("charlie".to_string()));
+    }
+
+    #[test]
+    fn test_fit_to() {
+        let fit = |lines_to_show, preview: Option<u16>, terminal_height| {
+            let mut builder = FuzzyFinder::builder(items()).lines_to_show(lines_to_show);
+            if let Some(preview_height) = preview {
+                builder = builder
+                    .preview(|_: &String| Vec::new())
+                    .preview_height(preview_height);
+            }
+            let builder = builder.fit_to(terminal_height);
+            (builder.lines_to_show, builder.preview_rows())
+        };
+        assert_eq!(fit(7, None, 24), (7, 0));
+        assert_eq!(fit(20, None, 10), (8, 0));
+        assert_eq!(fit(7, Some(5), 24), (7, 5));
+        // The results shrink first, then the preview.
+        assert_eq!(fit(20, Some(5), 10), (3, 5));
+        assert_eq!(fit(20, Some(5), 5), (1, 2));
+        assert_eq!(fit(20, Some(5), 1), (1, 0));
+    }
+
+    /// A terminal that records what's written to it, and how much had been when it was
+    /// restored.
+    #[derive(Default)]
+    struct Recorder {
+        output: Vec<u8>,
+        restored_after: Option<usize>,
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Terminal for &mut Recorder {
+        fn restore(&mut self) -> io::Result<()> {
+            self.restored_after = Some(self.output.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_terminal_is_restored_before_returning() {
+        let cleared = format!(
+            "{}{}{Show}",
+            termion::cursor::Goto(1, 1),
+            termion::clear::AfterCursor
+        );
+        for key in [Key::Char('\n'), Key::Esc, Key::Ctrl('c'), Key::Ctrl('d')] {
+            let mut terminal = Recorder::default();
+            let pressed = [key];
+            let builder = FuzzyFinder::builder(items()).lines_to_show(5);
+            FuzzyFinder::new(builder, &mut terminal, keys(&pressed), 1, 0)
+                .find()
+                .unwrap();
+            // It's restored last, once what was rendered has been cleared and the cursor shown.
+            assert_eq!(
+                terminal.restored_after,
+                Some(terminal.output.len()),
+                "{key:?}"
+            );
+            assert!(terminal.output.ends_with(cleared.as_bytes()), "{key:?}");
+        }
+    }
+
+    #[test]
+    fn test_terminal_is_restored_after_an_error() {
+        let mut terminal = Recorder::default();
+        let keys = std::iter::once(Err(io::Error::other("tty went away")));
+        let builder = FuzzyFinder::builder(items()).lines_to_show(5);
+        assert!(FuzzyFinder::new(builder, &mut terminal, keys, 1, 0)
+            .find()
+            .is_err());
+        assert!(terminal.restored_after.is_some());
+    }
+
+    #[test]
+    fn test_more_overflow_than_offset() {
+        // The terminal scrolled further than the row we started on, which used to underflow.
+        let pressed = [Key::Char('\n')];
+        let builder = FuzzyFinder::builder(items()).lines_to_show(5);
+        let result = FuzzyFinder::new(builder, keys::Plain(Vec::new()), keys(&pressed), 2, 30)
+            .find()
+            .unwrap();
+        assert_eq!(result, Outcome::Picked(vec!["alpha".to_string()]));
+    }
+
+    #[test]
+    fn test_map_items() {
+        let items = items()
+            .into_iter()
+            .map(|item| {
+                let name = item.name.to_uppercase();
+                item.map(|item| (name, item))
+            })
+            .collect();
+        let mut output: Vec<u8> = Vec::new();
+        let result = FuzzyFinder::builder(items)
+            .run_with(&mut output, keys(&[Key::Char('b'), Key::Char('\n')]))
+            .unwrap();
+        assert_eq!(result, Some(("BRAVO".to_string(), "bravo".to_string())));
+    }
+
+    #[test]
+    fn test_start() {
+        let no_cursor = || Err(io::Error::other("no reply"));
+        // At the start of a line we can start right there.
+        assert_eq!(start(Ok((1, 5)), 24), (5, String::new()));
+        // Part way along one we go down a line, so we don't draw over it.
+        assert_eq!(start(Ok((12, 5)), 24), (6, "\r\n".to_string()));
+        // The terminal scrolls when we're at the bottom.
+        assert_eq!(start(Ok((12, 24)), 24), (24, "\r\n".to_string()));
+        assert_eq!(
+            start(no_cursor(), 24),
+            (24, format!("\r\n{}", termion::cursor::Goto(1, 24)))
+        );
+    }
+
+    #[test]
+    fn test_exiting_clears_what_was_rendered() {
+        let cleared = format!(
+            "{}{}{Show}",
+            termion::cursor::Goto(1, 1),
+            termion::clear::AfterCursor
+        );
+        for key in [Key::Char('\n'), Key::Esc, Key::Ctrl('c')] {
+            let (_, rendered) = run(&[key]);
+            assert!(rendered.ends_with(&cleared), "{key:?}");
+        }
+        // Running out of keys cancels too.
+        let (_, rendered) = run(&[]);
+        assert!(rendered.ends_with(&cleared));
+    }
+
+    #[test]
+    fn test_score_fields() {
+        let matcher = SkimMatcherV2::default().smart_case();
+        let fields = vec![
+            Field::new("infra/", 50),
+            Field::new("deploy.sh", 100),
+            Field::separator(" - "),
+            Field::new("build", 100),
+        ];
+        // Words can match different fields, but never the separator.
+        let (_, indices) = score_fields(&matcher, &fields, "infra build").unwrap();
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 18, 19, 20, 21, 22]);
+        assert!(score_fields(&matcher, &fields, "-").is_none());
+        assert!(score_fields(&matcher, &fields, "sh - b").is_none());
+        assert!(score_fields(&matcher, &fields, "infra nope").is_none());
+        // A match in the directory counts for less.
+        let in_directory = vec![Field::new("deploy/", 50), Field::new("x.sh", 100)];
+        let in_file_name = vec![Field::new("x/", 50), Field::new("deploy.sh", 100)];
+        assert!(
+            score_fields(&matcher, &in_directory, "deploy").unwrap().0
+                < score_fields(&matcher, &in_file_name, "deploy").unwrap().0
+        );
     }
 }
@@ -1,15 +1,17 @@
 /// The list and events for handling movement within the list. No UI.
 use super::item::Item;
 
-pub struct List<T>
+pub(crate) struct List<T>
 where
     T: Clone,
 {
-    pub top_index: u8,
-    pub bottom_index: u8,
+    pub top_index: usize,
+    pub bottom_index: usize,
     pub lines_to_show: i8,
     pub selected_index: i8,
     pub items: Vec<Item<T>>,
+    /// How many matches there were at the last `update`.
+    match_count: usize,
 }
 
 impl<T> List<T>
@@ -19,18 +21,18 @@ where
     pub fn new(lines_to_show: i8) -> Self {
         List {
             items: vec![],
-            top_index: lines_to_show as u8 - 1,
-            selected_index: (lines_to_show - 1) as i8,
+            top_index: lines_to_show as usize - 1,
+            selected_index: lines_to_show - 1,
             lines_to_show,
             bottom_index: 0,
+            match_count: 0,
         }
     }
 
     pub fn up(&mut self, matches: &[Item<T>]) {
-        let match_count = matches.len() as i8;
         if self.selected_index > 0 {
             self.selected_index -= 1;
-        } else if self.top_index < (match_count - 1) as u8 {
+        } else if self.top_index + 1 < matches.len() {
             self.bottom_index += 1;
             self.top_index += 1;
         }
@@ -39,7 +41,7 @@ where
 
     pub fn down(&mut self) {
         // Should we move the selection down?
-        if self.selected_index < self.top_index as i8 {
+        if (self.selected_index as usize) < self.top_index {
             self.selected_index += 1;
         }
 
@@ -56,24 +58,79 @@ where
         self.floor_selected_index();
     }
 
+    /// Selects the match at `position`, counting from 0 at the best match, scrolling as little
+    /// as possible to show it. Positions past the last match select the last match.
+    pub fn jump_to(&mut self, position: usize, matches: &[Item<T>]) {
+        let Some(last) = matches.len().checked_sub(1) else {
+            return;
+        };
+        let position = position.min(last);
+        let lines = self.lines_to_show as usize;
+        let mut bottom = self.bottom_index;
+        if position < bottom {
+            bottom = position;
+        } else if position >= bottom + lines {
+            bottom = position + 1 - lines;
+        }
+        self.bottom_index = bottom;
+        self.top_index = bottom + lines - 1;
+        self.selected_index = (self.top_index - position) as i8;
+        self.update(matches);
+    }
+
+    /// Moves the selection a page of `lines_to_show` towards the worse matches.
+    pub fn page_up(&mut self, matches: &[Item<T>]) {
+        let position = self.selected_position() + self.lines_to_show as usize;
+        self.jump_to(position, matches);
+    }
+
+    /// Moves the selection a page of `lines_to_show` towards the best match.
+    pub fn page_down(&mut self, matches: &[Item<T>]) {
+        let position = self
+            .selected_position()
+            .saturating_sub(self.lines_to_show as usize);
+        self.jump_to(position, matches);
+    }
+
+    /// Selects `matches[index]`, scrolling if need be. If `index` is past the end of `matches`
+    /// then the last match is selected.
+    pub fn select(&mut self, index: usize, matches: &[Item<T>]) {
+        for _ in 0..index.min(matches.len().saturating_sub(1)) {
+            self.up(matches);
+        }
+        self.update(matches);
+    }
+
     fn floor_selected_index(&mut self) {
         let index_of_first_blank = self.items.iter().rev().position(|item| item.is_blank);
         if let Some(rev_index) = index_of_first_blank {
             let index = self.lines_to_show - rev_index as i8;
-            if self.selected_index < index as i8 {
+            if self.selected_index < index {
                 self.selected_index = index
             }
         }
+        // When everything is blank the floor is past the last line, so keep it on the list.
+        if self.selected_index > self.lines_to_show - 1 {
+            self.selected_index = self.lines_to_show - 1
+        }
     }
 
     /// Takes the current matches and updates the visible contents.
     pub fn update(&mut self, matches: &[Item<T>]) {
         log::info!("Updating view with {} match(es)", matches.len());
+        // If there are fewer matches than before then we might have scrolled past them all, so
+        // scroll back until the view ends at the last match.
+        let max_bottom_index = matches.len().saturating_sub(self.lines_to_show as usize);
+        if self.bottom_index > max_bottom_index {
+            let overshoot = self.bottom_index - max_bottom_index;
+            self.bottom_index -= overshoot;
+            self.top_index -= overshoot;
+        }
         let mut to_render: Vec<Item<T>> = Vec::new();
         // Get everything in our display window
         for i in self.bottom_index..self.top_index + 1 {
-            if matches.len() > (i).into() {
-                to_render.push(matches[i as usize].clone());
+            if matches.len() > i {
+                to_render.push(matches[i].clone());
             } else {
                 to_render.push(Item::empty());
             }
@@ -81,6 +138,7 @@ where
         to_render.reverse();
 
         self.items = to_render;
+        self.match_count = matches.len();
         self.floor_selected_index();
     }
 
@@ -88,6 +146,22 @@ where
         let index = self.selected_index as usize;
         &self.items[index]
     }
+
+    /// Where the selected item is in the matches, counting from 0 at the best match.
+    pub fn selected_position(&self) -> usize {
+        self.position_of(self.selected_index as usize)
+    }
+
+    /// Where the item on line `line` of the view is in the matches. The view is upside down, so
+    /// the top line shows `matches[top_index]`.
+    pub fn position_of(&self, line: usize) -> usize {
+        self.top_index.saturating_sub(line)
+    }
+
+    /// How many matches there are, including the ones scrolled out of view.
+    pub fn match_count(&self) -> usize {
+        self.match_count
+    }
 }
 
 #[cfg(test)]
@@ -245,4 +319,249 @@ mod tests {
         assert_eq!(setup.view.items.len(), 8); // Still 8, but blanks
         assert_eq!(setup.view.selected_index, 5);
     }
+
+    #[test]
+    fn test_select() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+
+        // WHEN
+        setup.view.select(10, &setup.items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "K");
+    }
+
+    #[test]
+    fn test_selected_position() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+        assert_eq!(setup.view.selected_position(), 0);
+        assert_eq!(setup.view.match_count(), 13);
+
+        // WHEN
+        // Past the top of the view, so it scrolls.
+        for _ in 0..10 {
+            setup.view.up(&setup.items);
+        }
+        setup.view.down();
+        setup.view.update(&setup.items);
+
+        // THEN
+        assert_eq!(setup.view.selected_position(), 9);
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "J");
+    }
+
+    #[test]
+    fn test_selected_position_with_few_items() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.few_items);
+
+        // WHEN
+        // Up doesn't go past the last match.
+        for _ in 0..5 {
+            setup.view.up(&setup.few_items);
+        }
+
+        // THEN
+        assert_eq!(setup.view.selected_position(), 2);
+        assert_eq!(setup.view.match_count(), 3);
+    }
+
+    #[test]
+    fn test_update_with_fewer_matches() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+        setup.view.select(10, &setup.items);
+
+        // WHEN
+        setup.view.update(&setup.few_items);
+
+        // THEN
+        // We'd scrolled past all of them, so we scroll back, to the furthest match.
+        assert_eq!(setup.view.bottom_index, 0);
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "C");
+    }
+
+    #[test]
+    fn test_update_with_fewer_matches_clamps_the_selection() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+        setup.view.select(5, &setup.items);
+
+        // WHEN
+        setup.view.update(&setup.few_items);
+
+        // THEN
+        // F has gone, so the selection moves to the furthest match that's left.
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "C");
+        assert_eq!(setup.view.selected_position(), 2);
+    }
+
+    #[test]
+    fn test_no_matches() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+        setup.view.up(&setup.items);
+
+        // WHEN
+        setup.view.update(&[]);
+        setup.view.up(&[]);
+        setup.view.down();
+
+        // THEN
+        // Everything's blank, and the selection stays on the view.
+        assert_eq!(setup.view.items.len(), 8);
+        assert!(setup.view.items.iter().all(|item| item.is_blank));
+        assert_eq!(setup.view.selected_index, 7);
+        assert!(setup.view.get_selected().item.is_none());
+        assert_eq!(setup.view.match_count(), 0);
+    }
+
+    #[test]
+    fn test_down_past_the_end_scrolls_back() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+        setup.view.select(12, &setup.items);
+
+        // WHEN
+        for _ in 0..20 {
+            setup.view.down();
+        }
+        setup.view.update(&setup.items);
+
+        // THEN
+        assert_eq!(setup.view.bottom_index, 0);
+        assert_eq!(setup.view.selected_index, 7);
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "A");
+    }
+
+    #[test]
+    fn test_jump_to() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+
+        // WHEN
+        // Past the top of the view, so it scrolls just enough to show it.
+        setup.view.jump_to(10, &setup.items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "K");
+        assert_eq!(setup.view.selected_index, 0);
+        assert_eq!(setup.view.bottom_index, 3);
+
+        // WHEN
+        // Back within the view, so it doesn't scroll.
+        setup.view.jump_to(5, &setup.items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "F");
+        assert_eq!(setup.view.bottom_index, 3);
+
+        // WHEN
+        setup.view.jump_to(0, &setup.items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "A");
+        assert_eq!(setup.view.bottom_index, 0);
+        assert_eq!(setup.view.selected_index, 7);
+    }
+
+    #[test]
+    fn test_jump_past_the_end() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.few_items);
+
+        // WHEN
+        setup.view.jump_to(usize::MAX, &setup.few_items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "C");
+        assert_eq!(setup.view.selected_position(), 2);
+
+        // WHEN
+        // There's nothing to jump to, so nothing changes.
+        setup.view.update(&[]);
+        setup.view.jump_to(3, &[]);
+
+        // THEN
+        assert!(setup.view.get_selected().item.is_none());
+    }
+
+    #[test]
+    fn test_jump_to_the_end_of_a_long_list() {
+        // GIVEN
+        // More matches than fit in a byte.
+        let items: Vec<Item<TestItem>> = (0..300).map(|i| item(&i.to_string())).collect();
+        let mut view = List::<TestItem>::new(8);
+        view.update(&items);
+
+        // WHEN
+        view.jump_to(usize::MAX, &items);
+
+        // THEN
+        assert_eq!(view.get_selected().item.as_ref().unwrap().name, "299");
+        assert_eq!(view.selected_position(), 299);
+
+        // WHEN
+        view.page_down(&items);
+        view.up(&items);
+        view.down();
+
+        // THEN
+        assert_eq!(view.selected_position(), 291);
+        assert_eq!(view.bottom_index, 291);
+    }
+
+    #[test]
+    fn test_page_up_and_down() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.items);
+
+        // WHEN
+        setup.view.page_up(&setup.items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "I");
+
+        // WHEN
+        // It stops at the last match.
+        setup.view.page_up(&setup.items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "M");
+        assert_eq!(setup.view.selected_index, 0);
+
+        // WHEN
+        setup.view.page_down(&setup.items);
+        setup.view.page_down(&setup.items);
+
+        // THEN
+        // And at the best one.
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "A");
+        assert_eq!(setup.view.bottom_index, 0);
+    }
+
+    #[test]
+    fn test_select_out_of_range() {
+        // GIVEN
+        let mut setup = Setup::new(8);
+        setup.view.update(&setup.few_items);
+
+        // WHEN
+        setup.view.select(5, &setup.few_items);
+
+        // THEN
+        assert_eq!(setup.view.get_selected().item.as_ref().unwrap().name, "C");
+    }
 }
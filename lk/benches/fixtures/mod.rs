@@ -0,0 +1,47 @@
+// Each benchmark only uses some of these.
+#![allow(dead_code)]
+/// Generated scripts for the benchmarks. Everything's derived from the sizes we're given, so
+/// each run benchmarks the same files.
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// A script with a header and `functions` functions. They each have a comment, and some
+/// have params, an `# lk:` directive, or a group, so every part of parsing gets a go.
+pub fn large_script(functions: usize) -> String {
+    let mut script =
+        String::from("#!/usr/bin/env bash\n#\n# A generated script for benchmarks.\n\n");
+    for i in 0..functions {
+        if i % 50 == 0 {
+            script.push_str(&format!("# lk: group \"Group {}\"\n\n", i / 50));
+        }
+        script.push_str(&format!("# Does step {i} of the benchmark.\n"));
+        if i % 3 == 0 {
+            script.push_str("# @param env Where to run it\n");
+        }
+        if i % 10 == 0 {
+            script.push_str(&format!("# lk: alias s{i}\n"));
+        }
+        script.push_str(&format!(
+            "step_{i}() {{\n    local env=\"$1\"\n    echo \"step {i} in $env\"\n}}\n\n"
+        ));
+    }
+    script
+}
+
+/// Writes `directories` directories of `scripts` executable scripts under `root`, with
+/// a `target` directory full of them alongside, which lk ignores by default.
+pub fn script_tree(root: &Path, directories: usize, scripts: usize) {
+    for directory in (0..directories)
+        .map(|i| root.join(format!("dir_{i}")))
+        .chain([root.join("target")])
+    {
+        std::fs::create_dir_all(&directory).unwrap();
+        for i in 0..scripts {
+            let path = directory.join(format!("script_{i}.sh"));
+            std::fs::write(&path, large_script(5)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        // Not executable, so not a script.
+        std::fs::write(directory.join("README.md"), "# Not a script\n").unwrap();
+    }
+}
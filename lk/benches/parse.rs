@@ -0,0 +1,19 @@
+/// Benchmarks parsing a large script.
+use criterion::{criterion_group, criterion_main, Criterion};
+use lk::executables::Executable;
+use lk::script::{ParseOptions, Script};
+
+mod fixtures;
+
+fn parse(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.sh");
+    std::fs::write(&path, fixtures::large_script(500)).unwrap();
+    let executable = Executable::from_absolute_path(&path);
+    c.bench_function("Script::new with 500 functions", |b| {
+        b.iter(|| Script::new(&executable, &ParseOptions::default()).unwrap())
+    });
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
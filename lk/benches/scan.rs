@@ -0,0 +1,28 @@
+/// Benchmarks finding the scripts in a directory tree.
+use criterion::{criterion_group, criterion_main, Criterion};
+use lk::executables::{is_executable_file, is_executable_script, Executables, Ignores};
+
+mod fixtures;
+
+fn scan(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    fixtures::script_tree(dir.path(), 20, 50);
+    let roots = vec![dir.path().to_string_lossy().to_string()];
+    let ignores = Ignores::new(&[]).unwrap();
+    c.bench_function("Executables::from_roots over 1000 scripts", |b| {
+        b.iter(|| {
+            Executables::from_roots(&roots, &ignores, &[], &is_executable_script, false, |_| {})
+        })
+    });
+    c.bench_function(
+        "Executables::from_roots over 1000 scripts, without the binary check",
+        |b| {
+            b.iter(|| {
+                Executables::from_roots(&roots, &ignores, &[], &is_executable_file, false, |_| {})
+            })
+        },
+    );
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
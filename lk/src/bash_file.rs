@@ -1,16 +1,113 @@
 /// A temporary file for executing the requested function.
 use crate::script::Function;
 use crate::script::Script;
-use crate::ui::print_complete_header;
-use anyhow::Result;
+use crate::ui::{confirm_run, print_missing_env_file};
+use anyhow::{Context, Result};
 use nanoid::nanoid;
-use std::io::Write;
+use std::fmt;
+use std::io::{Read, Write};
 use std::os::unix::fs::OpenOptionsExt;
-use std::path::PathBuf;
-use std::process::Command;
-use std::process::Stdio;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
+/// The env file we source if there's one where lk is run, and `--env-file` doesn't say otherwise.
+const DEFAULT_ENV_FILE: &str = ".env";
+
+/// How often we check whether a function with a timeout has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a timed out function gets to tidy up after SIGTERM, before we SIGKILL it.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// The error when a function is still running after its timeout.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub function: String,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} was killed because it was still running after {}s",
+            self.function,
+            self.timeout.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// How to run a function.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    /// Ask the user before running the function, and only run it if they say yes.
+    pub confirm: bool,
+    /// Kill the function if it's still running after this long.
+    pub timeout: Option<Duration>,
+    /// Environment variables to set for the function, on top of lk's own.
+    pub env: Vec<(String, String)>,
+    /// An absolute path to a file of `KEY=VALUE` lines, like a `.env`, that's sourced before
+    /// the script, so the script can override what it sets.
+    pub env_file: Option<PathBuf>,
+    /// Export every variable the script sets when it's sourced, like `set -a`, so whatever
+    /// the function runs sees them too.
+    pub autoexport: bool,
+    /// Print the lk file, rather than running it.
+    pub show_runner: bool,
+}
+
+/// Quotes `word` so the shell reads it as one word, as it is, e.g. `'my scripts/deploy.sh'`.
+/// Words that don't need quoting are left alone.
+pub fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+=:,@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Parses a `KEY=VALUE` environment variable, as passed to `--env`.
+pub fn parse_env(env: &str) -> std::result::Result<(String, String), String> {
+    let (key, value) = env
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, but there's no '=' in '{env}'"))?;
+    let mut chars = key.chars();
+    let valid_key = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_key {
+        return Err(format!(
+            "'{key}' isn't a valid variable name. Use letters, digits and underscores, not starting with a digit"
+        ));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// The env file to source before the script: `path`, as passed to `--env-file`, or if that's
+/// not given and `source_dotenv` is on, then `.env` in the current directory, if there is one.
+/// It's made absolute, because the lk file runs from the script's directory. If `path` doesn't
+/// exist then we say so, and don't source anything.
+pub fn find_env_file(path: Option<&Path>, source_dotenv: bool) -> Option<PathBuf> {
+    match path {
+        Some(path) => match std::fs::canonicalize(path) {
+            Ok(absolute_path) if absolute_path.is_file() => Some(absolute_path),
+            _ => {
+                print_missing_env_file(path);
+                None
+            }
+        },
+        None if source_dotenv => std::fs::canonicalize(DEFAULT_ENV_FILE)
+            .ok()
+            .filter(|path| path.is_file()),
+        None => None,
+    }
+}
+
 pub struct BashFile {
     // This isn't read but if the TempDir goes out-of-scope it might get deleted by the operating system.
     #[allow(dead_code)]
@@ -19,26 +116,112 @@ pub struct BashFile {
     script: Script,
     function: Function,
     params: Vec<String>,
+    /// Functions to call after `function`, in the same shell, as long as each one succeeds.
+    then: Vec<Function>,
+    /// The env file to source before the script, from the options we're run with.
+    env_file: Option<PathBuf>,
+    /// Whether to export the variables the script sets, from the options we're run with.
+    autoexport: bool,
 }
 
 impl BashFile {
-    pub fn new(script: Script, function: Function, params: Vec<String>) -> Self {
-        let dir = tempfile::tempdir().unwrap();
+    /// Makes the temporary directory the lk file goes in. It fails if the system's temporary
+    /// directory, e.g. `$TMPDIR`, isn't writable.
+    pub fn new(script: Script, function: Function, params: Vec<String>) -> Result<Self> {
+        let dir = tempfile::tempdir().with_context(|| {
+            format!(
+                "Unable to create a temporary directory in {} to run {} from. Set TMPDIR to a directory you can write to",
+                std::env::temp_dir().display(),
+                function.name
+            )
+        })?;
         let file_name = format!("./~lk_{}", nanoid!(10));
         let full_path = dir.path().join(&file_name);
-        Self {
+        Ok(Self {
             dir,
             full_path,
             script,
             function,
             params,
+            then: vec![],
+            env_file: None,
+            autoexport: false,
+        })
+    }
+
+    /// Runs the function, returning its exit status, or `None` if it wasn't run because the
+    /// user didn't confirm it or we're only showing the runner. `on_start` is called just
+    /// before it's run, once the user's confirmed it. If it's still running after the
+    /// options' timeout then it's killed, along with anything it started, and the error is
+    /// `TimedOut`.
+    pub fn run(
+        script: Script,
+        function: Function,
+        params: Vec<String>,
+        options: &RunOptions,
+        on_start: impl FnOnce(),
+    ) -> Result<Option<ExitStatus>> {
+        BashFile::new(script, function, params)?.launch(options, on_start)
+    }
+
+    /// Runs `functions` one after the other, like `run`, but in the same shell, so whatever one
+    /// function sets is seen by the next. If one fails then the rest aren't run, and the
+    /// status is the failed function's.
+    pub fn run_chain(
+        script: Script,
+        mut functions: Vec<Function>,
+        options: &RunOptions,
+        on_start: impl FnOnce(),
+    ) -> Result<Option<ExitStatus>> {
+        let function = functions.remove(0);
+        let bash_file = BashFile {
+            then: functions,
+            ..BashFile::new(script, function, vec![])?
+        };
+        bash_file.launch(options, on_start)
+    }
+
+    /// Writes the lk file and executes it, or just prints it if we're showing the runner.
+    fn launch(
+        mut self,
+        options: &RunOptions,
+        on_start: impl FnOnce(),
+    ) -> Result<Option<ExitStatus>> {
+        self.env_file = options.env_file.to_owned();
+        self.autoexport = options.autoexport;
+        if options.show_runner {
+            print!("{}", self.contents());
+            return Ok(None);
         }
+        self.write()?;
+        self.execute(options, on_start)
+    }
+
+    /// The names of the functions we call, in order.
+    fn function_names(&self) -> String {
+        std::iter::once(&self.function)
+            .chain(&self.then)
+            .map(|function| function.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
     }
 
-    pub fn run(script: Script, function: Function, params: Vec<String>) -> Result<()> {
-        let bash_file = BashFile::new(script, function, params);
+    /// Runs the function like `run`, but captures its stdout and stderr instead of writing them
+    /// to the terminal, so lk can be used from other programs. Nothing is printed, the user
+    /// isn't asked to confirm, and the function's stdin is empty.
+    pub fn run_captured(
+        script: Script,
+        function: Function,
+        params: Vec<String>,
+        options: &RunOptions,
+    ) -> Result<Output> {
+        let bash_file = BashFile {
+            env_file: options.env_file.to_owned(),
+            autoexport: options.autoexport,
+            ..BashFile::new(script, function, params)?
+        };
         bash_file.write()?;
-        bash_file.execute()
+        bash_file.execute_captured(options)
     }
 
     /// lk uses a temporary file in order to execute a function in a script. This temporary file
@@ -46,47 +229,579 @@ impl BashFile {
     /// have been loaded into the shell. `std::process::Command` has no way to do this. An alternative
     /// would be adding `"$@"` to the end of the scripts but I'd rather avoid this stipulation.
     pub fn write(&self) -> Result<()> {
-        let mut file = std::fs::OpenOptions::new()
+        std::fs::OpenOptions::new()
             .create(true)
+            .truncate(true)
             .write(true)
             .mode(0o700)
-            .open(&self.full_path)?;
+            .open(&self.full_path)
+            .and_then(|mut file| file.write_all(self.contents().as_bytes()))
+            .with_context(|| {
+                format!(
+                    "Unable to write the lk file to {} to run {}. Check that {} is writable, or set TMPDIR to a directory that is",
+                    self.full_path.display(),
+                    self.function.name,
+                    self.dir.path().display()
+                )
+            })
+    }
 
+    /// What goes in the temporary file, as `write` writes it and `--show-runner` prints it.
+    /// The first function's `# lk: interpreter` and `# lk: shellopts` apply to the whole file,
+    /// because functions we call after it run in the same shell.
+    pub fn contents(&self) -> String {
         // Write the file header
-        let bash_file = r#"#!/usr/bin/env bash
+        let interpreter = self.function.interpreter();
+        let mut contents = format!(
+            r#"#!/usr/bin/env {interpreter}
 # 
 # Temporary lk file used to execute functions in scripts.
 # If you see it here you can delete it and/or gitignore it.
-"#;
-        writeln!(file, "{}", bash_file)?;
+
+"#,
+        );
 
         // CD to the scripts dir. This is an assumption we're making here,
         // but we can't avoid making an assumption, and this is safer than
         // assuming that the script can be run from any directory,
         // although that should be possible in a well written-script.
-        let script_file_name = self.script.file_name();
-        let script_path = self.script.working_dir_absolute();
-        writeln!(file, "cd {script_path}")?;
+        // Both are quoted, so scripts in directories with spaces in, say, still run.
+        let script_file_name = shell_quote(&format!("./{}", self.script.file_name()));
+        let script_path = shell_quote(&self.script.working_dir_absolute());
+        contents.push_str(&format!("cd {script_path}\n"));
 
-        // Source the script so we can access its functions
-        writeln!(file, "source ./{script_file_name}")?;
+        // The env file comes before the script, so the script can override it. Its variables
+        // are exported, so whatever the function runs sees them too.
+        if let Some(env_file) = &self.env_file {
+            let env_file = shell_quote(&env_file.to_string_lossy());
+            contents.push_str(&format!("set -a\n. {env_file}\nset +a\n"));
+        }
+
+        // Set the options the function asked for before sourcing, so they apply to both.
+        if let Some(shellopts) = &self.function.shellopts {
+            contents.push_str(&format!("set {shellopts}\n"));
+        }
+
+        // Source the script so we can access its functions. With autoexport the variables it
+        // sets are exported, but only while it's sourced, so the function's own aren't.
+        if self.autoexport {
+            contents.push_str(&format!("set -a\n. {script_file_name}\nset +a\n"));
+        } else {
+            contents.push_str(&format!(". {script_file_name}\n"));
+        }
 
         // Call the function the user asked for
-        writeln!(file, "{} {}", self.function.name, self.params.join(" "))?;
+        let mut call = std::iter::once(&self.function.name)
+            .chain(&self.params)
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .join(" ");
+        // Then any others, stopping at the first that fails, like `set -e` would.
+        for function in &self.then {
+            contents.push_str(&format!("{call} || exit $?\n"));
+            call = function.name.to_owned();
+        }
+        contents.push_str(&format!("{call}\n"));
+        contents
+    }
+
+    /// This executes the lk file, and then removes it. The function's exit status is returned
+    /// rather than exited with, so it's up to the caller what to do when it fails. `on_start`
+    /// is called once the user's confirmed the function, just before it's run.
+    pub fn execute(
+        &self,
+        options: &RunOptions,
+        on_start: impl FnOnce(),
+    ) -> Result<Option<ExitStatus>> {
+        if options.confirm && !confirm_run(&self.function_names())? {
+            return Ok(None);
+        }
+        on_start();
 
-        Ok(())
+        let mut command = self.command(options);
+        command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+        let status = match options.timeout {
+            Some(timeout) => self.execute_with_timeout(command, timeout)?,
+            None => self.spawn(&mut command)?.wait()?,
+        };
+        Ok(Some(status))
     }
 
-    /// This executes the lk file, and then removes it.
-    pub fn execute(&self) -> Result<()> {
-        print_complete_header(&self.script, &self.function, &self.params);
+    /// Executes the lk file, capturing its output.
+    fn execute_captured(&self, options: &RunOptions) -> Result<Output> {
+        let mut command = self.command(options);
+        command.stdin(Stdio::null());
+        let timeout = match options.timeout {
+            Some(timeout) => timeout,
+            None => return Ok(command.output()?),
+        };
+        let mut child = self.spawn(
+            command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .process_group(0),
+        )?;
+        // We read the output while we wait, or the function would block once a pipe's full.
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let group = child.id() as libc::pid_t;
+        if !wait_or_kill(&mut child, group, timeout)? {
+            return Err(TimedOut {
+                function: self.function_names(),
+                timeout,
+            }
+            .into());
+        }
+        Ok(Output {
+            status: child.wait()?,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
 
-        Command::new(&self.full_path)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
+    /// Starts `command`, saying which lk file it couldn't start if it can't.
+    fn spawn(&self, command: &mut Command) -> Result<Child> {
+        command
             .spawn()
+            .with_context(|| format!("Unable to run {}", self.full_path.display()))
+    }
+
+    /// The command that executes the lk file.
+    fn command(&self, options: &RunOptions) -> Command {
+        let mut command = Command::new(&self.full_path);
+        command.envs(options.env.iter().map(|(key, value)| (key, value)));
+        command
+    }
+
+    /// Executes the lk file in its own process group, so that if it's still running after
+    /// `timeout` we can kill it and everything it started. The group is put in the foreground
+    /// while it runs, so it still gets the terminal's input and ctrl-c.
+    fn execute_with_timeout(&self, mut command: Command, timeout: Duration) -> Result<ExitStatus> {
+        let mut child = self.spawn(command.process_group(0))?;
+        let group = child.id() as libc::pid_t;
+        set_foreground(group);
+        let result = wait_or_kill(&mut child, group, timeout);
+        set_foreground(unsafe { libc::getpgrp() });
+        if result? {
+            Ok(child.wait()?)
+        } else {
+            Err(TimedOut {
+                function: self.function_names(),
+                timeout,
+            }
+            .into())
+        }
+    }
+}
+
+/// Waits for `child` to finish. If it hasn't after `timeout` then its process group is
+/// terminated, and killed if it's still around after `KILL_GRACE`. Returns whether it finished.
+fn wait_or_kill(child: &mut Child, group: libc::pid_t, timeout: Duration) -> Result<bool> {
+    if wait_for(child, timeout)? {
+        return Ok(true);
+    }
+    log::warn!("Timed out after {}s, terminating", timeout.as_secs_f64());
+    unsafe { libc::kill(-group, libc::SIGTERM) };
+    if !wait_for(child, KILL_GRACE)? {
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+        child.wait()?;
+    }
+    // Anything left in the group that ignored SIGTERM goes too.
+    unsafe { libc::kill(-group, libc::SIGKILL) };
+    Ok(false)
+}
+
+/// Reads everything from `reader` on another thread.
+fn read_in_background<R>(reader: Option<R>) -> JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut reader) = reader {
+            if let Err(err) = reader.read_to_end(&mut output) {
+                log::warn!("Unable to read the function's output: {err}");
+            }
+        }
+        output
+    })
+}
+
+/// Waits up to `timeout` for `child` to finish, returning whether it did.
+fn wait_for(child: &mut Child, timeout: Duration) -> Result<bool> {
+    let started = Instant::now();
+    loop {
+        if child.try_wait()?.is_some() {
+            return Ok(true);
+        }
+        if started.elapsed() >= timeout {
+            return Ok(false);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Makes `group` the terminal's foreground process group. Does nothing if stdin isn't a terminal.
+fn set_foreground(group: libc::pid_t) {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 1 {
+            // Once the child's in the foreground we aren't, and the kernel would stop us for
+            // taking the terminal back, unless we ignore SIGTTOU while we do it.
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(libc::STDIN_FILENO, group);
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Writes an executable script called `name` to `dir`, with `functions` as its body.
+    fn script(dir: &std::path::Path, name: &str, functions: &str) -> Script {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/usr/bin/env bash\n\n{functions}")).unwrap();
+        std::fs::set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+        Script {
+            path: path.to_owned(),
+            absolute_path: path,
+            comment: vec![],
+            functions: vec![],
+            shebang: Some("bash".to_string()),
+        }
+    }
+
+    fn function(name: &str) -> Function {
+        Function {
+            name: name.to_string(),
+            line: 1,
+            comment: vec![],
+            body: vec![],
+            group: None,
+            aliases: vec![],
+            shellopts: None,
+            interpreter: None,
+        }
+    }
+
+    #[test]
+    fn test_timeout_kills_function() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(dir.path(), "slow.sh", "slow() {\n    sleep 30\n}\n");
+        let options = RunOptions {
+            timeout: Some(Duration::from_millis(200)),
+            ..RunOptions::default()
+        };
+
+        let started = Instant::now();
+        let result = BashFile::run(script, function("slow"), vec![], &options, || {});
+        assert!(result.unwrap_err().is::<TimedOut>());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_run_returns_exit_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(dir.path(), "fail.sh", "fail() {\n    exit 3\n}\n");
+        let status = BashFile::run(
+            script,
+            function("fail"),
+            vec![],
+            &RunOptions::default(),
+            || {},
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn test_env_is_visible_to_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        // The script reads the variable when it's sourced, as well as in the function.
+        let script = script(
+            dir.path(),
+            "deploy.sh",
+            "SOURCED_ENV=\"$ENV\"\n\ndeploy() {\n    echo \"$SOURCED_ENV $ENV\" > \"$OUT\"\n}\n",
+        );
+        let options = RunOptions {
+            env: vec![
+                ("ENV".to_string(), "staging".to_string()),
+                ("OUT".to_string(), out.to_string_lossy().to_string()),
+            ],
+            ..RunOptions::default()
+        };
+        BashFile::run(script, function("deploy"), vec![], &options, || {}).unwrap();
+        assert_eq!(std::fs::read_to_string(out).unwrap(), "staging staging\n");
+    }
+
+    #[test]
+    fn test_execute_returns_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(dir.path(), "deploy.sh", "deploy() {\n    true\n}\n");
+        // The lk file hasn't been written, so it can't be run, but that's an error, not a panic.
+        let bash_file = BashFile::new(script, function("deploy"), vec![]).unwrap();
+        let err = bash_file
+            .execute(&RunOptions::default(), || {})
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Unable to run"));
+    }
+
+    #[test]
+    fn test_write_to_a_read_only_directory() {
+        if unsafe { libc::geteuid() } == 0 {
+            eprintln!("Skipping test_write_to_a_read_only_directory: root can write anywhere");
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(dir.path(), "deploy.sh", "deploy() {\n    true\n}\n");
+        let bash_file = BashFile::new(script, function("deploy"), vec![]).unwrap();
+        let read_only = Permissions::from_mode(0o500);
+        std::fs::set_permissions(bash_file.dir.path(), read_only).unwrap();
+        let result = bash_file.write();
+        std::fs::set_permissions(bash_file.dir.path(), Permissions::from_mode(0o700)).unwrap();
+        let err = result.unwrap_err().to_string();
+        assert!(err.starts_with("Unable to write the lk file"));
+        assert!(err.contains("set TMPDIR"));
+    }
+
+    #[test]
+    fn test_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        // It's quoted, so the path is sourced as it is.
+        let env_dir = dir.path().join("my \"env\" $dir");
+        std::fs::create_dir(&env_dir).unwrap();
+        let env_file = env_dir.join(".env");
+        std::fs::write(
+            &env_file,
+            format!("ENV=staging\nREGION=eu\nOUT={}\n", out.display()),
+        )
+        .unwrap();
+        // The script overrides one of the variables when it's sourced.
+        let script = script(
+            dir.path(),
+            "deploy.sh",
+            "REGION=us\n\ndeploy() {\n    bash -c 'echo \"$ENV $REGION\"' > \"$OUT\"\n}\n",
+        );
+        let options = RunOptions {
+            env_file: find_env_file(Some(&env_file), false),
+            ..RunOptions::default()
+        };
+        BashFile::run(script, function("deploy"), vec![], &options, || {}).unwrap();
+        assert_eq!(std::fs::read_to_string(out).unwrap(), "staging us\n");
+    }
+
+    #[test]
+    fn test_script_in_a_directory_with_a_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_dir = dir.path().join("my dir");
+        std::fs::create_dir(&script_dir).unwrap();
+        let script = script(
+            &script_dir,
+            "it's.sh",
+            "deploy() {\n    echo deploying\n}\n",
+        );
+        let output =
+            BashFile::run_captured(script, function("deploy"), vec![], &RunOptions::default())
+                .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "deploying\n");
+    }
+
+    #[test]
+    fn test_autoexport() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            dir.path(),
+            "deploy.sh",
+            "REGION=eu\n\nshow() {\n    printenv REGION\n}\n",
+        );
+        let run = |autoexport| {
+            let options = RunOptions {
+                autoexport,
+                ..RunOptions::default()
+            };
+            BashFile::run_captured(script.clone(), function("show"), vec![], &options).unwrap()
+        };
+        assert_eq!(String::from_utf8(run(true).stdout).unwrap(), "eu\n");
+        // Without it the function can see REGION, but not the programs it runs, like printenv.
+        assert!(run(false).stdout.is_empty());
+    }
+
+    #[test]
+    fn test_posix_interpreter() {
+        // `source` is a bashism, so the lk file has to work in plain sh too.
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            dir.path(),
+            "deploy.sh",
+            "deploy() {\n    echo deploying\n}\n",
+        );
+        let deploy = Function {
+            interpreter: Some("sh".to_string()),
+            ..function("deploy")
+        };
+        let output =
+            BashFile::run_captured(script, deploy, vec![], &RunOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "deploying\n");
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn test_missing_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(find_env_file(Some(&dir.path().join(".env")), true), None);
+        // A directory isn't an env file either.
+        assert_eq!(find_env_file(Some(dir.path()), true), None);
+    }
+
+    #[test]
+    fn test_run_captured() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            dir.path(),
+            "greet.sh",
+            "greet() {\n    echo \"hello $1\"\n    echo oops >&2\n}\n",
+        );
+        let params = vec!["world".to_string()];
+        for timeout in [None, Some(Duration::from_secs(10))] {
+            let options = RunOptions {
+                timeout,
+                ..RunOptions::default()
+            };
+            let output =
+                BashFile::run_captured(script.clone(), function("greet"), params.clone(), &options)
+                    .unwrap();
+            assert!(output.status.success());
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+            assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+        }
+    }
+
+    #[test]
+    fn test_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            dir.path(),
+            "deploy.sh",
+            "deploy() {\n    echo deploying\n}\n",
+        );
+        let contents = BashFile::new(script, function("deploy"), vec!["dev".to_string()])
             .unwrap()
-            .wait()?;
-        Ok(())
+            .contents();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "#!/usr/bin/env bash");
+        assert_eq!(
+            lines[lines.len() - 3..],
+            [
+                format!("cd {}", dir.path().display()).as_str(),
+                ". ./deploy.sh",
+                "deploy dev",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpreter_and_shellopts() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            dir.path(),
+            "deploy.sh",
+            "deploy() {\n    echo deploying\n}\n",
+        );
+        let deploy = Function {
+            interpreter: Some("zsh".to_string()),
+            shellopts: Some("-euo pipefail".to_string()),
+            ..function("deploy")
+        };
+        let contents = BashFile::new(script, deploy, vec![]).unwrap().contents();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "#!/usr/bin/env zsh");
+        assert_eq!(
+            lines[lines.len() - 3..],
+            ["set -euo pipefail", ". ./deploy.sh", "deploy"]
+        );
+    }
+
+    #[test]
+    fn test_shellopts_apply_to_the_function() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = script(
+            dir.path(),
+            "steps.sh",
+            &format!(
+                "steps() {{\n    false\n    echo after > {}\n}}\n",
+                out.display()
+            ),
+        );
+        let steps = Function {
+            shellopts: Some("-e".to_string()),
+            ..function("steps")
+        };
+        let status = BashFile::run(script, steps, vec![], &RunOptions::default(), || {})
+            .unwrap()
+            .unwrap();
+        // With -e the function stops at false.
+        assert_eq!(status.code(), Some(1));
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn test_run_chain_shares_the_shell() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = script(
+            dir.path(),
+            "steps.sh",
+            &format!(
+                "build() {{\n    BUILT=yes\n}}\ncheck() {{\n    echo \"$BUILT\" > {}\n    return 2\n}}\nrelease() {{\n    echo released >> {}\n}}\n",
+                out.display(),
+                out.display()
+            ),
+        );
+        let functions = vec![function("build"), function("check"), function("release")];
+        let status = BashFile::run_chain(script, functions, &RunOptions::default(), || {})
+            .unwrap()
+            .unwrap();
+        // check sees what build set, and release isn't run because check failed.
+        assert_eq!(status.code(), Some(2));
+        assert_eq!(std::fs::read_to_string(out).unwrap(), "yes\n");
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("./infra/deploy.sh"), "./infra/deploy.sh");
+        assert_eq!(
+            shell_quote("my scripts/deploy.sh"),
+            "'my scripts/deploy.sh'"
+        );
+        assert_eq!(shell_quote("$(rm -rf ~)\""), "'$(rm -rf ~)\"'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_parse_env() {
+        assert_eq!(
+            parse_env("ENV=staging"),
+            Ok(("ENV".to_string(), "staging".to_string()))
+        );
+        assert_eq!(
+            parse_env("URL=http://x?a=b"),
+            Ok(("URL".to_string(), "http://x?a=b".to_string()))
+        );
+        assert_eq!(
+            parse_env("EMPTY="),
+            Ok(("EMPTY".to_string(), "".to_string()))
+        );
+        assert!(parse_env("ENV").is_err());
+        assert!(parse_env("=staging").is_err());
+        assert!(parse_env("1ENV=staging").is_err());
+        assert!(parse_env("MY-ENV=staging").is_err());
     }
 }
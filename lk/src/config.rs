@@ -1,66 +1,790 @@
+use anyhow::{bail, Context, Result};
+use fuzzy_finder::colours::Colours;
+use globset::GlobBuilder;
 /// Holds all the configuraion for lk.
+use lk::script::{FunctionOrder, LabelFormat};
+use lk::theme::{Palette, Theme};
+use pastel_colours::Colour;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use std::{
+    collections::BTreeMap,
     fs::{self, OpenOptions},
     io::{BufWriter, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+/// Where lk keeps its config, history and logs: `.config/lk` in `home_dir`, which is created
+/// if it doesn't exist yet. If there's no home directory, or the directory can't be created,
+/// then a temporary directory is used instead, so lk can still run.
+pub fn lk_dir(home_dir: Option<PathBuf>) -> String {
+    // Use a dir in ~/.config like a good human, but then store logs in it lol.
+    match home_dir.map(|home_dir| home_dir.join(".config/lk")) {
+        Some(lk_dir) => match fs::create_dir_all(&lk_dir) {
+            Ok(()) => return lk_dir.to_string_lossy().to_string(),
+            Err(err) => eprintln!(
+                "Unable to create {}: {err}. Using a temporary directory instead.",
+                lk_dir.display()
+            ),
+        },
+        None => {
+            eprintln!("Unable to access your home directory. Using a temporary directory instead.")
+        }
+    }
+    tempfile::tempdir()
+        .unwrap()
+        .into_path()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The modes `default_mode` can be.
+const MODES: [&str; 2] = ["fuzzy", "list"];
+
+/// The settings `--set` can change. Any `env.<NAME>` can be set too.
+const SETTINGS: [&str; 21] = [
+    "default_mode",
+    "ignore",
+    "library_dirs",
+    "skip_binary_check",
+    "hide_empty_scripts",
+    "sort_functions",
+    "label_format",
+    "strip_common_prefix",
+    "hyperlinks",
+    "parse_docstrings",
+    "source_dotenv",
+    "frecency",
+    "quiet",
+    "confirm_patterns",
+    "lines_to_show",
+    "prompt_symbol",
+    "theme",
+    "colors.match",
+    "colors.selected_bg",
+    "colors.pointer",
+    "colors.prompt",
+];
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     /// The default mode: fuzzy or list
     pub default_mode: String,
+    /// Gitignore-style patterns for paths to ignore. A `.lkignore` takes precedence.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Directories of scripts to search as well as the current one, wherever lk is run, e.g.
+    /// `~/.local/share/lk-scripts`. `~` is your home directory.
+    #[serde(default)]
+    pub library_dirs: Vec<String>,
+    /// Whether to find scripts by their executable bit alone, like `--no-binary-check`, rather
+    /// than reading each one to check it isn't a binary. Scanning is quicker, but executable
+    /// binaries are listed too.
+    #[serde(default)]
+    pub skip_binary_check: bool,
+    /// Whether to leave scripts without any functions, like libraries of `_private` helpers,
+    /// out of the list and the fuzzy finder. `--show-empty-scripts` shows them anyway.
+    #[serde(default = "default_hide_empty_scripts")]
+    pub hide_empty_scripts: bool,
+    /// How to order a script's functions when they're listed: none, which keeps the script's
+    /// order, or alpha. `--sort-functions` takes precedence.
+    #[serde(default)]
+    pub sort_functions: FunctionOrder,
+    /// How the fuzzy finder labels functions, e.g. `{path}/{file}::{function}`. Scripts are
+    /// labelled with the part from `{path}` to `{file}`.
+    #[serde(default)]
+    pub label_format: LabelFormat,
+    /// Whether the fuzzy finder leaves the directory that all the scripts are in out of their
+    /// labels, like `--strip-common-prefix`.
+    #[serde(default)]
+    pub strip_common_prefix: bool,
+    /// Whether to make script paths links that open the script, in terminals that look like
+    /// they support them. They're left out when lk's output isn't a terminal.
+    #[serde(default)]
+    pub hyperlinks: bool,
+    /// Whether a function without a comment above it can be described by a `: 'docstring'`
+    /// at the start of its body instead. It's off by default, because it's slower.
+    #[serde(default)]
+    pub parse_docstrings: bool,
+    /// Whether to source the `.env` where lk's run, if there is one, before the script, when
+    /// `--env-file` isn't given. It's off by default, so a `.env` that's meant for something
+    /// else isn't run.
+    #[serde(default)]
+    pub source_dotenv: bool,
+    /// Whether functions you run often get a small boost in the fuzzy finder.
+    #[serde(default)]
+    pub frecency: bool,
+    /// Whether to leave out the banner before a function's output, like `--quiet`.
+    #[serde(default)]
+    pub quiet: bool,
+    /// Regexes for functions that lk asks about before running, e.g. `^destroy`.
+    #[serde(default)]
+    pub confirm_patterns: Vec<String>,
+    /// The number of lines to show in the fuzzy finder. `--number` takes precedence.
+    pub lines_to_show: Option<i8>,
+    /// What the fuzzy finder shows before the search, e.g. `❯`. `colors.prompt` colours it.
+    pub prompt_symbol: Option<String>,
+    /// The colours lk uses: default, high-contrast or colorblind. `colors` overrides the
+    /// fuzzy finder's.
+    #[serde(default)]
+    pub theme: Theme,
+    /// Environment variables to set for every function. `--env` takes precedence.
+    /// Like `colors`, this is a TOML table, so it must come after the plain values.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Colours for the fuzzy finder. A TOML table, so it must come after the plain values.
+    #[serde(default)]
+    pub colors: ColorsConfig,
+    /// Overrides for particular directories, as `[[project]]` sections. Must come last,
+    /// because it's an array of TOML tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub project: Vec<ProjectConfig>,
+}
+
+fn default_hide_empty_scripts() -> bool {
+    true
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            default_mode: "list".to_string(),
+            ignore: vec![],
+            library_dirs: vec![],
+            skip_binary_check: false,
+            hide_empty_scripts: default_hide_empty_scripts(),
+            sort_functions: FunctionOrder::default(),
+            label_format: LabelFormat::default(),
+            strip_common_prefix: false,
+            hyperlinks: false,
+            parse_docstrings: false,
+            source_dotenv: false,
+            frecency: false,
+            quiet: false,
+            confirm_patterns: vec![],
+            lines_to_show: None,
+            prompt_symbol: None,
+            theme: Theme::default(),
+            env: BTreeMap::new(),
+            colors: ColorsConfig::default(),
+            project: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// The colours to print with: the theme's, with the fuzzy finder's overridden by
+    /// `colors`.
+    pub fn palette(&self) -> Palette {
+        let palette = self.theme.palette();
+        Palette {
+            finder: self.colors.resolve(palette.finder.to_owned()),
+            ..palette
+        }
+    }
+
+    /// The `library_dirs`, with `~` expanded.
+    pub fn library_dirs(&self) -> Vec<String> {
+        self.library_dirs
+            .iter()
+            .map(|dir| expand_home(dir))
+            .collect()
+    }
+
+    /// Whether `function` matches one of the `confirm_patterns`. Invalid patterns are
+    /// logged and skipped.
+    pub fn needs_confirmation(&self, function: &str) -> bool {
+        self.confirm_patterns
+            .iter()
+            .any(|pattern| match Regex::new(pattern) {
+                Ok(regex) => regex.is_match(function),
+                Err(err) => {
+                    log::warn!("Ignoring invalid confirm pattern {pattern}: {err}");
+                    false
+                }
+            })
+    }
+
+    /// Checks that `default_mode`, and every project's, is one of the modes lk has.
+    fn check_modes(&self) -> Result<()> {
+        let project_modes = self
+            .project
+            .iter()
+            .filter_map(|project| project.default_mode.as_ref());
+        for mode in std::iter::once(&self.default_mode).chain(project_modes) {
+            if !MODES.contains(&mode.as_str()) {
+                bail!("'{mode}' isn't a mode. Use {}", MODES.join(" or "));
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the setting called `key`, e.g. `lines_to_show` or `colors.match`, as passed to
+    /// `--set`. `value` is read as TOML, e.g. `10` or `["vendor/"]`, or as a string if it
+    /// isn't valid TOML or isn't the right type.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let is_env = matches!(key.strip_prefix("env."), Some(name) if !name.is_empty());
+        if !SETTINGS.contains(&key) && !is_env {
+            bail!(
+                "Unknown setting '{key}'. Valid settings are {} and env.<NAME>",
+                SETTINGS.join(", ")
+            );
+        }
+        let typed = toml::from_str::<toml::Value>(&format!("value = {value}"))
+            .ok()
+            .and_then(|table| table.get("value").cloned());
+        let untyped = Some(toml::Value::String(value.to_string()));
+        for value in typed.into_iter().chain(untyped) {
+            let mut config = toml::Value::try_from(&*self)?;
+            let mut table = config.as_table_mut().unwrap();
+            let mut names = key.split('.').peekable();
+            while let Some(name) = names.next() {
+                if names.peek().is_none() {
+                    table.insert(name.to_string(), value.clone());
+                } else {
+                    table = table
+                        .entry(name)
+                        .or_insert_with(|| toml::Value::Table(Default::default()))
+                        .as_table_mut()
+                        .unwrap();
+                }
+            }
+            if let Ok(config) = config.try_into::<Config>() {
+                config.check_modes()?;
+                *self = config;
+                return Ok(());
+            }
+        }
+        bail!("'{value}' isn't a valid value for {key}")
+    }
+
+    /// The first `[[project]]` whose `path` matches `dir`, or one of its parents. Invalid
+    /// paths are logged and skipped.
+    pub fn project_for(&self, dir: &Path) -> Option<&ProjectConfig> {
+        self.project.iter().find(|project| {
+            let pattern = expand_home(&project.path);
+            match GlobBuilder::new(&pattern).literal_separator(true).build() {
+                Ok(glob) => {
+                    let glob = glob.compile_matcher();
+                    dir.ancestors().any(|dir| glob.is_match(dir))
+                }
+                Err(err) => {
+                    log::warn!("Ignoring invalid project path {}: {err}", project.path);
+                    false
+                }
+            }
+        })
+    }
+}
+
+/// Parses a `key=value` setting, as passed to `--set`.
+pub fn parse_setting(setting: &str) -> std::result::Result<(String, String), String> {
+    setting
+        .split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .ok_or_else(|| format!("expected key=value, but there's no '=' in '{setting}'"))
+}
+
+/// A `[[project]]` section, which overrides the global settings when lk is run in, or under,
+/// a directory matching `path`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProjectConfig {
+    /// A directory, or a glob matching directories, e.g. `~/work/*`. `~` is your home directory.
+    pub path: String,
+    pub default_mode: Option<String>,
+    /// Replaces the global `ignore` list.
+    pub ignore: Option<Vec<String>>,
+    pub lines_to_show: Option<i8>,
+}
+
+/// Expands a leading `~` into the home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home_dir)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{rest}", home_dir.to_string_lossy())
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// The `[colors]` section. Each colour is one of the pastel colour names, e.g. `dark_blue`,
+/// an ANSI colour code from 0 to 255, or a hex colour like `#71bef2`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ColorsConfig {
+    /// The background of characters that match the search.
+    #[serde(rename = "match")]
+    pub matched: Option<String>,
+    /// The background of the selected line.
+    pub selected_bg: Option<String>,
+    /// The `>` that points at the selected line.
+    pub pointer: Option<String>,
+    /// The `$` before the search.
+    pub prompt: Option<String>,
+}
+
+impl ColorsConfig {
+    /// Resolves the configured colours, falling back to `defaults` for any that
+    /// aren't set or aren't valid.
+    pub fn resolve(&self, defaults: Colours) -> Colours {
+        Colours {
+            matched: resolve_colour("match", &self.matched, Colour::bg, defaults.matched),
+            selected: resolve_colour(
+                "selected_bg",
+                &self.selected_bg,
+                Colour::bg,
+                defaults.selected,
+            ),
+            pointer: resolve_colour("pointer", &self.pointer, Colour::fg, defaults.pointer),
+            prompt: resolve_colour("prompt", &self.prompt, Colour::fg, defaults.prompt),
+        }
+    }
+}
+
+fn resolve_colour(
+    role: &str,
+    colour: &Option<String>,
+    to_escape_code: fn(&Colour) -> String,
+    default: String,
+) -> String {
+    match colour {
+        Some(name) => match Colour::parse(name) {
+            Some(colour) => to_escape_code(&colour),
+            None => {
+                log::warn!("Unknown colour for {role}: {name}");
+                eprintln!(
+                    "Unknown colour '{name}' for '{role}' in your config. Using the default."
+                );
+                default
+            }
+        },
+        None => default,
+    }
 }
 
 pub struct ConfigFile {
     pub config: Config,
+    /// The `[[project]]` for the current directory, if there is one.
+    pub project: Option<ProjectConfig>,
     lk_dir: String,
     file_name: String,
 }
 
 impl ConfigFile {
-    pub fn new(lk_dir: &str, file_name: &str) -> Self {
+    /// Loads the config file, creating it first if it doesn't exist. It fails if the file
+    /// can't be read, or isn't a valid config, e.g. it has an unknown theme.
+    pub fn new(lk_dir: &str, file_name: &str) -> Result<Self> {
         let path = PathBuf::from(format!("{}/{}", lk_dir, file_name));
         // Create a default config file if it doesn't exist
         if !path.exists() {
             log::info!("Creating config file at {}", path.display());
-            fs::create_dir(&path.parent().expect("failed to get `.config` dir"))
+            fs::create_dir_all(path.parent().expect("failed to get `.config` dir"))
                 .unwrap_or_else(|_| panic!("failed to create {} directory", path.display()));
-            match OpenOptions::new().write(true).create(true).open(&path) {
-                Ok(file) => {
-                    let mut buffered = BufWriter::new(file);
-                    let default_config = Config {
-                        default_mode: "list".to_string(),
-                    };
-                    let toml = toml::to_string(&default_config).unwrap();
-                    write!(buffered, "{}", toml).expect("Failed to write to file");
-                }
-                Err(e) => log::error!("Unable to create default config file: {}", e),
+            if let Err(e) = write_config(&path, &Config::default()) {
+                log::error!("Unable to create default config file: {}", e);
+                // Don't leave a half-written file behind for next time.
+                let _ = fs::remove_file(&path);
             }
         } else {
             log::info!("Using config file at {}", path.display());
         }
 
-        // Load the config file
-        let config_string = std::fs::read_to_string(path).expect("Couldn't read config file");
-        let config = toml::from_str::<Config>(&config_string).expect("Couldn't parse config file");
-        Self {
+        // Load the config file, if we managed to create it.
+        let config = if path.exists() {
+            let config_string = std::fs::read_to_string(&path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            let config = toml::from_str::<Config>(&config_string)
+                .with_context(|| format!("Unable to parse {}", path.display()))?;
+            config
+                .check_modes()
+                .with_context(|| format!("Unable to use {}", path.display()))?;
+            config
+        } else {
+            Config::default()
+        };
+        let project = std::env::current_dir()
+            .ok()
+            .and_then(|dir| config.project_for(&dir).cloned());
+        if let Some(project) = &project {
+            log::info!("Using the config for project {}", project.path);
+        }
+        Ok(Self {
             config,
+            project,
             lk_dir: lk_dir.to_string(),
             file_name: file_name.to_string(),
+        })
+    }
+
+    /// The default mode, from the current project if it sets one.
+    pub fn default_mode(&self) -> &str {
+        self.project
+            .as_ref()
+            .and_then(|project| project.default_mode.as_deref())
+            .unwrap_or(&self.config.default_mode)
+    }
+
+    /// The patterns to ignore, from the current project if it sets them.
+    pub fn ignore(&self) -> &[String] {
+        self.project
+            .as_ref()
+            .and_then(|project| project.ignore.as_deref())
+            .unwrap_or(&self.config.ignore)
+    }
+
+    /// The number of lines to show in the fuzzy finder, from the current project if it
+    /// sets one.
+    pub fn lines_to_show(&self) -> Option<i8> {
+        self.project
+            .as_ref()
+            .and_then(|project| project.lines_to_show)
+            .or(self.config.lines_to_show)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = PathBuf::from(format!("{}/{}", self.lk_dir, self.file_name));
+        write_config(&path, &self.config)
+            .with_context(|| format!("Couldn't write config file at {}", path.display()))
+    }
+}
+
+/// Writes `config` to `path`, replacing whatever's there.
+fn write_config(path: &Path, config: &Config) -> Result<()> {
+    let toml = toml::to_string(config)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    let mut buffered = BufWriter::new(file);
+    write!(buffered, "{}", toml)?;
+    // Dropping a BufWriter flushes it, but ignores any error.
+    buffered.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pastel_colours::{BLUE_FG, RED_BG, RED_FG};
+
+    #[test]
+    fn test_lk_dir_is_created() {
+        let home = tempfile::tempdir().unwrap();
+        let lk_dir = lk_dir(Some(home.path().join("new_user")));
+        assert_eq!(
+            PathBuf::from(&lk_dir),
+            home.path().join("new_user/.config/lk")
+        );
+        assert!(Path::new(&lk_dir).is_dir());
+        // And lk can use it.
+        ConfigFile::new(&lk_dir, "lk.toml").unwrap();
+        assert!(Path::new(&lk_dir).join("lk.toml").exists());
+    }
+
+    #[test]
+    fn test_lk_dir_falls_back_to_a_temporary_directory() {
+        let home = tempfile::tempdir().unwrap();
+        // A file where the home directory should be, so nothing can be created in it.
+        let not_a_dir = home.path().join("file");
+        fs::write(&not_a_dir, "").unwrap();
+        for home_dir in [Some(not_a_dir), None] {
+            let lk_dir = lk_dir(home_dir);
+            assert!(Path::new(&lk_dir).is_dir());
+            assert!(!lk_dir.starts_with(&*home.path().to_string_lossy()));
+            std::fs::remove_dir(lk_dir).unwrap();
         }
     }
 
-    pub fn save(&self) {
-        let path = format!("{}/{}", self.lk_dir, self.file_name);
-        let toml = toml::to_string(&self.config).expect("Couldn't serialize config file");
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&path)
-            .unwrap_or_else(|_| panic!("Couldn't open config file at {}", path));
-        let mut buffered = BufWriter::new(file);
-        write!(buffered, "{}", toml).expect("Couldn't write to config file");
+    #[test]
+    fn test_resolve_colours() {
+        let colors = ColorsConfig {
+            matched: Some("red".to_string()),
+            selected_bg: Some("#0d588a".to_string()),
+            pointer: Some("208".to_string()),
+            prompt: None,
+        };
+        let colours = colors.resolve(Colours::default());
+        assert_eq!(colours.matched, RED_BG.to_string());
+        assert_eq!(colours.selected, "\u{1b}[48;2;13;88;138m");
+        assert_eq!(colours.pointer, "\u{1b}[38;5;208m");
+        assert_eq!(colours.prompt, BLUE_FG.to_string());
+    }
+
+    #[test]
+    fn test_invalid_colours_fall_back() {
+        let colors = ColorsConfig {
+            matched: Some("not a colour".to_string()),
+            selected_bg: Some("#12345".to_string()),
+            pointer: Some("256".to_string()),
+            prompt: Some("".to_string()),
+        };
+        assert_eq!(colors.resolve(Colours::default()), Colours::default());
+    }
+
+    #[test]
+    fn test_theme() {
+        let mut config = Config::default();
+        assert_eq!(config.palette(), Theme::Default.palette());
+        config.set("theme", "colorblind").unwrap();
+        assert_eq!(config.theme, Theme::Colorblind);
+        // The fuzzy finder's colours can still be changed on top of the theme.
+        config.set("colors.prompt", "red").unwrap();
+        let palette = config.palette();
+        let colorblind = Theme::Colorblind.palette();
+        assert_eq!(palette.finder.prompt, RED_FG.to_string());
+        assert_eq!(palette.finder.pointer, colorblind.finder.pointer);
+        assert_eq!(palette.function, colorblind.function);
+        assert!(config.set("theme", "neon").is_err());
+        assert_eq!(config.theme, Theme::Colorblind);
+    }
+
+    #[test]
+    fn test_needs_confirmation() {
+        let config = Config {
+            confirm_patterns: vec!["^destroy".to_string(), "drop_".to_string(), "(".to_string()],
+            ..Config::default()
+        };
+        assert!(config.needs_confirmation("destroy_everything"));
+        assert!(config.needs_confirmation("maybe_drop_database"));
+        assert!(!config.needs_confirmation("build"));
+        assert!(!Config::default().needs_confirmation("destroy"));
+    }
+
+    #[test]
+    fn test_colors_section() {
+        let config =
+            toml::from_str::<Config>("default_mode = \"fuzzy\"\n\n[colors]\nmatch = \"teal\"\n")
+                .unwrap();
+        assert_eq!(config.colors.matched, Some("teal".to_string()));
+        assert_eq!(config.colors.prompt, None);
+    }
+
+    #[test]
+    fn test_env_section_round_trips() {
+        let mut config = Config::default();
+        config.env.insert("ENV".to_string(), "staging".to_string());
+        let toml = toml::to_string(&config).unwrap();
+        let config = toml::from_str::<Config>(&toml).unwrap();
+        assert_eq!(config.env.get("ENV"), Some(&"staging".to_string()));
+    }
+
+    fn project(path: &str, default_mode: &str) -> ProjectConfig {
+        ProjectConfig {
+            path: path.to_string(),
+            default_mode: Some(default_mode.to_string()),
+            ignore: None,
+            lines_to_show: None,
+        }
+    }
+
+    #[test]
+    fn test_project_for() {
+        let config = Config {
+            project: vec![
+                project("/work/api", "fuzzy"),
+                project("/work/*", "list"),
+                project("~/scripts", "fuzzy"),
+                project("[", "fuzzy"),
+            ],
+            ..Config::default()
+        };
+        let mode = |dir: &Path| {
+            config
+                .project_for(dir)
+                .and_then(|project| project.default_mode.as_deref())
+        };
+        assert_eq!(mode(Path::new("/work/api")), Some("fuzzy"));
+        // Sub-directories are part of the project.
+        assert_eq!(mode(Path::new("/work/api/src")), Some("fuzzy"));
+        assert_eq!(mode(Path::new("/work/web/src")), Some("list"));
+        assert_eq!(
+            mode(&dirs::home_dir().unwrap().join("scripts")),
+            Some("fuzzy")
+        );
+        assert_eq!(mode(Path::new("/home/work/api")), None);
+        assert_eq!(mode(Path::new("/")), None);
+    }
+
+    #[test]
+    fn test_projects_round_trip() {
+        let mut config = Config::default();
+        config.env.insert("ENV".to_string(), "dev".to_string());
+        config.project = vec![
+            ProjectConfig {
+                ignore: Some(vec!["vendor/".to_string()]),
+                lines_to_show: Some(12),
+                ..project("~/work/api", "fuzzy")
+            },
+            project("~/work/*", "list"),
+        ];
+        let toml = toml::to_string(&config).unwrap();
+        assert!(toml.contains("[[project]]"));
+        let parsed = toml::from_str::<Config>(&toml).unwrap();
+        assert_eq!(parsed.project, config.project);
+        assert_eq!(parsed.env, config.env);
+        // Without any projects there's no `project` key at all.
+        let toml = toml::to_string(&Config::default()).unwrap();
+        assert!(!toml.contains("project"));
+        assert!(toml::from_str::<Config>(&toml).unwrap().project.is_empty());
+    }
+
+    #[test]
+    fn test_project_overrides() {
+        let config_file = |dir: &str| {
+            let config = toml::from_str::<Config>(
+                "default_mode = \"list\"\nignore = [\"old/\"]\nlines_to_show = 9\n\n[[project]]\npath = \"/work/api\"\ndefault_mode = \"fuzzy\"\n",
+            )
+            .unwrap();
+            ConfigFile {
+                project: config.project_for(Path::new(dir)).cloned(),
+                config,
+                lk_dir: String::new(),
+                file_name: String::new(),
+            }
+        };
+        let in_project = config_file("/work/api");
+        assert_eq!(in_project.default_mode(), "fuzzy");
+        // Anything the project doesn't set falls back to the global settings.
+        assert_eq!(in_project.ignore(), ["old/".to_string()]);
+        assert_eq!(in_project.lines_to_show(), Some(9));
+        assert_eq!(config_file("/work/web").default_mode(), "list");
+    }
+
+    #[test]
+    fn test_set() {
+        let mut config = Config::default();
+        config.set("default_mode", "fuzzy").unwrap();
+        config.set("lines_to_show", "10").unwrap();
+        config.set("frecency", "true").unwrap();
+        config.set("quiet", "true").unwrap();
+        config.set("ignore", "[\"vendor/\", \"old/\"]").unwrap();
+        // Colours are strings, even when they look like numbers.
+        config.set("colors.prompt", "208").unwrap();
+        config.set("env.ENV", "staging").unwrap();
+        config.set("prompt_symbol", "❯").unwrap();
+        config.set("sort_functions", "alpha").unwrap();
+        config.set("hyperlinks", "true").unwrap();
+        config.set("strip_common_prefix", "true").unwrap();
+        config.set("parse_docstrings", "true").unwrap();
+        config.set("source_dotenv", "true").unwrap();
+        assert_eq!(config.default_mode, "fuzzy");
+        assert_eq!(config.lines_to_show, Some(10));
+        assert!(config.frecency);
+        assert!(config.quiet);
+        assert_eq!(config.ignore, vec!["vendor/", "old/"]);
+        assert_eq!(config.colors.prompt, Some("208".to_string()));
+        assert_eq!(config.env.get("ENV"), Some(&"staging".to_string()));
+        assert_eq!(config.prompt_symbol, Some("❯".to_string()));
+        assert_eq!(config.sort_functions, FunctionOrder::Alpha);
+        assert!(config.hyperlinks);
+        assert!(config.strip_common_prefix);
+        assert!(config.parse_docstrings);
+        assert!(config.source_dotenv);
+    }
+
+    #[test]
+    fn test_label_format() {
+        let mut config = Config::default();
+        assert_eq!(config.label_format, LabelFormat::default());
+        config.set("label_format", "{file}::{function}").unwrap();
+        assert_eq!(
+            String::from(config.label_format.clone()),
+            "{file}::{function}"
+        );
+        assert!(config.set("label_format", "{file}::{name}").is_err());
+        assert!(toml::from_str::<Config>(
+            "default_mode = \"list\"\nlabel_format = \"{script} {function}\"\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_library_dirs() {
+        let config: Config = toml::from_str(
+            "default_mode = \"list\"\nlibrary_dirs = [\"~/lk-scripts\", \"/opt/scripts\"]\n",
+        )
+        .unwrap();
+        let home_dir = dirs::home_dir().unwrap();
+        assert_eq!(
+            config.library_dirs(),
+            vec![
+                format!("{}/lk-scripts", home_dir.to_string_lossy()),
+                "/opt/scripts".to_string()
+            ]
+        );
+        assert!(Config::default().library_dirs().is_empty());
+    }
+
+    #[test]
+    fn test_hide_empty_scripts_by_default() {
+        // Configs from before the setting existed hide them too.
+        let config: Config = toml::from_str("default_mode = \"list\"\n").unwrap();
+        assert!(config.hide_empty_scripts);
+        assert!(Config::default().hide_empty_scripts);
+        let mut config = Config::default();
+        config.set("hide_empty_scripts", "false").unwrap();
+        assert!(!config.hide_empty_scripts);
+    }
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(
+            parse_setting("ignore = [\"a=b\"]"),
+            Ok(("ignore".to_string(), "[\"a=b\"]".to_string()))
+        );
+        assert!(parse_setting("frecency").is_err());
+    }
+
+    #[test]
+    fn test_set_invalid() {
+        let mut config = Config::default();
+        let err = config.set("colours.match", "teal").unwrap_err().to_string();
+        assert!(err.starts_with(
+            "Unknown setting 'colours.match'. Valid settings are default_mode, ignore,"
+        ));
+        assert!(config.set("env.", "oops").is_err());
+        assert!(config.set("lines_to_show", "lots").is_err());
+        assert!(config.set("default_mode", "tree").is_err());
+        assert_eq!(config.default_mode, "list");
+        assert_eq!(config.lines_to_show, None);
+    }
+
+    #[test]
+    fn test_projects_modes_are_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let lk_dir = dir.path().to_string_lossy().to_string();
+        std::fs::write(
+            dir.path().join("lk.toml"),
+            "default_mode = \"list\"\n\n[[project]]\npath = \"/work\"\ndefault_mode = \"fuzy\"\n",
+        )
+        .unwrap();
+        let err = ConfigFile::new(&lk_dir, "lk.toml").err().unwrap();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "'fuzy' isn't a mode. Use fuzzy or list"
+        );
+    }
+
+    #[test]
+    fn test_config_file_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let lk_dir = dir.path().join("lk").to_string_lossy().to_string();
+        let mut config_file = ConfigFile::new(&lk_dir, "lk.toml").unwrap();
+        assert_eq!(config_file.config.default_mode, "list");
+        config_file.config.default_mode = "fuzzy".to_string();
+        config_file
+            .config
+            .env
+            .insert("ENV".to_string(), "dev".to_string());
+        config_file.config.project = vec![project("~/work/*", "list")];
+        config_file.save().unwrap();
+        let config_file = ConfigFile::new(&lk_dir, "lk.toml").unwrap();
+        assert_eq!(config_file.config.default_mode, "fuzzy");
+        assert_eq!(config_file.config.env.get("ENV"), Some(&"dev".to_string()));
+        assert_eq!(
+            config_file.config.project,
+            vec![project("~/work/*", "list")]
+        );
     }
 }
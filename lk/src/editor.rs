@@ -0,0 +1,86 @@
+/// Opens scripts in the user's editor, for `--edit`.
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// What we edit with if neither `$VISUAL` nor `$EDITOR` is set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Opens `path` in the user's editor, at `line` if we know how to tell it, and waits for them
+/// to quit it. Returns whether the editor exited successfully.
+pub fn edit(path: &Path, line: usize) -> Result<bool> {
+    let visual = std::env::var("VISUAL").ok();
+    let editor = std::env::var("EDITOR").ok();
+    let editor = choose_editor(visual.as_deref(), editor.as_deref());
+    let status = editor_command(editor, path, line)
+        .status()
+        .with_context(|| format!("Unable to start your editor, {editor}"))?;
+    Ok(status.success())
+}
+
+/// `$VISUAL` if it's set, then `$EDITOR`, then vi, like most tools that open an editor.
+fn choose_editor<'a>(visual: Option<&'a str>, editor: Option<&'a str>) -> &'a str {
+    [visual, editor]
+        .into_iter()
+        .flatten()
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or(DEFAULT_EDITOR)
+}
+
+/// Builds the command for `editor`, which is split on whitespace like a shell would,
+/// e.g. `code --wait`. Most editors go to a line with `+<line>`, but some want `path:line`.
+fn editor_command(editor: &str, path: &Path, line: usize) -> Command {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    let mut command = Command::new(program);
+    command.args(words);
+    let name = Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path = path.display();
+    match name.as_str() {
+        "code" | "codium" => command.args(["--goto".to_string(), format!("{path}:{line}")]),
+        "subl" | "hx" | "zed" => command.arg(format!("{path}:{line}")),
+        _ => command.args([format!("+{line}"), path.to_string()]),
+    };
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_words;
+
+    #[test]
+    fn test_choose_editor() {
+        assert_eq!(choose_editor(Some("nvim"), Some("nano")), "nvim");
+        assert_eq!(choose_editor(None, Some("nano")), "nano");
+        assert_eq!(choose_editor(Some(" "), Some("nano")), "nano");
+        assert_eq!(choose_editor(None, None), "vi");
+    }
+
+    #[test]
+    fn test_editor_command() {
+        let words =
+            |editor| command_words(&editor_command(editor, Path::new("/work/deploy.sh"), 12));
+        assert_eq!(words("vim"), vec!["vim", "+12", "/work/deploy.sh"]);
+        assert_eq!(
+            words("/usr/bin/emacs -nw"),
+            vec!["/usr/bin/emacs", "-nw", "+12", "/work/deploy.sh"]
+        );
+        assert_eq!(
+            words("code --wait"),
+            vec!["code", "--wait", "--goto", "/work/deploy.sh:12"]
+        );
+        assert_eq!(words("hx"), vec!["hx", "/work/deploy.sh:12"]);
+    }
+
+    #[test]
+    fn test_edit_fails_with_the_editor() {
+        let status = |program: &str| editor_command(program, Path::new("/dev/null"), 1).status();
+        assert!(status("true").unwrap().success());
+        assert!(!status("false").unwrap().success());
+        assert!(status("not-a-real-editor").is_err());
+    }
+}
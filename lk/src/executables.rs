@@ -1,80 +1,337 @@
 /// Finds executables in the current directory.
-use crate::{ui::print_root_header};
+use crate::pager::page;
+use crate::script::Script;
+use crate::ui::{hyperlink, root_footer, root_header, PrintOptions};
+use anyhow::{Context, Result};
 use content_inspector::{inspect, ContentType};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use pad::{Alignment, PadStr};
-use pastel_colours::{DARK_GREEN_FG, RESET_FG};
+use pastel_colours::RESET_FG;
 use std::{
+    cmp::Reverse,
+    fmt::Write,
     fs::Permissions,
     io::Read,
     os::unix::fs::PermissionsExt,
-    path::{Component, Path, PathBuf},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 use walkdir::{DirEntry, WalkDir};
 
+/// What we ignore unless told otherwise. These are gitignore-style patterns.
+const DEFAULT_IGNORES: [&str; 12] = [
+    "target",
+    ".github",
+    ".vscode",
+    ".git",
+    "node_modules",
+    ".nvm",
+    ".Trash",
+    ".npm",
+    ".cache",
+    "Library",
+    ".cargo",
+    ".sock",
+];
+
 pub struct Executable {
     pub short_name: String,
+    /// The root we found the executable under.
+    pub root: String,
     pub path: PathBuf,
     pub absolute_path: PathBuf,
 }
 
+impl Executable {
+    /// An executable we already know the absolute path of, e.g. from the history, rather than
+    /// one we found under a root.
+    pub fn from_absolute_path(absolute_path: &Path) -> Self {
+        Executable {
+            short_name: absolute_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            root: absolute_path
+                .parent()
+                .unwrap_or(absolute_path)
+                .to_string_lossy()
+                .to_string(),
+            path: absolute_path.to_owned(),
+            absolute_path: absolute_path.to_owned(),
+        }
+    }
+
+    /// The executable's path, relative to the root we found it under.
+    pub fn relative_path(&self) -> &Path {
+        self.path.strip_prefix(&self.root).unwrap_or(&self.path)
+    }
+}
+
+/// What we found when looking up an executable by name.
+pub enum Lookup<'a> {
+    Found(&'a Executable),
+    /// More than one executable has the name.
+    Ambiguous(Vec<&'a Executable>),
+    Missing,
+}
+
+/// How to order the scripts we list, as passed to `--sort`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptOrder {
+    /// By file name.
+    Name,
+    /// By path, so scripts in the same directory are together.
+    Path,
+    /// Most recently modified first.
+    Recent,
+}
+
+impl FromStr for ScriptOrder {
+    type Err = String;
+
+    fn from_str(order: &str) -> std::result::Result<Self, Self::Err> {
+        match order {
+            "name" => Ok(ScriptOrder::Name),
+            "path" => Ok(ScriptOrder::Path),
+            "recent" => Ok(ScriptOrder::Recent),
+            _ => Err(format!("expected name, path or recent, not '{order}'")),
+        }
+    }
+}
+
+/// Decides whether a file is a script to look for functions in. `is_executable_script` is
+/// what lk uses unless told otherwise.
+pub type IsCandidate = dyn Fn(&DirEntry) -> bool;
+
 pub struct Executables {
-    // root: String,
+    /// The roots we searched, in the order they were given.
+    pub roots: Vec<String>,
     pub executables: Vec<Executable>,
 }
 
 impl Executables {
-    pub fn new(root: &str, ignores: &[PathBuf]) -> Self {
-        // TODO: Load this from .gitignore/other ignore files
-        let ignored = vec![
-            "target",
-            ".github",
-            ".vscode",
-            ".git",
-            "node_modules",
-            ".nvm",
-            ".Trash",
-            ".npm",
-            ".cache",
-            "Library",
-            ".cargo",
-            ".sock",
-        ];
-        let walker = WalkDir::new(root).into_iter();
+    /// Finds executables under `root`. Whatever matches `ignores` is always ignored. After that
+    /// the patterns in `root`'s `.lkignore` take precedence over `config_ignores`, which
+    /// take precedence over `DEFAULT_IGNORES`.
+    pub fn new(root: &str, ignores: &Ignores, config_ignores: &[String]) -> Self {
+        Executables::scan(
+            root,
+            ignores,
+            config_ignores,
+            &is_executable_script,
+            false,
+            &mut |_| {},
+        )
+    }
+
+    /// Finds the files under `root` that `is_candidate` accepts, ignoring paths like `new`
+    /// does. Symlinks are skipped unless `follow_symlinks`. `on_scanned` is called with the
+    /// number of files and directories looked at so far, after looking at each one.
+    fn scan(
+        root: &str,
+        ignores: &Ignores,
+        config_ignores: &[String],
+        is_candidate: &IsCandidate,
+        follow_symlinks: bool,
+        on_scanned: &mut dyn FnMut(usize),
+    ) -> Self {
+        // TODO: Load this from .gitignore too
+        let patterns = ignore_patterns(root, config_ignores);
+        // Ignored paths are absolute, so we compare them with where entries really are.
+        let absolute_root = std::fs::canonicalize(root).unwrap_or_else(|_| PathBuf::from(root));
+        let walker = WalkDir::new(root).follow_links(follow_symlinks).into_iter();
         let mut executables: Vec<Executable> = Vec::new();
-        for result in walker.filter_entry(|e| !is_ignored(e.path(), &ignored, ignores)) {
+        let mut scanned = 0;
+        for result in walker
+            .filter_entry(|e| !is_ignored(e, &patterns, ignores, Path::new(root), &absolute_root))
+        {
+            scanned += 1;
+            on_scanned(scanned);
             let entry = match result {
                 Ok(entry) => entry,
+                // A symlink back up to a directory we're already in would have us going round
+                // in circles, so it's skipped.
+                Err(e) if e.loop_ancestor().is_some() => {
+                    log::warn!(
+                        "Not following symlink loop at {}",
+                        e.path().unwrap().display()
+                    );
+                    continue;
+                }
                 Err(e) => match e.path() {
                     Some(p) => {
                         log::warn!("Could not open path {}", p.to_string_lossy());
-                        continue
+                        continue;
                     }
                     None => panic!("Could not read dir !"),
                 },
             };
-            if should_include_file(&entry) {
+            if is_candidate(&entry) {
                 let path = entry.into_path();
                 let absolute_path = std::fs::canonicalize(&path).unwrap();
                 executables.push(Executable {
                     short_name: path.file_name().unwrap().to_string_lossy().to_string(),
+                    root: root.to_string(),
                     path,
                     absolute_path,
                 })
             }
         }
-        Self { executables }
+        Self {
+            roots: vec![root.to_string()],
+            executables,
+        }
     }
 
-    pub fn get(&self, name: &str) -> Option<&Executable> {
-        self.executables
+    /// Finds the files `is_candidate` accepts under each of `roots`, ignoring paths as `new`
+    /// does, and following symlinks if `follow_symlinks`. A file that's under more than one
+    /// root, or linked to more than once, is only included once, where it's found first.
+    /// `on_scanned` is called with the number of files and directories looked at so far,
+    /// across all the roots, so a slow scan can show its progress.
+    pub fn from_roots(
+        roots: &[String],
+        ignores: &Ignores,
+        config_ignores: &[String],
+        is_candidate: &IsCandidate,
+        follow_symlinks: bool,
+        mut on_scanned: impl FnMut(usize),
+    ) -> Self {
+        let mut searched: Vec<String> = Vec::new();
+        let mut executables: Vec<Executable> = Vec::new();
+        let mut scanned_before = 0;
+        for root in roots {
+            if searched.contains(root) {
+                continue;
+            }
+            searched.push(root.to_owned());
+            let mut scanned_here = 0;
+            let found = Executables::scan(
+                root,
+                ignores,
+                config_ignores,
+                is_candidate,
+                follow_symlinks,
+                &mut |scanned| {
+                    scanned_here = scanned;
+                    on_scanned(scanned_before + scanned);
+                },
+            );
+            scanned_before += scanned_here;
+            for executable in found.executables {
+                if !executables
+                    .iter()
+                    .any(|existing| existing.absolute_path == executable.absolute_path)
+                {
+                    executables.push(executable);
+                }
+            }
+        }
+        Self {
+            roots: searched,
+            executables,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.executables.is_empty()
+    }
+
+    /// Leaves out the executables `scripts` found no functions in, like libraries of
+    /// `_private` helpers, so they aren't listed. Executables that aren't in `scripts`, e.g.
+    /// because they couldn't be parsed, are kept.
+    pub fn hide_empty(&mut self, scripts: &[Script]) {
+        self.executables.retain(|executable| {
+            !scripts.iter().any(|script| {
+                script.absolute_path == executable.absolute_path && script.functions.is_empty()
+            })
+        });
+    }
+
+    /// Puts the executables in `order`. Otherwise they're in the order we found them.
+    pub fn sort(&mut self, order: ScriptOrder) {
+        match order {
+            ScriptOrder::Name => self
+                .executables
+                .sort_by(|a, b| a.short_name.cmp(&b.short_name)),
+            ScriptOrder::Path => self.executables.sort_by(|a, b| a.path.cmp(&b.path)),
+            // Scripts we can't get the time of go last.
+            ScriptOrder::Recent => self.executables.sort_by_cached_key(|executable| {
+                Reverse(
+                    std::fs::metadata(&executable.absolute_path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok(),
+                )
+            }),
+        }
+    }
+
+    /// Looks up the executable the user means by `name`. That's the one whose path, relative
+    /// to its root, is `name`, or failing that the one whose path ends with `name`, so
+    /// `a/deploy.sh` picks it out from `b/deploy.sh`, and `deploy.sh` works when there's
+    /// only one. Scripts in different directories can share a name, and then we can't
+    /// tell which one the user meant, unless they give its whole path, root and all. If
+    /// nothing matches then we try again without the scripts' `.sh` or `.bash` extensions,
+    /// so `deploy` finds `deploy.sh`.
+    pub fn get(&self, name: &str) -> Lookup<'_> {
+        let full_name = Path::new(name);
+        let exact: Vec<&Executable> = self
+            .executables
             .iter()
-            .find(|&executable| executable.short_name == name)
+            .filter(|&executable| {
+                executable.path == full_name || executable.absolute_path == full_name
+            })
+            .collect();
+        let mut candidates = if exact.is_empty() {
+            self.matching(full_name, |path| path.to_owned())
+        } else {
+            exact
+        };
+        if candidates.is_empty() {
+            candidates = self.matching(full_name, without_script_extension);
+        }
+        match candidates.len() {
+            0 => Lookup::Missing,
+            1 => Lookup::Found(candidates.remove(0)),
+            _ => Lookup::Ambiguous(candidates),
+        }
+    }
+
+    /// The executables whose path relative to their root, as `to_name` makes it, is `name`,
+    /// or failing that ends with `name`.
+    fn matching(&self, name: &Path, to_name: impl Fn(&Path) -> PathBuf) -> Vec<&Executable> {
+        let name = name.strip_prefix(".").unwrap_or(name);
+        let names: Vec<(&Executable, PathBuf)> = self
+            .executables
+            .iter()
+            .map(|executable| (executable, to_name(executable.relative_path())))
+            .collect();
+        let exact: Vec<&Executable> = names
+            .iter()
+            .filter(|(_, path)| path == name)
+            .map(|(executable, _)| *executable)
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        names
+            .iter()
+            .filter(|(_, path)| path.ends_with(name))
+            .map(|(executable, _)| *executable)
+            .collect()
     }
 
     /// Pretty-prints the executables we found on the path, so the
-    /// user can select one to run.
-    pub fn pretty_print(&self) {
-        print_root_header();
+    /// user can select one to run. They're grouped by the root they were found under, and
+    /// shown through the pager if there are too many to fit in the terminal.
+    pub fn pretty_print(&self, options: &PrintOptions) -> Result<()> {
+        page(&self.render(options), options.pager)
+    }
+
+    fn render(&self, options: &PrintOptions) -> String {
+        let absolute_paths = options.absolute_paths;
+        let mut output = String::new();
         // Get the longest executable name
         const INDENT: usize = 2;
         let padding = self
@@ -85,19 +342,67 @@ impl Executables {
             .short_name
             .len()
             + INDENT;
-        self.executables.iter().for_each(|executable| {
-            let path = executable.path.as_os_str().to_string_lossy().to_string();
-            // We'll pad right so everything aligns nicely.
-            let to_print = executable
-                .short_name
-                .pad_to_width_with_alignment(padding, Alignment::Right);
-            println!("{DARK_GREEN_FG}{to_print}{RESET_FG} - {path}");
-        });
+        for root in &self.roots {
+            let mut executables = self
+                .executables
+                .iter()
+                .filter(|executable| &executable.root == root)
+                .peekable();
+            if executables.peek().is_none() {
+                continue;
+            }
+            let root = if absolute_paths {
+                std::fs::canonicalize(root)
+                    .map(|root| root.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| root.to_owned())
+            } else {
+                root.to_owned()
+            };
+            writeln!(output, "{}", root_header(&root, &options.palette)).unwrap();
+            executables.for_each(|executable| {
+                let path = if absolute_paths {
+                    &executable.absolute_path
+                } else {
+                    &executable.path
+                };
+                let path = hyperlink(&path.to_string_lossy(), &executable.absolute_path);
+                // We'll pad right so everything aligns nicely.
+                let to_print = executable
+                    .short_name
+                    .pad_to_width_with_alignment(padding, Alignment::Right);
+                writeln!(
+                    output,
+                    "{}{to_print}{RESET_FG} - {path}",
+                    options.palette.script
+                )
+                .unwrap();
+            });
+        }
+        writeln!(output, "{}", root_footer(&options.palette)).unwrap();
+        output
+    }
+}
+
+/// `path` without its `.sh` or `.bash` extension, if it has one.
+fn without_script_extension(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(extension) if extension == "sh" || extension == "bash" => path.with_extension(""),
+        _ => path.to_owned(),
     }
 }
 
 /// Determines whether or not we should include this entry in our search results
-fn should_include_file(entry: &DirEntry) -> bool {
+/// Whether `entry` is an executable text file, which is what lk looks for functions in by
+/// default.
+pub fn is_executable_script(entry: &DirEntry) -> bool {
+    // This involves reading the first few bytes if the file, and for performance reasons
+    // we want to do this as little as possible. So it's the last thing we check.
+    is_executable_file(entry) && !is_binary(entry)
+}
+
+/// Whether `entry` is an executable file, text or not. This is quicker than
+/// `is_executable_script` because it doesn't read the file, but binaries will be included too.
+pub fn is_executable_file(entry: &DirEntry) -> bool {
     // We'll need to check file permissions
     let permissions = match entry.metadata() {
         Ok(metadata) => metadata.permissions(),
@@ -105,40 +410,163 @@ fn should_include_file(entry: &DirEntry) -> bool {
     };
 
     // If we don't have permissions to access the file we're not going to get very far.
-    if has_permissions(&permissions)
+    has_permissions(&permissions)
         // We're ignoring dirs, obviously
-        && !entry.file_type().is_dir() 
+        && !entry.file_type().is_dir()
         // We're including executables
-        && is_executable(&permissions) 
-        // We're ignoring symlinks (for now)
-        && !entry.path_is_symlink()
-    {
-        // This involves reading the first few bytes if the file, and for performance reasons
-        // we want to do this as little as possible. So it's the last thing we check.
-        if !is_binary(entry)
-        // We're ignoring binary files
-        {
-            return true;
-        }
-    }
-    false
+        && is_executable(&permissions)
+        // We're ignoring symlinks, unless we're following them, and then this is the target's
+        && !entry.file_type().is_symlink()
 }
 
 fn has_permissions(permissions: &Permissions) -> bool {
     // TODO: learn about octal representations of permissions.
     //       All I currently know is that we can't read this.
-    permissions.mode() != 33279 
+    permissions.mode() != 33279
 }
 
-fn is_ignored(p: &Path, ignored: &[&str], ignores: &[PathBuf]) -> bool {
-    ignores.iter().any(|s| p.starts_with(s))
-        || ignored.iter().any(|i| {
-            p.components().any(|c| match c {
-                Component::Normal(c) => *c == **i,
-                _ => false,
-            })
+/// Builds the gitignore-style patterns for `root`. Later patterns take precedence over
+/// earlier ones, so a `!target` in `.lkignore` will stop lk ignoring `target` directories.
+fn ignore_patterns(root: &str, config_ignores: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in DEFAULT_IGNORES {
+        builder
+            .add_line(None, pattern)
+            .expect("default ignores are valid patterns");
+    }
+    for pattern in config_ignores {
+        if let Err(err) = builder.add_line(None, pattern) {
+            log::warn!("Ignoring invalid pattern in config file: {pattern}. The error was: {err}");
+        }
+    }
+    let lkignore = Path::new(root).join(".lkignore");
+    if lkignore.exists() {
+        log::info!("Using ignore file at {}", lkignore.display());
+        if let Some(err) = builder.add(&lkignore) {
+            log::warn!("Problem reading {}: {err}", lkignore.display());
+        }
+    }
+    builder.build().unwrap_or_else(|err| {
+        log::error!(
+            "Unable to build ignore patterns, so only using --ignore. The error was: {err}"
+        );
+        Gitignore::empty()
+    })
+}
+
+/// What's passed to `--ignore`. Each one is either a path, or a glob pattern that's matched
+/// against paths relative to the root, e.g. `*.test.sh` or `**/vendor`.
+#[derive(Default)]
+pub struct Ignores {
+    /// Absolute paths, from `resolve_ignore`.
+    paths: Vec<PathBuf>,
+    globs: GlobSet,
+}
+
+impl Ignores {
+    pub fn new(ignores: &[PathBuf]) -> Result<Self> {
+        let mut paths = Vec::new();
+        let mut globs = GlobSetBuilder::new();
+        for ignore in ignores {
+            let pattern = ignore.to_string_lossy();
+            if pattern.contains(['*', '?', '[', '{']) {
+                let glob = Glob::new(&pattern)
+                    .with_context(|| format!("'{pattern}' isn't a valid --ignore pattern"))?;
+                globs.add(glob);
+            } else {
+                paths.push(resolve_ignore(ignore));
+            }
+        }
+        Ok(Self {
+            paths,
+            globs: globs.build()?,
         })
-        .unwrap_or(false)
+    }
+
+    /// Whether the entry at `absolute_path`, which is at `relative_path` under the root,
+    /// should be ignored.
+    fn is_match(&self, absolute_path: &Path, relative_path: &Path) -> bool {
+        self.paths
+            .iter()
+            .any(|path| absolute_path.starts_with(path))
+            || self.globs.is_match(relative_path)
+    }
+}
+
+/// Everything that's ignored under one root, for checking paths that weren't found by
+/// scanning it, e.g. ones that have just changed.
+pub struct RootIgnores<'a> {
+    root: PathBuf,
+    absolute_root: PathBuf,
+    patterns: Gitignore,
+    ignores: &'a Ignores,
+}
+
+impl<'a> RootIgnores<'a> {
+    pub fn new(root: &str, ignores: &'a Ignores, config_ignores: &[String]) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            absolute_root: std::fs::canonicalize(root).unwrap_or_else(|_| PathBuf::from(root)),
+            patterns: ignore_patterns(root, config_ignores),
+            ignores,
+        }
+    }
+
+    /// Whether scanning the root would ignore `path`, or skip a directory it's in. Paths that
+    /// aren't under the root aren't ignored.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Ok(relative_path) = path
+            .strip_prefix(&self.root)
+            .or_else(|_| path.strip_prefix(&self.absolute_root))
+        else {
+            return false;
+        };
+        relative_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| {
+                self.ignores
+                    .is_match(&self.absolute_root.join(ancestor), ancestor)
+            })
+            || self
+                .patterns
+                .matched_path_or_any_parents(self.root.join(relative_path), path.is_dir())
+                .is_ignore()
+    }
+}
+
+/// Turns a path passed to `--ignore` into an absolute one: `~` is expanded, and relative
+/// paths are relative to the current directory.
+fn resolve_ignore(path: &Path) -> PathBuf {
+    let path = match path.strip_prefix("~") {
+        Ok(rest) => match dirs::home_dir() {
+            Some(home_dir) => home_dir.join(rest),
+            None => path.to_path_buf(),
+        },
+        Err(_) => path.to_path_buf(),
+    };
+    let path = if path.is_relative() {
+        std::env::current_dir()
+            .map(|dir| dir.join(&path))
+            .unwrap_or(path)
+    } else {
+        path
+    };
+    // If the path exists then resolve any symlinks, so it matches what's under the roots.
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+fn is_ignored(
+    entry: &DirEntry,
+    patterns: &Gitignore,
+    ignores: &Ignores,
+    root: &Path,
+    absolute_root: &Path,
+) -> bool {
+    let p = entry.path();
+    let relative_path = p.strip_prefix(root).unwrap_or(p);
+    ignores.is_match(&absolute_root.join(relative_path), relative_path)
+        || patterns.matched(p, entry.file_type().is_dir()).is_ignore()
 }
 
 fn is_executable(permissions: &Permissions) -> bool {
@@ -172,7 +600,9 @@ fn is_binary(entry: &DirEntry) -> bool {
         Ok(_) => inspect(&buffer) == ContentType::BINARY,
         Err(err) => {
             if err.to_string().as_str() == "failed to fill whole buffer" {
-                log::debug!( "Found a tiny file and didn't read it all. Ignoring it. Path: {path_str}");
+                log::debug!(
+                    "Found a tiny file and didn't read it all. Ignoring it. Path: {path_str}"
+                );
             } else {
                 log::error!("Unable to read file: {path_str}. The error was: {err}");
             }
@@ -180,3 +610,489 @@ fn is_binary(entry: &DirEntry) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::ParseOptions;
+    use crate::theme::Palette;
+
+    /// Writes an executable script at `path`, under `dir`.
+    fn script(dir: &Path, path: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "#!/usr/bin/env bash\n\nfoo() {\n    echo foo\n}\n").unwrap();
+        std::fs::set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn short_names(executables: &Executables) -> Vec<String> {
+        let mut names: Vec<String> = executables
+            .executables
+            .iter()
+            .map(|e| e.short_name.to_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_empty_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        assert!(executables.is_empty());
+    }
+
+    #[test]
+    fn test_default_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "a.sh");
+        script(dir.path(), "target/b.sh");
+        script(dir.path(), "sub/node_modules/c.sh");
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        assert_eq!(short_names(&executables), vec!["a.sh"]);
+    }
+
+    #[test]
+    fn test_lkignore() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "a.sh");
+        script(dir.path(), "old/b.sh");
+        script(dir.path(), "c.test.sh");
+        script(dir.path(), "target/d.sh");
+        std::fs::write(dir.path().join(".lkignore"), "old/\n*.test.sh\n!target\n").unwrap();
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        assert_eq!(short_names(&executables), vec!["a.sh", "d.sh"]);
+    }
+
+    #[test]
+    fn test_lkignore_overrides_config() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "a.sh");
+        script(dir.path(), "b.sh");
+        script(dir.path(), "c.sh");
+        std::fs::write(dir.path().join(".lkignore"), "!b.sh\n").unwrap();
+        let config_ignores = vec!["b.sh".to_string(), "c.sh".to_string()];
+        let executables = Executables::new(
+            &dir.path().to_string_lossy(),
+            &Ignores::default(),
+            &config_ignores,
+        );
+        assert_eq!(short_names(&executables), vec!["a.sh", "b.sh"]);
+    }
+
+    #[test]
+    fn test_cli_ignores_override_lkignore() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "a.sh");
+        script(dir.path(), "b.sh");
+        std::fs::write(dir.path().join(".lkignore"), "!b.sh\n").unwrap();
+        let ignores = Ignores::new(&[dir.path().join("b.sh")]).unwrap();
+        let executables = Executables::new(&dir.path().to_string_lossy(), &ignores, &[]);
+        assert_eq!(short_names(&executables), vec!["a.sh"]);
+    }
+
+    #[test]
+    fn test_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "a.sh");
+        script(dir.path(), "scripts/b.sh");
+        script(dir.path(), "scripts/old/c.sh");
+        script(dir.path(), "other/d.sh");
+        // The root doesn't have to be absolute.
+        let root = format!("{}/./", dir.path().to_string_lossy());
+        let ignores =
+            Ignores::new(&[dir.path().join("scripts/old"), dir.path().join("other")]).unwrap();
+        let executables = Executables::new(&root, &ignores, &[]);
+        assert_eq!(short_names(&executables), vec!["a.sh", "b.sh"]);
+    }
+
+    #[test]
+    fn test_glob_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "a.sh");
+        script(dir.path(), "a.test.sh");
+        script(dir.path(), "scripts/b.test.sh");
+        script(dir.path(), "src/vendor/c.sh");
+        script(dir.path(), "vendor/d.sh");
+        script(dir.path(), "e.sh");
+        let ignores = Ignores::new(&[
+            PathBuf::from("*.test.sh"),
+            PathBuf::from("src/vend?r"),
+            PathBuf::from("{e,f}.sh"),
+        ])
+        .unwrap();
+        let executables = Executables::new(&dir.path().to_string_lossy(), &ignores, &[]);
+        assert_eq!(short_names(&executables), vec!["a.sh", "d.sh"]);
+    }
+
+    #[test]
+    fn test_root_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".lkignore"), "*.log\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("node_modules/pkg")).unwrap();
+        let root = dir.path().to_string_lossy();
+        let ignores = Ignores::new(&[PathBuf::from("*.tmp")]).unwrap();
+        let root_ignores = RootIgnores::new(&root, &ignores, &["vendor".to_string()]);
+        let is_ignored = |path: &str| root_ignores.is_ignored(&dir.path().join(path));
+        assert!(is_ignored("target/debug/lk"));
+        assert!(is_ignored("node_modules/pkg/index.js"));
+        assert!(is_ignored("vendor/lib.sh"));
+        assert!(is_ignored("lk.log"));
+        assert!(is_ignored("a/b.tmp"));
+        assert!(!is_ignored("deploy.sh"));
+        assert!(!is_ignored("bin/deploy.sh"));
+        assert!(!root_ignores.is_ignored(Path::new("/elsewhere/target")));
+    }
+
+    #[test]
+    fn test_invalid_glob_ignore() {
+        assert!(Ignores::new(&[PathBuf::from("[a")]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_ignore() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            resolve_ignore(Path::new("scripts/old")),
+            cwd.join("scripts/old")
+        );
+        assert_eq!(
+            resolve_ignore(Path::new("/not/a/real/dir")),
+            PathBuf::from("/not/a/real/dir")
+        );
+        assert_eq!(
+            resolve_ignore(Path::new("~/not-a-real-dir")),
+            dirs::home_dir().unwrap().join("not-a-real-dir")
+        );
+        // Only a leading ~ is expanded.
+        assert_eq!(
+            resolve_ignore(Path::new("not~/real")),
+            cwd.join("not~/real")
+        );
+    }
+
+    #[test]
+    fn test_from_roots() {
+        let project = tempfile::tempdir().unwrap();
+        let personal = tempfile::tempdir().unwrap();
+        script(project.path(), "a.sh");
+        script(project.path(), "bin/b.sh");
+        script(personal.path(), "c.sh");
+        let project_root = project.path().to_string_lossy().to_string();
+        let roots = vec![
+            project_root.to_owned(),
+            personal.path().to_string_lossy().to_string(),
+            // Already found under the project root, so its scripts aren't found twice.
+            format!("{project_root}/bin/../bin"),
+        ];
+        let mut scanned = 0;
+        let executables = Executables::from_roots(
+            &roots,
+            &Ignores::default(),
+            &[],
+            &is_executable_script,
+            false,
+            |n| scanned = n,
+        );
+        assert_eq!(short_names(&executables), vec!["a.sh", "b.sh", "c.sh"]);
+        // Each root, bin and every script, across all three roots.
+        assert_eq!(scanned, 8);
+        match executables.get("b.sh") {
+            Lookup::Found(b) => assert_eq!(b.root, project_root),
+            _ => panic!("b.sh should be found"),
+        }
+    }
+
+    #[test]
+    fn test_from_roots_with_the_same_script_name() {
+        let project = tempfile::tempdir().unwrap();
+        let library = tempfile::tempdir().unwrap();
+        script(project.path(), "deploy.sh");
+        script(library.path(), "deploy.sh");
+        script(library.path(), "notes.sh");
+        let library_root = library.path().to_string_lossy().to_string();
+        let roots = vec![
+            project.path().to_string_lossy().to_string(),
+            library_root.to_owned(),
+        ];
+        let executables = Executables::from_roots(
+            &roots,
+            &Ignores::default(),
+            &[],
+            &is_executable_script,
+            false,
+            |_| {},
+        );
+        assert_eq!(
+            short_names(&executables),
+            vec!["deploy.sh", "deploy.sh", "notes.sh"]
+        );
+        assert!(matches!(
+            executables.get("deploy.sh"),
+            Lookup::Ambiguous(candidates) if candidates.len() == 2
+        ));
+        match executables.get(&format!("{library_root}/deploy.sh")) {
+            Lookup::Found(deploy) => assert_eq!(deploy.root, library_root),
+            _ => panic!("the library's deploy.sh should be found by its path"),
+        }
+    }
+
+    #[test]
+    fn test_get_ambiguous_name() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "api/deploy.sh");
+        script(dir.path(), "web/deploy.sh");
+        script(dir.path(), "build.sh");
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        match executables.get("deploy.sh") {
+            Lookup::Ambiguous(candidates) => {
+                let mut paths: Vec<PathBuf> = candidates
+                    .iter()
+                    .map(|executable| executable.path.strip_prefix(dir.path()).unwrap().to_owned())
+                    .collect();
+                paths.sort();
+                assert_eq!(
+                    paths,
+                    vec![
+                        PathBuf::from("api/deploy.sh"),
+                        PathBuf::from("web/deploy.sh")
+                    ]
+                );
+            }
+            _ => panic!("deploy.sh should be ambiguous"),
+        }
+        assert!(matches!(executables.get("build.sh"), Lookup::Found(_)));
+        assert!(matches!(executables.get("nope.sh"), Lookup::Missing));
+    }
+
+    #[test]
+    fn test_get_without_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "deploy.sh");
+        script(dir.path(), "infra/build.bash");
+        script(dir.path(), "notes.txt");
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        let found = |name| match executables.get(name) {
+            Lookup::Found(executable) => executable.relative_path().to_owned(),
+            _ => panic!("{name} should be found"),
+        };
+        assert_eq!(found("deploy"), PathBuf::from("deploy.sh"));
+        assert_eq!(found("build"), PathBuf::from("infra/build.bash"));
+        assert_eq!(found("infra/build"), PathBuf::from("infra/build.bash"));
+        // Only script extensions are left off.
+        assert!(matches!(executables.get("notes"), Lookup::Missing));
+    }
+
+    #[test]
+    fn test_get_without_extension_ambiguous() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "deploy.sh");
+        script(dir.path(), "deploy.bash");
+        script(dir.path(), "build.sh");
+        script(dir.path(), "build");
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        match executables.get("deploy") {
+            Lookup::Ambiguous(candidates) => {
+                let mut names: Vec<&str> = candidates
+                    .iter()
+                    .map(|executable| executable.short_name.as_str())
+                    .collect();
+                names.sort();
+                assert_eq!(names, vec!["deploy.bash", "deploy.sh"]);
+            }
+            _ => panic!("deploy should be ambiguous"),
+        }
+        // A script that's called exactly that wins.
+        match executables.get("build") {
+            Lookup::Found(executable) => assert_eq!(executable.short_name, "build"),
+            _ => panic!("build should be found"),
+        }
+    }
+
+    #[test]
+    fn test_get_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "api/deploy.sh");
+        script(dir.path(), "web/deploy.sh");
+        script(dir.path(), "web/old/deploy.sh");
+        let executables = Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        let found = |name| match executables.get(name) {
+            Lookup::Found(executable) => executable.relative_path().to_owned(),
+            _ => panic!("{name} should be found"),
+        };
+        assert_eq!(found("api/deploy.sh"), PathBuf::from("api/deploy.sh"));
+        assert_eq!(found("./api/deploy.sh"), PathBuf::from("api/deploy.sh"));
+        // The whole relative path wins over another script it's a suffix of.
+        assert_eq!(found("web/deploy.sh"), PathBuf::from("web/deploy.sh"));
+        assert_eq!(found("old/deploy.sh"), PathBuf::from("web/old/deploy.sh"));
+        // Suffixes are matched a directory at a time.
+        assert!(matches!(executables.get("i/deploy.sh"), Lookup::Missing));
+        assert!(matches!(
+            executables.get("deploy.sh"),
+            Lookup::Ambiguous(candidates) if candidates.len() == 3
+        ));
+    }
+
+    #[test]
+    fn test_custom_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "deploy.sh");
+        script(dir.path(), "deploy.py");
+        // Not executable, but it's still a .sh.
+        std::fs::write(dir.path().join("notes.sh"), "#!/usr/bin/env bash\n").unwrap();
+        let is_shell_script = |entry: &DirEntry| {
+            entry.file_type().is_file()
+                && entry
+                    .path()
+                    .extension()
+                    .is_some_and(|extension| extension == "sh")
+        };
+        let executables = Executables::from_roots(
+            &[dir.path().to_string_lossy().to_string()],
+            &Ignores::default(),
+            &[],
+            &is_shell_script,
+            false,
+            |_| {},
+        );
+        assert_eq!(short_names(&executables), vec!["deploy.sh", "notes.sh"]);
+    }
+
+    #[test]
+    fn test_binary_check() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "deploy.sh");
+        let binary = dir.path().join("tool");
+        std::fs::write(&binary, [0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0]).unwrap();
+        std::fs::set_permissions(&binary, Permissions::from_mode(0o755)).unwrap();
+        let scan = |is_candidate: &IsCandidate| {
+            short_names(&Executables::from_roots(
+                &[dir.path().to_string_lossy().to_string()],
+                &Ignores::default(),
+                &[],
+                is_candidate,
+                false,
+                |_| {},
+            ))
+        };
+        assert_eq!(scan(&is_executable_script), vec!["deploy.sh"]);
+        assert_eq!(scan(&is_executable_file), vec!["deploy.sh", "tool"]);
+    }
+
+    #[test]
+    fn test_hide_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "deploy.sh");
+        let library = dir.path().join("library.sh");
+        std::fs::write(
+            &library,
+            "#!/usr/bin/env bash\n\n_helper() {\n    true\n}\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&library, Permissions::from_mode(0o755)).unwrap();
+        let mut executables =
+            Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        let scripts: Vec<Script> = executables
+            .executables
+            .iter()
+            .map(|executable| Script::new(executable, &ParseOptions::default()).unwrap())
+            .collect();
+        assert_eq!(short_names(&executables), vec!["deploy.sh", "library.sh"]);
+        executables.hide_empty(&scripts);
+        assert_eq!(short_names(&executables), vec!["deploy.sh"]);
+    }
+
+    #[test]
+    fn test_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        let modified = |path: &str, secs: u64| {
+            std::fs::File::options()
+                .write(true)
+                .open(dir.path().join(path))
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+                .unwrap();
+        };
+        for (path, secs) in [("b.sh", 20), ("a/c.sh", 10), ("z/a.sh", 30)] {
+            script(dir.path(), path);
+            modified(path, secs);
+        }
+        let mut executables =
+            Executables::new(&dir.path().to_string_lossy(), &Ignores::default(), &[]);
+        let mut sorted = |order| {
+            executables.sort(order);
+            executables
+                .executables
+                .iter()
+                .map(|executable| executable.relative_path().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(sorted(ScriptOrder::Name), vec!["z/a.sh", "b.sh", "a/c.sh"]);
+        assert_eq!(sorted(ScriptOrder::Path), vec!["a/c.sh", "b.sh", "z/a.sh"]);
+        assert_eq!(
+            sorted(ScriptOrder::Recent),
+            vec!["z/a.sh", "b.sh", "a/c.sh"]
+        );
+    }
+
+    #[test]
+    fn test_render_uses_the_palette() {
+        let dir = tempfile::tempdir().unwrap();
+        script(dir.path(), "deploy.sh");
+        let root = dir.path().to_string_lossy();
+        let executables = Executables::new(&root, &Ignores::default(), &[]);
+        let options = PrintOptions {
+            palette: Palette {
+                function: "<function>".to_string(),
+                script: "<script>".to_string(),
+                banner: "<banner>".to_string(),
+                ..Palette::default()
+            },
+            ..PrintOptions::default()
+        };
+        let rendered = executables.render(&options);
+        assert!(rendered.starts_with(&format!("<banner>lk: {root}/")));
+        // The names are padded, so they line up.
+        assert!(rendered
+            .lines()
+            .any(|line| line.starts_with("<script>") && line.contains("deploy.sh")));
+        assert!(rendered.contains("Run <function>lk <script>"));
+    }
+
+    #[test]
+    fn test_parse_script_order() {
+        assert_eq!("recent".parse(), Ok(ScriptOrder::Recent));
+        assert!("size".parse::<ScriptOrder>().is_err());
+    }
+
+    #[test]
+    fn test_follow_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        script(elsewhere.path(), "deploy.sh");
+        std::os::unix::fs::symlink(
+            elsewhere.path().join("deploy.sh"),
+            root.path().join("deploy.sh"),
+        )
+        .unwrap();
+        // A loop back up to the root.
+        std::os::unix::fs::symlink(root.path(), root.path().join("loop")).unwrap();
+        let find = |follow_symlinks| {
+            Executables::from_roots(
+                &[root.path().to_string_lossy().to_string()],
+                &Ignores::default(),
+                &[],
+                &is_executable_script,
+                follow_symlinks,
+                |_| {},
+            )
+        };
+        assert!(find(false).is_empty());
+        let executables = find(true);
+        assert_eq!(short_names(&executables), vec!["deploy.sh"]);
+        assert_eq!(
+            executables.executables[0].absolute_path,
+            std::fs::canonicalize(elsewhere.path().join("deploy.sh")).unwrap()
+        );
+    }
+}
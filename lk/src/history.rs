@@ -0,0 +1,257 @@
+use anyhow::Result;
+/// Remembers which functions have been run, and when.
+use lk::script::{Function, Script};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A function that's been run.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// The absolute path of the script the function is in.
+    pub script: PathBuf,
+    pub function: String,
+    /// The params the function was last run with, so `lk --last` can run it the same way.
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// When the function was last run, in seconds since the unix epoch.
+    pub last_run: u64,
+    /// How many times the function has been run.
+    pub count: u32,
+}
+
+impl Entry {
+    pub fn is_for(&self, script: &Script, function: &Function) -> bool {
+        self.script == script.absolute_path && self.function == function.name
+    }
+}
+
+/// How much each run boosts a function's fuzzy score.
+const BOOST_PER_RUN: i64 = 1;
+/// Runs beyond this don't boost a function any further. Skim scores are typically in the
+/// tens, so the boost settles between close matches but can't beat a much better one.
+const MAX_BOOSTED_RUNS: u32 = 10;
+
+#[derive(Serialize, Deserialize, Default)]
+struct HistoryFile {
+    entries: Vec<Entry>,
+}
+
+pub struct History {
+    path: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+impl History {
+    /// Loads the history from `lk_dir`. If there isn't one yet, or it can't be read,
+    /// then the history starts empty.
+    pub fn load(lk_dir: &str) -> Self {
+        let path = PathBuf::from(format!("{lk_dir}/history.toml"));
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(history) => match toml::from_str::<HistoryFile>(&history) {
+                Ok(history) => history.entries,
+                Err(err) => {
+                    log::error!("Unable to parse history at {}: {err}", path.display());
+                    vec![]
+                }
+            },
+            Err(err) => {
+                log::info!("No history at {}: {err}", path.display());
+                vec![]
+            }
+        };
+        Self { path, entries }
+    }
+
+    /// Records that `function` has just been run with `params`, and saves the history.
+    pub fn record(
+        &mut self,
+        script: &Script,
+        function: &Function,
+        params: &[String],
+    ) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.is_for(script, function))
+        {
+            Some(entry) => {
+                entry.params = params.to_vec();
+                entry.last_run = now;
+                entry.count += 1;
+            }
+            None => self.entries.push(Entry {
+                script: script.absolute_path.to_owned(),
+                function: function.name.to_owned(),
+                params: params.to_vec(),
+                last_run: now,
+                count: 1,
+            }),
+        }
+        self.save()
+    }
+
+    /// The entries, most recently run first.
+    pub fn recent(&self) -> Vec<&Entry> {
+        let mut recent: Vec<&Entry> = self.entries.iter().collect();
+        recent.sort_by_key(|entry| Reverse(entry.last_run));
+        recent
+    }
+
+    /// The entry that was run most recently, if any.
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.iter().max_by_key(|entry| entry.last_run)
+    }
+
+    /// How much to boost `function`'s fuzzy score, based on how often it's been run.
+    pub fn boost(&self, script: &Script, function: &Function) -> i64 {
+        self.entries
+            .iter()
+            .find(|entry| entry.is_for(script, function))
+            .map_or(0, |entry| {
+                i64::from(entry.count.min(MAX_BOOSTED_RUNS)) * BOOST_PER_RUN
+            })
+    }
+
+    fn save(&self) -> Result<()> {
+        let history = HistoryFile {
+            entries: self.entries.to_owned(),
+        };
+        std::fs::write(&self.path, toml::to_string(&history)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(name: &str, functions: &[&str]) -> Script {
+        Script {
+            path: PathBuf::from(format!("./{name}")),
+            absolute_path: PathBuf::from(format!("/scripts/{name}")),
+            comment: vec![],
+            functions: functions
+                .iter()
+                .map(|name| Function {
+                    name: name.to_string(),
+                    line: 1,
+                    comment: vec![],
+                    body: vec![],
+                    group: None,
+                    aliases: vec![],
+                    shellopts: None,
+                    interpreter: None,
+                })
+                .collect(),
+            shebang: None,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let lk_dir = dir.path().to_string_lossy();
+        let deploy = script("deploy.sh", &["release", "rollback"]);
+
+        let mut history = History::load(&lk_dir);
+        assert!(history.entries.is_empty());
+        history.record(&deploy, &deploy.functions[0], &[]).unwrap();
+        history.record(&deploy, &deploy.functions[1], &[]).unwrap();
+        let params = vec!["dev".to_string()];
+        history
+            .record(&deploy, &deploy.functions[0], &params)
+            .unwrap();
+
+        let history = History::load(&lk_dir);
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.entries[0].function, "release");
+        assert_eq!(
+            history.entries[0].script,
+            PathBuf::from("/scripts/deploy.sh")
+        );
+        assert_eq!(history.entries[0].count, 2);
+        assert_eq!(history.entries[0].params, params);
+        assert_eq!(history.entries[1].count, 1);
+        assert!(history.entries[1].params.is_empty());
+    }
+
+    #[test]
+    fn test_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let deploy = script("deploy.sh", &["release", "rollback", "build"]);
+        let mut history = History::load(&dir.path().to_string_lossy());
+        for (function, last_run) in [("release", 20), ("rollback", 30), ("build", 10)] {
+            history.entries.push(Entry {
+                script: deploy.absolute_path.to_owned(),
+                function: function.to_string(),
+                params: vec![],
+                last_run,
+                count: 1,
+            });
+        }
+        let recent: Vec<&str> = history
+            .recent()
+            .iter()
+            .map(|entry| entry.function.as_str())
+            .collect();
+        assert_eq!(recent, vec!["rollback", "release", "build"]);
+        assert_eq!(history.last().unwrap().function, "rollback");
+    }
+
+    #[test]
+    fn test_last_without_history() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(History::load(&dir.path().to_string_lossy())
+            .last()
+            .is_none());
+    }
+
+    #[test]
+    fn test_history_without_params() {
+        // Histories from before params were recorded still load.
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("history.toml"),
+            "[[entries]]\nscript = \"/scripts/deploy.sh\"\nfunction = \"release\"\nlast_run = 10\ncount = 1\n",
+        )
+        .unwrap();
+        let history = History::load(&dir.path().to_string_lossy());
+        assert!(history.entries[0].params.is_empty());
+    }
+
+    #[test]
+    fn test_boost_is_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let deploy = script("deploy.sh", &["release", "rollback", "build"]);
+        let mut history = History::load(&dir.path().to_string_lossy());
+        for (function, count) in [("release", 3), ("rollback", 500)] {
+            history.entries.push(Entry {
+                script: deploy.absolute_path.to_owned(),
+                function: function.to_string(),
+                params: vec![],
+                last_run: 10,
+                count,
+            });
+        }
+        assert_eq!(history.boost(&deploy, &deploy.functions[0]), 3);
+        assert_eq!(
+            history.boost(&deploy, &deploy.functions[1]),
+            i64::from(MAX_BOOSTED_RUNS) * BOOST_PER_RUN
+        );
+        assert_eq!(history.boost(&deploy, &deploy.functions[2]), 0);
+    }
+
+    #[test]
+    fn test_unreadable_history_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("history.toml"), "not = [valid").unwrap();
+        assert!(History::load(&dir.path().to_string_lossy())
+            .entries
+            .is_empty());
+    }
+}
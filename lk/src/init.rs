@@ -0,0 +1,103 @@
+/// Writes starter scripts, for `lk init`, so new users have something of the right shape
+/// for lk to find.
+use anyhow::{bail, Context, Result};
+use std::fs::{OpenOptions, Permissions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// What `lk init` calls the script if it isn't given a name.
+pub const DEFAULT_NAME: &str = "tasks";
+
+/// The path `lk init name` writes to, which is `name` with a `.sh` extension, unless it
+/// already has `.sh` or `.bash`.
+pub fn script_path(name: &str) -> PathBuf {
+    if name.ends_with(".sh") || name.ends_with(".bash") {
+        PathBuf::from(name)
+    } else {
+        PathBuf::from(format!("{name}.sh"))
+    }
+}
+
+/// A script with a header comment and one documented function, the way lk likes them.
+fn template(file_name: &str) -> String {
+    format!(
+        "#!/usr/bin/env bash
+#
+# What the functions in {file_name} are for. lk shows this comment above them.
+
+# Says hello. lk shows this comment next to the function.
+# Run it with: lk {file_name} example
+example() {{
+    echo \"Hello from {file_name}!\"
+}}
+"
+    )
+}
+
+/// Writes a starter script called `name`, and makes it executable. It won't overwrite a
+/// file that's already there. Returns the script's path.
+pub fn init(name: &str) -> Result<PathBuf> {
+    let path = script_path(name);
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .with_context(|| format!("'{name}' isn't a file name"))?;
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            bail!("{} already exists, so it's been left alone", path.display())
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("Unable to create {}", path.display()))
+        }
+    };
+    file.write_all(template(&file_name).as_bytes())
+        .and_then(|_| file.set_permissions(Permissions::from_mode(0o755)))
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lk::executables::{Executables, Ignores};
+    use lk::script::{ParseOptions, Script};
+
+    #[test]
+    fn test_script_path() {
+        assert_eq!(script_path("deploy"), PathBuf::from("deploy.sh"));
+        assert_eq!(script_path("deploy.sh"), PathBuf::from("deploy.sh"));
+        assert_eq!(
+            script_path("bin/deploy.bash"),
+            PathBuf::from("bin/deploy.bash")
+        );
+    }
+
+    #[test]
+    fn test_init() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = dir.path().join("deploy");
+        let path = init(&name.to_string_lossy()).unwrap();
+        assert_eq!(path, dir.path().join("deploy.sh"));
+
+        // lk finds it straight away.
+        let root = dir.path().to_string_lossy();
+        let executables = Executables::new(&root, &Ignores::default(), &[]);
+        assert_eq!(executables.executables.len(), 1);
+        let script = Script::new(&executables.executables[0], &ParseOptions::default()).unwrap();
+        assert_eq!(script.comment.len(), 1);
+        assert_eq!(script.functions.len(), 1);
+        assert_eq!(script.functions[0].name, "example");
+        assert!(!script.functions[0].comment.is_empty());
+    }
+
+    #[test]
+    fn test_init_leaves_existing_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.sh");
+        std::fs::write(&path, "keep me").unwrap();
+        assert!(init(&path.to_string_lossy()).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    }
+}
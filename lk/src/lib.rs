@@ -0,0 +1,18 @@
+// The parts of lk that find, parse, run and edit scripts. main.rs is the command line on top of
+// them, and the benchmarks use them too.
+pub mod bash_file;
+pub mod editor;
+pub mod executables;
+pub mod pager;
+pub mod script;
+pub mod theme;
+pub mod ui;
+
+/// The program `command` runs and its args, as strings, so tests can compare commands.
+#[cfg(test)]
+fn command_words(command: &std::process::Command) -> Vec<String> {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|word| word.to_string_lossy().to_string())
+        .collect()
+}
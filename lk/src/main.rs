@@ -1,109 +1,547 @@
-mod bash_file;
 mod config;
-mod executables;
-// mod history;
-mod script;
+mod history;
+mod init;
 mod shells;
-mod ui;
+mod watch;
 
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use bash_file::BashFile;
-use executables::Executables;
-use fuzzy_finder::item::Item;
-use fuzzy_finder::FuzzyFinder;
+use anyhow::{bail, Result};
+use bash_file::{find_env_file, parse_env, BashFile, RunOptions, TimedOut};
+use executables::{
+    is_executable_file, is_executable_script, Executable, Executables, Ignores, IsCandidate,
+    Lookup, RootIgnores, ScriptOrder,
+};
+use fuzzy_finder::builder::FuzzyFinderBuilder;
+use fuzzy_finder::item::{Field, Item};
+use fuzzy_finder::{FuzzyFinder, Outcome};
+use lk::{bash_file, editor, executables, pager, script, theme, ui};
 use log::LevelFilter;
+use log4rs::append::console::{ConsoleAppender, Target};
 use log4rs::append::file::FileAppender;
+use log4rs::append::Append;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
-use pastel_colours::{GREEN_FG, RED_FG, RESET_FG};
+use pastel_colours::{GREEN_FG, ORANGE_FG, RED_FG, RESET_FG};
 use script::Function;
-use shells::UserShell;
+use shells::{PrintTarget, UserShell};
 use spinners::{Spinner, Spinners};
 use structopt::StructOpt;
-use tempfile::tempdir;
-use ui::{print_bad_function_name, print_bad_script_name};
+use termion::style::{Faint, NoFaint};
+use theme::Palette;
+use ui::{
+    confirm_add_function, print_ambiguous_script_name, print_bad_function_name,
+    print_bad_script_name, print_complete_header, print_edit_failed, print_examples,
+    print_function_added, print_function_failed, print_function_no_longer_in_script,
+    print_no_executables_found, print_no_functions_in_script_help, print_no_last_function,
+    print_no_match, print_no_recent_functions, print_script_created, print_script_error,
+    print_skipped_scripts, print_watching, prompt_for_function, ColorMode, PrintOptions,
+};
 
-// use crate::history::History;
-use crate::script::Script;
+use crate::history::History;
+use script::{
+    duplicate_functions, is_function_name, FunctionOrder, LabelFormat, LabelPart, ParseOptions,
+    Script, ScriptError,
+};
+
+/// How often the spinner shows how many files we've scanned.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The exit code when a function times out, the same as `timeout`'s.
+const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+/// How much matching a script's directory counts in fuzzy mode, as a percentage of matching its
+/// file name or a function's name. Lots of scripts share a directory, so it says less about them.
+const DIRECTORY_WEIGHT: i64 = 50;
+
+/// How many lines lk fuzzy finds over, unless told otherwise.
+const DEFAULT_LINES_TO_SHOW: i8 = 7;
+
+/// A few common ways to use lk, shown after the options in `--help`.
+const EXAMPLES: &str = "EXAMPLES:
+    lk                      Use your default mode, which starts as 'list'
+    lk deploy.sh            List the functions in deploy.sh
+    lk deploy.sh build      Run the build function in deploy.sh
+    lk deploy.sh build dev  Run it with params
+    lk run deploy.sh build  Run build, for use in other scripts
+    lk --chain deploy.sh build test
+                            Run build, then test, in the same shell
+    lk -f                   Fuzzy search all the functions
+    lk -f --edit            Edit the function you pick, then run it
+    lk init deploy          Write a starter script, deploy.sh, to add functions to
+    lk --default fuzzy      Make 'lk' fuzzy search from now on
+
+FUZZY FINDER KEYS:
+    up, ctrl-p              Select the match above
+    down, ctrl-n            Select the match below
+    page up, page down      Move the selection a page
+    home, end               Select the best match, or the last one
+    enter                   Run the selected function, or search a selected script's functions
+    tab                     Complete the search with the selected match, or mark it with --multi
+    ctrl-s                  Sort the matches alphabetically, or by how well they match
+    esc                     Search everything again, after searching one script, or quit
+    ctrl-c, ctrl-d          Quit
+
+SHELL WIDGET:
+    --print-to lets a key binding put the selected command on the prompt, like ctrl-r.
+    For zsh, add this to ~/.zshrc to fuzzy find with ctrl-k:
+
+    lk-widget() {
+        LBUFFER+=\"$(lk --fuzzy --print-to 3 3>&1 >/dev/tty </dev/tty)\"
+        zle reset-prompt
+    }
+    zle -N lk-widget
+    bindkey '^k' lk-widget
+
+Run 'lk --examples' for a longer guide.";
 
 /// Use lk to explore and execute scripts in your current directory,
 /// and in its sub-directories. lk offers two options: 'list' or 'fuzzy'.
 /// 'list' lets you explore your scripts and their functions in a
-/// hierarchical way. 'fuzzy' lets you do a fuzzy search over all the
-/// scripts and functions found by lk.
+/// hierarchical way: 'lk' lists the scripts, 'lk <script>' lists a
+/// script's functions, and 'lk <script> <function>' runs one. 'fuzzy'
+/// lets you do a fuzzy search over all the scripts and functions found
+/// by lk. The 'run', 'list' and 'fuzzy' subcommands do the same, but
+/// say which you mean.
 #[derive(StructOpt)]
+#[structopt(after_help = EXAMPLES)]
 struct Cli {
-    /// Set the default mode: fuzzy or list
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    /// Set the default mode: fuzzy or list. This is saved, so plain 'lk' uses it from then on.
+    /// Short for --set default_mode=<mode>
     #[structopt(long, short)]
     default: Option<String>,
+    /// Change a setting in lk.toml, e.g. --set lines_to_show=10. Values are TOML, e.g.
+    /// --set 'ignore=["vendor/"]', or strings
+    #[structopt(long, number_of_values = 1, parse(try_from_str = config::parse_setting))]
+    set: Vec<(String, String)>,
+    /// Print a guide to using lk, with examples.
+    #[structopt(long)]
+    examples: bool,
     /// Fuzzy search for available scripts and functions.
     #[structopt(long, short)]
     fuzzy: bool,
     /// List available scripts and functions.
     #[structopt(long, short)]
     list: bool,
+    /// Fuzzy search for functions, marking the ones to run with tab, and then run them in the
+    /// order they were marked, stopping at the first that fails
+    #[structopt(long, conflicts_with_all = &["print-to", "recent", "script"])]
+    multi: bool,
+    /// Fuzzy search the functions you've run recently, most recent first.
+    #[structopt(long)]
+    recent: bool,
+    /// Run the function you ran last again, with the same params. 'lk -' does the same.
+    #[structopt(long)]
+    last: bool,
+    /// Fuzzy search the lines read from stdin, rather than scripts, and print the selected one,
+    /// e.g. git branch | lk --stdin. Exits with 1 if nothing's selected.
+    #[structopt(long)]
+    stdin: bool,
     /// Optional: the name of a script to explore or use
     script: Option<String>,
     /// Optional: the name of the function to run.
     function: Option<String>,
-    /// Optional: paths to ignore in the search
+    /// Optional: paths to ignore in the search, or glob patterns like '*.test.sh'
     #[structopt(long, short)]
     ignore: Vec<PathBuf>,
-    /// Number of lines to show in fuzzy search
-    #[structopt(long, short = "n", default_value = "7")]
-    number: i8,
+    /// Optional: more directories to search for scripts, as well as the current one
+    #[structopt(long, number_of_values = 1)]
+    path: Vec<PathBuf>,
+    /// Look for scripts through symlinks too. Links back to a directory that's already being
+    /// searched are skipped
+    #[structopt(long)]
+    follow_symlinks: bool,
+    /// Find scripts by their executable bit alone, without reading each one to check it isn't
+    /// a binary. Scanning is quicker, but any executable binaries are listed too
+    #[structopt(long)]
+    no_binary_check: bool,
+    /// List scripts that don't have any functions too, e.g. libraries of _private helpers,
+    /// even if hide_empty_scripts is set, as it is by default
+    #[structopt(long)]
+    show_empty_scripts: bool,
+    /// Check that every script can be read and has at least one function,
+    /// exiting with a non-zero code if not, or if there aren't any scripts.
+    #[structopt(long)]
+    check: bool,
+    /// Look for problems with the scripts, like functions with the same name in different
+    /// scripts, exiting with a non-zero code if there are any.
+    #[structopt(long, alias = "doctor")]
+    lint: bool,
+    /// Print how many scripts and functions lk finds, e.g. '12 scripts, 87 functions', rather
+    /// than listing them. Scripts that wouldn't be listed aren't counted
+    #[structopt(long)]
+    count: bool,
+    /// Optional: kill the function if it's still running after this many seconds
+    #[structopt(long)]
+    timeout: Option<u64>,
+    /// Print the absolute path of the script, or path:line of the function, rather than
+    /// running it, e.g. for an editor to jump to. Prints nothing and exits with 1 if it isn't
+    /// found
+    #[structopt(long, requires = "script", conflicts_with_all = &["fuzzy", "multi", "loop", "all-functions", "watch"])]
+    print_path: bool,
+    /// Print just the names of the script's functions, one per line, e.g. for shell completion.
+    /// Exits with 1 if the script isn't found
+    #[structopt(long, requires = "script", conflicts_with_all = &["function", "print-path", "fuzzy", "multi", "loop", "all-functions", "watch"])]
+    functions_only: bool,
+    /// Print the bash file lk would run the function with, rather than running it. It sources
+    /// the script from the script's directory, then calls the function.
+    #[structopt(long)]
+    show_runner: bool,
+    /// Open the function in $VISUAL or $EDITOR, at the line it starts on, then run it as it is
+    /// once you've quit the editor. It isn't run if the editor exits with an error
+    #[structopt(long)]
+    edit: bool,
+    /// Run every function in the script, in order, stopping at the first that fails. Each
+    /// function runs in its own shell, so variables one sets aren't seen by the next
+    #[structopt(long, requires = "script", conflicts_with = "function")]
+    all_functions: bool,
+    /// After running a function, list the script's functions again and ask which to run next,
+    /// until you quit. lk then exits with the last function's exit code
+    #[structopt(
+        long = "loop",
+        requires = "script",
+        conflicts_with_all = &["function", "all-functions", "watch"]
+    )]
+    loop_: bool,
+    /// With --all-functions, run the rest of the functions even if one fails
+    #[structopt(long, requires = "all-functions")]
+    continue_on_error: bool,
+    /// Run the words after the function as functions too, one after the other in the same
+    /// shell, rather than passing them to it, e.g. 'lk --chain deploy.sh build test'. It stops
+    /// at the first that fails
+    #[structopt(long, requires = "function")]
+    chain: bool,
+    /// Don't print the banner before the function's output, or the progress of the search for
+    /// scripts, so the function's output is all that's printed. Errors go to stderr
+    #[structopt(long, short)]
+    quiet: bool,
+    /// Optional: a file of KEY=VALUE lines to source before the script, so the function sees
+    /// them [default: .env, if there is one and source_dotenv is on]
+    #[structopt(long)]
+    env_file: Option<PathBuf>,
+    /// Export every variable the script sets when it's sourced, like 'set -a', so the programs
+    /// the function runs see them too. That includes any secrets the script sets
+    #[structopt(long)]
+    autoexport: bool,
+    /// Optional: set an environment variable for the function, e.g. --env ENV=staging
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_env))]
+    env: Vec<(String, String)>,
+    /// List the scripts, or a script's functions, again whenever they change, until ctrl-c.
+    #[structopt(long)]
+    watch: bool,
+    /// Print the list of scripts straight to the terminal, rather than through $PAGER when
+    /// it's too long to fit.
+    #[structopt(long)]
+    no_pager: bool,
+    /// List any scripts that were skipped because they couldn't be read or parsed, and why.
+    #[structopt(long, short)]
+    verbose: bool,
+    /// How much lk logs: off, error, warn, info, debug or trace. Logs go to lk.log in its
+    /// config directory, or to stderr with --log-stderr
+    #[structopt(
+        long,
+        default_value = "info",
+        possible_values = &["off", "error", "warn", "info", "debug", "trace"]
+    )]
+    log_level: LevelFilter,
+    /// Log to stderr rather than lk.log, e.g. to see what lk does while it does it
+    #[structopt(long)]
+    log_stderr: bool,
+    /// How to order a script's functions when they're listed: none, which keeps the script's
+    /// order, or alpha [default: none, or sort_functions in lk.toml]
+    #[structopt(long, possible_values = &["none", "alpha"])]
+    sort_functions: Option<FunctionOrder>,
+    /// How to order the list of scripts: name, path, or recent, which puts the most recently
+    /// changed first. They're in the order they're found otherwise
+    #[structopt(long, possible_values = &["name", "path", "recent"])]
+    sort: Option<ScriptOrder>,
+    /// Show absolute paths to scripts, rather than paths relative to the current directory.
+    #[structopt(long)]
+    absolute_paths: bool,
+    /// Leave the directory that all the scripts are in out of the fuzzy finder's labels, e.g.
+    /// './deploy.sh - build' rather than './infra/aws/deploy.sh - build'
+    #[structopt(long)]
+    strip_common_prefix: bool,
+    /// When to use colours: auto, always or never. 'auto' uses them when writing to a terminal,
+    /// unless NO_COLOR is set
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "always", "never"])]
+    color: ColorMode,
+    /// Number of lines to show in fuzzy search [default: 7, or `lines_to_show` in lk.toml]
+    #[structopt(long, short = "n")]
+    number: Option<i8>,
+    /// Optional: in fuzzy mode, write the selected function's lk command to this path or file
+    /// descriptor, rather than running it. For shell widgets, see below
+    #[structopt(long)]
+    print_to: Option<PrintTarget>,
+    /// With --print-to, write the label of the function you select, as the fuzzy finder showed
+    /// it, e.g. 'infra/deploy.sh - build', rather than its lk command
+    #[structopt(long, requires = "print-to")]
+    raw: bool,
     /// Optional: params for the function. We're not processing them yet (e.g. validating) but
     /// they need to be permitted as a param to lk.
     #[allow(dead_code)]
     params: Vec<String>,
+    /// The name of the script to write, for `lk init`.
+    #[structopt(skip)]
+    init: Option<String>,
+    /// What's been typed of the function name so far, for `lk __complete`.
+    #[structopt(skip)]
+    complete: Option<String>,
 }
 
-fn main() -> Result<()> {
-    let lk_dir = match dirs::home_dir() {
-        // Use a dir in ~/.config like a good human, but then store logs in it lol.
-        Some(home_dir) => format!("{}/.config/lk", home_dir.to_string_lossy()),
-        // If we don't have access to the home_dir for some reason then just use a temp dir.
-        None => {
-            println!("Unable to access your home directory. Using a temporary directory instead.");
-            tempdir().unwrap().into_path().to_string_lossy().to_string()
+/// Explicit versions of what `lk`'s positional args do, for scripting against lk. lk's options
+/// go before the subcommand, e.g. `lk --quiet run deploy.sh build`.
+#[derive(StructOpt)]
+enum Command {
+    /// Run a function, e.g. 'lk run deploy.sh build dev'. Exits with 1 if the script or
+    /// function isn't found
+    Run {
+        /// The name of the script the function is in
+        script: String,
+        /// The name of the function to run, or its number
+        function: String,
+        /// Params for the function
+        params: Vec<String>,
+    },
+    /// List the scripts, or a script's functions, like --list
+    List {
+        /// Optional: the name of a script to list the functions of
+        script: Option<String>,
+    },
+    /// Fuzzy search for scripts and functions, like --fuzzy
+    Fuzzy,
+    /// Write a starter script, with a header comment and an example function, for lk to find.
+    /// It won't overwrite a file that's already there
+    Init {
+        /// What to call the script. '.sh' is added unless it ends in '.sh' or '.bash'
+        #[structopt(default_value = init::DEFAULT_NAME)]
+        name: String,
+    },
+    /// Print the script's functions that start with `partial`, one per line, for the shell's
+    /// tab-completion to offer. See the README for how to hook it up
+    #[structopt(name = "__complete", setting = structopt::clap::AppSettings::Hidden)]
+    Complete {
+        /// The name of the script the function is in
+        script: String,
+        /// What's been typed of the function name so far
+        #[structopt(default_value = "")]
+        partial: String,
+    },
+}
+
+impl Cli {
+    /// Puts the subcommand's args where the positional ones would be, so that both go
+    /// through the same logic.
+    fn with_command(mut self) -> Self {
+        match self.command.take() {
+            Some(Command::Run {
+                script,
+                function,
+                params,
+            }) => {
+                self.script = Some(script);
+                self.function = Some(function);
+                self.params = params;
+            }
+            Some(Command::List { script }) => {
+                self.list = true;
+                self.script = script;
+            }
+            Some(Command::Fuzzy) => self.fuzzy = true,
+            Some(Command::Init { name }) => self.init = Some(name),
+            Some(Command::Complete { script, partial }) => {
+                self.script = Some(script);
+                self.complete = Some(partial);
+            }
+            None => {}
         }
-    };
+        self
+    }
+}
+
+fn main() -> Result<()> {
+    let lk_dir = config::lk_dir(dirs::home_dir());
 
-    let mut config_file = config::ConfigFile::new(&lk_dir, "lk.toml");
+    let mut config_file = config::ConfigFile::new(&lk_dir, "lk.toml")?;
+    let mut history = History::load(&lk_dir);
 
-    let args = Cli::from_args();
+    let args = Cli::from_args().with_command();
+    // Every colour lk prints comes from pastel_colours, so this turns them all on or off.
+    // `--stdin` draws on the terminal even when its output is piped.
+    pastel_colours::set_enabled(args.color.use_colors(args.stdin || pager::is_tty()));
+    // Links are escape codes too, so they're left out whenever colours are, or we're piped.
+    ui::set_hyperlinks(
+        config_file.config.hyperlinks
+            && pastel_colours::enabled()
+            && pager::is_tty()
+            && ui::terminal_supports_hyperlinks(),
+    );
 
-    let log_file_path = format!("{lk_dir}/lk.log");
-    let log_file = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{l} - {m}\n")))
-        .build(&log_file_path)?;
+    let encoder = Box::new(PatternEncoder::new("{l} - {m}\n"));
+    let log: Box<dyn Append> = if args.log_stderr {
+        Box::new(
+            ConsoleAppender::builder()
+                .target(Target::Stderr)
+                .encoder(encoder)
+                .build(),
+        )
+    } else {
+        let log_file_path = format!("{lk_dir}/lk.log");
+        Box::new(
+            FileAppender::builder()
+                .encoder(encoder)
+                .build(&log_file_path)?,
+        )
+    };
 
     let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(log_file)))
-        .build(Root::builder().appender("logfile").build(LevelFilter::Info))?;
+        .appender(Appender::builder().build("log", log))
+        .build(Root::builder().appender("log").build(args.log_level))?;
     log4rs::init_config(config)?;
 
     log::info!("\n\nStarting lk...");
 
-    let sp = Spinner::new(&Spinners::Line, "".to_string());
-    let executables = Executables::new(
-        ".",
-        &args
-            .ignore
+    if args.examples {
+        // There's no need to look for scripts first.
+        print_examples();
+        return Ok(());
+    }
+    if let Some(name) = &args.init {
+        print_script_created(&init::init(name)?);
+        return Ok(());
+    }
+
+    // Variables from the config come first, so the ones passed to --env are set last and win.
+    let run_options = RunOptions {
+        confirm: false,
+        timeout: args.timeout.map(Duration::from_secs),
+        env: config_file
+            .config
+            .env
             .iter()
-            .map(|p| PathBuf::from(".").join(p))
-            .collect::<Vec<_>>(),
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .chain(args.env.iter().cloned())
+            .collect(),
+        env_file: find_env_file(args.env_file.as_deref(), config_file.config.source_dotenv),
+        autoexport: args.autoexport,
+        show_runner: args.show_runner,
+    };
+    let print_options = PrintOptions {
+        pager: !args.no_pager,
+        absolute_paths: args.absolute_paths,
+        function_order: args
+            .sort_functions
+            .unwrap_or(config_file.config.sort_functions),
+        strip_common_prefix: args.strip_common_prefix || config_file.config.strip_common_prefix,
+        hide_empty_scripts: config_file.config.hide_empty_scripts && !args.show_empty_scripts,
+        // Only the path gets printed, so the search's progress mustn't show either.
+        quiet: args.quiet
+            || args.print_path
+            || args.functions_only
+            || args.count
+            || args.complete.is_some()
+            || config_file.config.quiet,
+        // Resolved now colours are on or off, so every part of lk agrees on them.
+        palette: config_file.config.palette(),
+    };
+    let options = Options {
+        run: run_options,
+        print: print_options,
+        parse: ParseOptions {
+            docstrings: config_file.config.parse_docstrings,
+        },
+        edit: args.edit,
+    };
+    let lines_to_show = args
+        .number
+        .or(config_file.lines_to_show())
+        .unwrap_or(DEFAULT_LINES_TO_SHOW);
+    if args.last || args.script.as_deref() == Some("-") {
+        // The history knows where the script is, so there's no need to look for it.
+        return run_last(&config_file.config, &mut history, &options);
+    }
+    if args.stdin {
+        // There's no need to look for scripts either.
+        if !fuzzy_stdin(
+            lines_to_show + 1,
+            &config_file.config,
+            &options.print.palette,
+        )? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let roots: Vec<String> = std::iter::once(".".to_string())
+        .chain(config_file.config.library_dirs())
+        .chain(args.path.iter().map(|p| p.to_string_lossy().to_string()))
+        .collect();
+    let ignores = Ignores::new(&args.ignore)?;
+    let is_candidate: &IsCandidate = if args.no_binary_check || config_file.config.skip_binary_check
+    {
+        &is_executable_file
+    } else {
+        &is_executable_script
+    };
+    // The spinner draws on stdout, so it'd end up in whatever we're piped to.
+    let sp = (!options.print.quiet && pager::is_tty())
+        .then(|| Spinner::new(&Spinners::Line, "".to_string()));
+    let mut last_progress = Instant::now();
+    let executables = Executables::from_roots(
+        &roots,
+        &ignores,
+        config_file.ignore(),
+        is_candidate,
+        args.follow_symlinks,
+        |scanned| {
+            // Updating the spinner for every file would slow the scan down.
+            if let Some(sp) = sp
+                .as_ref()
+                .filter(|_| last_progress.elapsed() >= PROGRESS_INTERVAL)
+            {
+                last_progress = Instant::now();
+                sp.message(format!("scanned {scanned} files…"));
+            }
+        },
     );
-    sp.stop();
+    if let Some(sp) = sp {
+        sp.stop();
+        // The spinner leaves its last frame behind, so clear it before anything else is printed.
+        print!("\r{}", termion::clear::CurrentLine);
+        std::io::stdout().flush()?;
+    }
 
-    let scripts: Vec<Script> = executables
+    let mut skipped: Vec<ScriptError> = Vec::new();
+    let mut scripts: Vec<Script> = executables
         .executables
         .iter()
-        .map(Script::new)
-        .filter_map(Result::ok)
+        .map(|executable| Script::new(executable, &options.parse))
+        .filter_map(|script| match script {
+            Ok(script) => Some(script),
+            Err(err) => {
+                log::warn!("Skipping script. {err}");
+                skipped.push(err);
+                None
+            }
+        })
         .collect();
+    if args.verbose {
+        print_skipped_scripts(&skipped);
+    }
+    // Scripts without functions aren't listed or fuzzy found, but can still be asked for by name.
+    let empty_scripts: Vec<Script> = if options.print.hide_empty_scripts {
+        let (empty, scripts_with_functions) = scripts
+            .into_iter()
+            .partition(|script| script.functions.is_empty());
+        scripts = scripts_with_functions;
+        empty
+    } else {
+        vec![]
+    };
 
     // Prints all scripts
     // scripts.iter().for_each(|script| {
@@ -112,109 +550,1197 @@ fn main() -> Result<()> {
     //         .iter()
     //         .for_each(|function| println!("{} - {}", script.file_name(), function.name))
     // });
-    if let Some(default) = args.default {
-        match default.as_str() {
-            "fuzzy" => {
-                println!("Setting default mode to {GREEN_FG}fuzzy{RESET_FG}");
-                config_file.config.default_mode = "fuzzy".to_string();
-                config_file.save();
-            }
-            "list" => {
-                println!("Setting default mode to {GREEN_FG}list{RESET_FG}");
-                config_file.config.default_mode = "list".to_string();
-                config_file.save();
-            }
-            _ => {
-                println!(
-                    "{RED_FG}Unknown default!{RESET_FG} Please specify either {GREEN_FG}fuzzy{RESET_FG} or {GREEN_FG}list{RESET_FG}. You can try out either using the {GREEN_FG}--fuzzy{RESET_FG} or {GREEN_FG}--list{RESET_FG} flags.",
-                );
+    let print_to = args.print_to.as_ref().map(|target| PrintTo {
+        target,
+        raw: args.raw,
+    });
+    // `--default` is short for `--set default_mode=...`.
+    let settings: Vec<(String, String)> = args
+        .default
+        .iter()
+        .map(|mode| ("default_mode".to_string(), mode.to_owned()))
+        .chain(args.set.iter().cloned())
+        .collect();
+    if !settings.is_empty() {
+        for (key, value) in settings {
+            if let Err(err) = config_file.config.set(&key, &value) {
+                eprintln!("{RED_FG}{err}{RESET_FG}");
+                std::process::exit(1);
             }
+            println!("Setting {key} to {GREEN_FG}{value}{RESET_FG}");
+        }
+        config_file.save()?;
+    } else if args.print_path {
+        if !print_path(&executables, &args, &options.parse) {
+            std::process::exit(1);
+        }
+    } else if args.functions_only {
+        if !print_function_names(&executables, &args, "", &options) {
+            std::process::exit(1);
         }
+    } else if let Some(partial) = &args.complete {
+        if !print_function_names(&executables, &args, partial, &options) {
+            std::process::exit(1);
+        }
+    } else if args.count {
+        print_count(&scripts);
+    } else if args.check {
+        if !check(&executables, &options.parse) {
+            std::process::exit(1);
+        }
+    } else if executables.is_empty() {
+        // There's nothing to list or fuzzy find, in either mode.
+        print_no_executables_found(&roots.join(", "));
+    } else if args.lint {
+        if !lint(&scripts) {
+            std::process::exit(1);
+        }
+    } else if args.recent {
+        recent(
+            &scripts,
+            lines_to_show + 1,
+            &config_file.config,
+            &mut history,
+            &options,
+            print_to,
+        )?
+    } else if args.multi {
+        fuzzy_multi(
+            &scripts,
+            lines_to_show + 1,
+            &config_file.config,
+            &mut history,
+            &options,
+        )?
     } else if args.fuzzy {
-        fuzzy(&scripts, args.number + 1)?
+        fuzzy(
+            &scripts,
+            lines_to_show + 1,
+            &config_file.config,
+            &mut history,
+            &options,
+            print_to,
+        )?
+    } else if args.watch {
+        watch_list(
+            &roots,
+            &ignores,
+            config_file.ignore(),
+            is_candidate,
+            &args,
+            &options,
+            &lk_dir,
+        )?
     } else if args.list || args.script.is_some() {
         // If the user is specifying --list OR if there's some value for script.
         // Any value there is implicitly take as --list.
-        list(executables, args)?
+        list(
+            executables,
+            &empty_scripts,
+            args,
+            &config_file.config,
+            &mut history,
+            &options,
+        )?
     } else {
         // Neither requested, so fall back on the default which will always exist.
-        match config_file.config.default_mode.as_str() {
-            "fuzzy" => fuzzy(&scripts, args.number + 1)?,
-            "list" => list(executables, args)?,
-            _ => panic!("No default mode set! Has there been a problem creating the config file?"),
+        match config_file.default_mode() {
+            "fuzzy" => fuzzy(
+                &scripts,
+                lines_to_show + 1,
+                &config_file.config,
+                &mut history,
+                &options,
+                print_to,
+            )?,
+            "list" => list(
+                executables,
+                &empty_scripts,
+                args,
+                &config_file.config,
+                &mut history,
+                &options,
+            )?,
+            // The config's modes are checked when it's loaded, so this shouldn't happen.
+            mode => bail!("'{mode}' isn't a mode. Use fuzzy or list"),
         }
     }
     Ok(())
 }
 
+/// Checks each executable can be parsed as a script with functions, printing one line per
+/// executable. Returns false if any can't, or there aren't any. Scripts that might not work
+/// when they're sourced, because of their shebang, are warned about too, but that's not a
+/// failure.
+fn check(executables: &Executables, options: &ParseOptions) -> bool {
+    if executables.is_empty() {
+        // Checking nothing shouldn't pass, e.g. if CI's run it in the wrong directory.
+        println!("{RED_FG}FAIL{RESET_FG} no scripts found to check");
+        return false;
+    }
+    let mut failures = 0;
+    for executable in &executables.executables {
+        let path = executable.path.to_string_lossy();
+        match Script::new(executable, options) {
+            Ok(script) if script.functions.is_empty() => {
+                failures += 1;
+                println!("{RED_FG}FAIL{RESET_FG} {path}: no functions");
+            }
+            Ok(script) => {
+                println!(
+                    "{GREEN_FG}OK{RESET_FG}   {path}: {} function(s)",
+                    script.functions.len()
+                );
+                if let Some(mismatch) = interpreter_mismatch(&script) {
+                    println!("{ORANGE_FG}WARN{RESET_FG} {mismatch}");
+                }
+            }
+            Err(err) => {
+                failures += 1;
+                println!("{RED_FG}FAIL{RESET_FG} {path}: {err}");
+            }
+        }
+    }
+    println!(
+        "{} script(s) checked, {failures} failed",
+        executables.executables.len()
+    );
+    failures == 0
+}
+
+/// Why the first of the script's functions that might not work when it's sourced might not.
+fn interpreter_mismatch(script: &Script) -> Option<String> {
+    script
+        .functions
+        .iter()
+        .find_map(|function| script.interpreter_mismatch(function.interpreter()))
+}
+
+/// Where `--print-to` writes the function the user selects in fuzzy mode, rather than running
+/// it, and whether that's its label, as passed to `--raw`, rather than its lk command.
+#[derive(Clone, Copy)]
+struct PrintTo<'a> {
+    target: &'a PrintTarget,
+    raw: bool,
+}
+
+/// How lk was asked to run functions, and to print everything else.
+#[derive(Clone)]
+struct Options {
+    run: RunOptions,
+    print: PrintOptions,
+    parse: ParseOptions,
+    /// Open the function in the user's editor first, and run it as it is once they're done.
+    edit: bool,
+}
+
+/// What the user can select in the fuzzy finder.
+#[derive(Clone, Copy)]
+enum Selection<'a> {
+    /// Selecting a script fuzzy finds over its functions.
+    Script(&'a Script),
+    Function(&'a Script, &'a Function),
+}
+
+impl Selection<'_> {
+    /// The comment that describes the selection. A function's comment is followed by the start
+    /// of its body, dimmed so it stands apart from the comment.
+    fn description(&self) -> Vec<String> {
+        match self {
+            Selection::Script(script) => script.comment.to_owned(),
+            Selection::Function(_, function) => {
+                let (faint, no_faint) = if pastel_colours::enabled() {
+                    (Faint.to_string(), NoFaint.to_string())
+                } else {
+                    (String::new(), String::new())
+                };
+                let body = function
+                    .body
+                    .iter()
+                    // Tabs would throw out the preview's alignment.
+                    .map(|line| format!("{faint}{}{no_faint}", line.replace('\t', "    ")));
+                function.description().cloned().chain(body).collect()
+            }
+        }
+    }
+}
+
+/// Prints how many scripts there are, and how many functions they have between them.
+fn print_count(scripts: &[Script]) {
+    let plural = |count: usize, noun: &str| match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    };
+    let functions = scripts.iter().map(|script| script.functions.len()).sum();
+    println!(
+        "{}, {}",
+        plural(scripts.len(), "script"),
+        plural(functions, "function")
+    );
+}
+
+/// Prints the function names that are defined in more than one script, and the scripts that
+/// define them. Returns false if there are any. Scripts that might not work when they're
+/// sourced, because of their shebang, are warned about too, but that's not a failure.
+fn lint(scripts: &[Script]) -> bool {
+    for script in scripts {
+        if let Some(mismatch) = interpreter_mismatch(script) {
+            println!("{ORANGE_FG}WARNING{RESET_FG} {mismatch}");
+        }
+    }
+    let duplicates = duplicate_functions(scripts);
+    for (function, scripts) in &duplicates {
+        let paths: Vec<String> = scripts
+            .iter()
+            .map(|script| script.path.to_string_lossy().to_string())
+            .collect();
+        println!(
+            "{RED_FG}DUPLICATE{RESET_FG} {function}: {}",
+            paths.join(", ")
+        );
+    }
+    println!(
+        "{} script(s) linted, {} duplicated function name(s)",
+        scripts.len(),
+        duplicates.len()
+    );
+    duplicates.is_empty()
+}
+
 /// Runs lk in 'fuzzy' mode.
-fn fuzzy(scripts: &[Script], lines_to_show: i8) -> Result<()> {
-    let result = FuzzyFinder::find(scripts_to_item(scripts), lines_to_show).unwrap();
-    if let Some(function) = result {
-        // We're going to write the equivelent lk command to the shell's history
-        // file, so the user can easily re-run it.
-        let history = UserShell::new();
-        match history {
-            Some(history) => {
-                let lk_command = format!("lk {} {}", function.0.file_name(), function.1.name,);
-                history.add_command(lk_command)?;
+fn fuzzy(
+    scripts: &[Script],
+    lines_to_show: i8,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+    print_to: Option<PrintTo>,
+) -> Result<()> {
+    let mut items = scripts_to_item(scripts, &config.label_format, &options.print);
+    if !items
+        .iter()
+        .any(|item| matches!(item.item, Some(Selection::Function(..))))
+    {
+        // Don't open the fuzzy finder if there's nothing to run.
+        print_no_functions_in_script_help();
+        return Ok(());
+    }
+    // Until a search is typed the items stay in this order, so recent functions come first.
+    sort_by_recent(&mut items, history);
+    fuzzy_find(
+        items,
+        None,
+        lines_to_show,
+        config,
+        history,
+        options,
+        print_to,
+    )
+}
+
+/// Runs lk in 'fuzzy' mode, but only over recently run functions.
+fn recent(
+    scripts: &[Script],
+    lines_to_show: i8,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+    print_to: Option<PrintTo>,
+) -> Result<()> {
+    let mut items: Vec<Item<Selection>> =
+        scripts_to_item(scripts, &config.label_format, &options.print)
+            .into_iter()
+            .filter(|item| match item.item.unwrap() {
+                Selection::Script(_) => false,
+                Selection::Function(script, function) => history
+                    .entries
+                    .iter()
+                    .any(|entry| entry.is_for(script, function)),
+            })
+            .collect();
+    if items.is_empty() {
+        print_no_recent_functions();
+        return Ok(());
+    }
+    sort_by_recent(&mut items, history);
+    fuzzy_find(
+        items,
+        None,
+        lines_to_show,
+        config,
+        history,
+        options,
+        print_to,
+    )
+}
+
+/// Fuzzy finds over the lines on stdin and prints the one the user selects. Returns false if
+/// they didn't select one.
+fn fuzzy_stdin(lines_to_show: i8, config: &config::Config, palette: &Palette) -> Result<bool> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+        eprintln!("{RED_FG}lk --stdin searches the lines piped to it, e.g. {GREEN_FG}git branch | lk --stdin{RESET_FG}");
+        return Ok(false);
+    }
+    let items: Vec<Item<String>> = std::io::stdin()
+        .lines()
+        .collect::<std::io::Result<Vec<String>>>()?
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Item::new(line.clone(), line))
+        .collect();
+    if items.is_empty() {
+        return Ok(false);
+    }
+    match finder(items, lines_to_show, config, palette).run()? {
+        Some(line) => {
+            println!("{line}");
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Sorts the items so the most recently run functions come first. The others keep their order.
+fn sort_by_recent(items: &mut [Item<Selection>], history: &History) {
+    let recent = history.recent();
+    items.sort_by_key(|item| match item.item.unwrap() {
+        Selection::Script(_) => usize::MAX,
+        Selection::Function(script, function) => recent
+            .iter()
+            .position(|entry| entry.is_for(script, function))
+            .unwrap_or(usize::MAX),
+    });
+}
+
+/// Fuzzy finds over the items, and runs whichever function the user selects, or writes its
+/// command to `print_to`. If they select a script then we fuzzy find over its functions. If
+/// we're only searching `script`'s functions and nothing matches, we offer to add the function
+/// the user searched for.
+fn fuzzy_find(
+    items: Vec<Item<Selection>>,
+    script: Option<&Script>,
+    lines_to_show: i8,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+    print_to: Option<PrintTo>,
+) -> Result<()> {
+    // Each selection comes back with the label the user saw, for `--raw`.
+    let items = with_frecency(items, config, history)
+        .into_iter()
+        .map(|item| {
+            let label = item.name.to_owned();
+            item.map(|selection| (label, selection))
+        })
+        .collect();
+    let result = finder(items, lines_to_show, config, &options.print.palette)
+        .preview(|(_, selection): &(String, Selection)| selection.description())
+        .run_outcome()?;
+    match result {
+        Outcome::Picked((_, Selection::Script(script))) => {
+            if script.functions.is_empty() {
+                print_no_functions_in_script_help();
+                return Ok(());
             }
-            None => {
-                log::warn!("Unable to write to history file because we couldn't figure out what shell you're using");
+            let directory = label_directory(std::slice::from_ref(script), &options.print);
+            let mut items = functions_to_items(
+                script,
+                &config.label_format,
+                &options.print,
+                directory.as_deref(),
+            );
+            sort_by_recent(&mut items, history);
+            fuzzy_find(
+                items,
+                Some(script),
+                lines_to_show,
+                config,
+                history,
+                options,
+                print_to,
+            )?;
+        }
+        Outcome::Picked((label, Selection::Function(script, function))) => {
+            let lk_command = format!(
+                "lk {} {}",
+                script.command_name(options.print.absolute_paths),
+                function.name
+            );
+            if let Some(print_to) = print_to {
+                // The shell runs it, and adds it to its history, if the user wants to.
+                return print_to
+                    .target
+                    .write(if print_to.raw { &label } else { &lk_command });
+            }
+            // We're going to write the equivelent lk command to the shell's history
+            // file, so the user can easily re-run it.
+            let shell = UserShell::new();
+            match shell {
+                Some(shell) => {
+                    shell.add_command(lk_command)?;
+                }
+                None => {
+                    log::warn!("Unable to write to history file because we couldn't figure out what shell you're using");
+                }
             }
+            exit_if_failed(run(script, function, vec![], config, history, options)?);
         }
-        // Finally we execute the function using a temporary bash file.
-        BashFile::run(function.0.to_owned(), function.1.to_owned(), [].to_vec())?;
+        Outcome::NoMatch(search) => match script {
+            Some(script) if is_function_name(&search) => {
+                if confirm_add_function(&search, script)? {
+                    script.add_function(&search)?;
+                    print_function_added(&search, script);
+                }
+            }
+            _ => print_no_match(&search),
+        },
+        Outcome::Cancelled => {}
     }
     Ok(())
 }
 
+/// Runs lk in 'fuzzy' mode, but over just the functions, and the user marks the ones to run
+/// with tab. They're run in the order they were marked, like `run_each`.
+fn fuzzy_multi(
+    scripts: &[Script],
+    lines_to_show: i8,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<()> {
+    let directory = label_directory(scripts, &options.print);
+    let mut items: Vec<Item<Selection>> = scripts
+        .iter()
+        .flat_map(|script| {
+            functions_to_items(
+                script,
+                &config.label_format,
+                &options.print,
+                directory.as_deref(),
+            )
+        })
+        .collect();
+    if items.is_empty() {
+        print_no_functions_in_script_help();
+        return Ok(());
+    }
+    sort_by_recent(&mut items, history);
+    let items = with_frecency(items, config, history);
+    let functions: Vec<(&Script, &Function)> =
+        finder(items, lines_to_show, config, &options.print.palette)
+            .preview(Selection::description)
+            .run_multi()?
+            .into_iter()
+            .filter_map(|selection| match selection {
+                Selection::Function(script, function) => Some((script, function)),
+                Selection::Script(_) => None,
+            })
+            .collect();
+    run_each(&functions, false, config, history, options)
+}
+
+/// Boosts the functions the user runs most, if they've turned `frecency` on.
+fn with_frecency<'a>(
+    items: Vec<Item<Selection<'a>>>,
+    config: &config::Config,
+    history: &History,
+) -> Vec<Item<Selection<'a>>> {
+    if !config.frecency {
+        return items;
+    }
+    items
+        .into_iter()
+        .map(|item| match item.item.unwrap() {
+            Selection::Script(_) => item,
+            Selection::Function(script, function) => {
+                let boost = history.boost(script, function);
+                item.with_boost(boost)
+            }
+        })
+        .collect()
+}
+
+/// A fuzzy finder over `items`, as the user's configured it.
+fn finder<T: Clone>(
+    items: Vec<Item<T>>,
+    lines_to_show: i8,
+    config: &config::Config,
+    palette: &Palette,
+) -> FuzzyFinderBuilder<T> {
+    let builder = FuzzyFinder::builder(items)
+        .lines_to_show(lines_to_show)
+        .colours(palette.finder.to_owned());
+    match &config.prompt_symbol {
+        Some(prompt_symbol) => builder.prompt_symbol(prompt_symbol),
+        None => builder,
+    }
+}
+
+/// Runs every function in `script`, in order, for `--all-functions`, like `run_each`.
+fn run_all(
+    script: &Script,
+    continue_on_error: bool,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<()> {
+    let functions: Vec<(&Script, &Function)> = script
+        .functions
+        .iter()
+        .map(|function| (script, function))
+        .collect();
+    run_each(&functions, continue_on_error, config, history, options)
+}
+
+/// Runs each of `functions`, in order, each in its own shell. We stop at the first one that
+/// fails, unless `continue_on_error`, and then exit with its exit code, or the last failure's
+/// if we carried on.
+fn run_each(
+    functions: &[(&Script, &Function)],
+    continue_on_error: bool,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<()> {
+    let mut exit_code = None;
+    for &(script, function) in functions {
+        let status = match run(script, function, vec![], config, history, options)? {
+            Some(status) if !status.success() => status,
+            _ => continue,
+        };
+        print_function_failed(function, status, continue_on_error);
+        // A function killed by a signal has no exit code.
+        exit_code = Some(status.code().unwrap_or(1));
+        if !continue_on_error {
+            break;
+        }
+    }
+    match exit_code {
+        Some(exit_code) => std::process::exit(exit_code),
+        None => Ok(()),
+    }
+}
+
+/// Runs the function, remembering that we did, and returns its exit status. Functions
+/// matching the config's `confirm_patterns` are only run if the user confirms, and if they
+/// don't there's no status. If the function times out then lk exits with
+/// `TIMED_OUT_EXIT_CODE`. With `--edit` it's opened in the user's editor first, and then
+/// the script's read again, so it runs as it was saved.
+fn run(
+    script: &Script,
+    function: &Function,
+    params: Vec<String>,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<Option<ExitStatus>> {
+    if options.edit {
+        if !editor::edit(&script.absolute_path, function.line)? {
+            print_edit_failed(function);
+            return Ok(None);
+        }
+        let edited = Script {
+            path: script.path.to_owned(),
+            ..Script::new(
+                &Executable::from_absolute_path(&script.absolute_path),
+                &options.parse,
+            )?
+        };
+        let Some(function) = edited.get(&function.name) else {
+            print_function_no_longer_in_script(&function.name, &edited);
+            return Ok(None);
+        };
+        let options = Options {
+            edit: false,
+            ..options.clone()
+        };
+        return run(&edited, function, params, config, history, &options);
+    }
+    print_banner(script, &function.signature(), &params, options);
+    // Finally we execute the function using a temporary bash file. It's only remembered once
+    // it's run, so not if the user doesn't confirm it, or if we're only showing the runner.
+    let run_options = RunOptions {
+        confirm: config.needs_confirmation(&function.name),
+        ..options.run.clone()
+    };
+    let recorded_params = params.clone();
+    exit_if_timed_out(BashFile::run(
+        script.to_owned(),
+        function.to_owned(),
+        params,
+        &run_options,
+        || {
+            if let Err(err) = history.record(script, function, &recorded_params) {
+                log::error!("Unable to record {} in the history: {err}", function.name);
+            }
+        },
+    ))
+}
+
+/// Runs `functions` one after the other, like `run` runs one, but in the same shell. The
+/// rest aren't run if one fails. If any of them match `confirm_patterns` then the user's
+/// asked about them all at once.
+fn run_chain(
+    script: &Script,
+    functions: &[&Function],
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<Option<ExitStatus>> {
+    let names: Vec<&str> = functions
+        .iter()
+        .map(|function| function.name.as_str())
+        .collect();
+    print_banner(script, &names.join(", "), &[], options);
+    let run_options = RunOptions {
+        confirm: functions
+            .iter()
+            .any(|function| config.needs_confirmation(&function.name)),
+        ..options.run.clone()
+    };
+    let record = || {
+        for function in functions {
+            if let Err(err) = history.record(script, function, &[]) {
+                log::error!("Unable to record {} in the history: {err}", function.name);
+            }
+        }
+    };
+    let functions = functions
+        .iter()
+        .map(|&function| function.to_owned())
+        .collect();
+    exit_if_timed_out(BashFile::run_chain(
+        script.to_owned(),
+        functions,
+        &run_options,
+        record,
+    ))
+}
+
+/// Prints the banner before a function's output, unless we're being quiet, or only showing
+/// the runner.
+fn print_banner(script: &Script, functions: &str, params: &[String], options: &Options) {
+    if !options.print.quiet && !options.run.show_runner {
+        print_complete_header(
+            script,
+            functions,
+            params,
+            options.print.absolute_paths,
+            &options.print.palette,
+        );
+    }
+}
+
+/// Exits with `TIMED_OUT_EXIT_CODE` if the function timed out. The bash file's gone by now,
+/// so we can exit without leaving it behind.
+fn exit_if_timed_out(result: Result<Option<ExitStatus>>) -> Result<Option<ExitStatus>> {
+    match result {
+        Err(err) if err.is::<TimedOut>() => {
+            eprintln!("{RED_FG}{err}{RESET_FG}");
+            std::process::exit(TIMED_OUT_EXIT_CODE);
+        }
+        result => result,
+    }
+}
+
+/// Exits with the function's exit code if it failed, so that lk fails like it did.
+fn exit_if_failed(status: Option<ExitStatus>) {
+    if let Some(status) = status.filter(|status| !status.success()) {
+        // A function killed by a signal has no exit code.
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+/// Runs the function that was run last again, with the same params.
+fn run_last(config: &config::Config, history: &mut History, options: &Options) -> Result<()> {
+    let last = match history.last() {
+        Some(last) => last.clone(),
+        None => {
+            print_no_last_function();
+            return Ok(());
+        }
+    };
+    let script = match Script::new(
+        &Executable::from_absolute_path(&last.script),
+        &options.parse,
+    ) {
+        Ok(script) => script,
+        Err(err) => {
+            print_script_error(&err);
+            std::process::exit(1);
+        }
+    };
+    match script.get(&last.function) {
+        Some(function) => {
+            exit_if_failed(run(
+                &script,
+                function,
+                last.params,
+                config,
+                history,
+                options,
+            )?);
+            Ok(())
+        }
+        None => {
+            print_bad_function_name(&script, &last.function, &options.print);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints where the script and function in `args` are, returning whether they were found.
+/// Nothing is printed if they weren't, so an editor can tell.
+fn print_path(executables: &Executables, args: &Cli, options: &ParseOptions) -> bool {
+    let Some(Lookup::Found(executable)) = args.script.as_deref().map(|name| executables.get(name))
+    else {
+        return false;
+    };
+    let path = executable.absolute_path.display();
+    let Some(function) = &args.function else {
+        println!("{path}");
+        return true;
+    };
+    match Script::new(executable, options)
+        .ok()
+        .and_then(|script| script.get_by_name_or_number(function).cloned())
+    {
+        Some(function) => {
+            println!("{path}:{}", function.line);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Prints the names of the functions in the script in `args` that start with `prefix`, one
+/// per line and without any colours, returning whether the script was found. Nothing is
+/// printed if it wasn't.
+fn print_function_names(
+    executables: &Executables,
+    args: &Cli,
+    prefix: &str,
+    options: &Options,
+) -> bool {
+    let Some(Lookup::Found(executable)) = args.script.as_deref().map(|name| executables.get(name))
+    else {
+        return false;
+    };
+    let Ok(script) = Script::new(executable, &options.parse) else {
+        return false;
+    };
+    let mut functions: Vec<&Function> = script
+        .functions
+        .iter()
+        .filter(|function| function.name.starts_with(prefix))
+        .collect();
+    options
+        .print
+        .function_order
+        .sort(&mut functions, |function| function);
+    for function in functions {
+        println!("{}", function.name);
+    }
+    true
+}
+
 /// Runs lk in 'list' mode.
-fn list(executables: Executables, args: Cli) -> Result<()> {
+fn list(
+    mut executables: Executables,
+    empty_scripts: &[Script],
+    args: Cli,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<()> {
+    if let Some(order) = args.sort {
+        executables.sort(order);
+    }
     // Did the user request a script?
-    if let Some(script) = args.script {
-        // Is it a script that exists on disk?
-        if let Some(executable) = executables.get(&script) {
-            // Yay, confirmed script
-            let script = Script::new(executable)?;
-            // Did the user pass a function?
-            if let Some(function) = args.function {
-                // Is it a function that exists in the script we found?
-                if let Some(function) = script.get(&function) {
-                    // Finally we execute the function using a temporary bash file.
-                    BashFile::run(script.to_owned(), function.to_owned(), args.params)?;
-                } else {
-                    print_bad_function_name(&script, &function);
+    let Some(script) = args.script else {
+        // No executable, display a list of what's available
+        executables.hide_empty(empty_scripts);
+        if executables.is_empty() {
+            print_no_executables_found(&executables.roots.join(", "));
+            return Ok(());
+        }
+        return executables.pretty_print(&options.print);
+    };
+    // Is it a script that exists on disk?
+    let executable = match executables.get(&script) {
+        Lookup::Found(executable) => executable,
+        Lookup::Ambiguous(candidates) => {
+            print_ambiguous_script_name(&script, &candidates, options.print.absolute_paths);
+            std::process::exit(1);
+        }
+        Lookup::Missing => {
+            print_bad_script_name(&script, executables, &options.print)?;
+            std::process::exit(1);
+        }
+    };
+    // Yay, confirmed script
+    let script = match Script::new(executable, &options.parse) {
+        Ok(script) => script,
+        Err(err) => {
+            print_script_error(&err);
+            std::process::exit(1);
+        }
+    };
+    // Did the user pass a function?
+    if args.loop_ {
+        run_loop(executable, script, config, history, options)?;
+    } else if args.all_functions {
+        run_all(&script, args.continue_on_error, config, history, options)?;
+    } else if let Some(function) = args.function {
+        // Is it a function that exists in the script we found? It might be a number
+        // from the list of functions.
+        if let Some(function) = script.get_by_name_or_number(&function) {
+            // With --chain the params are functions to run after it, rather than its params,
+            // e.g. lk --chain deploy.sh build test release.
+            if args.chain {
+                let mut functions = vec![function];
+                for name in &args.params {
+                    match script.get(name) {
+                        Some(function) => functions.push(function),
+                        None => {
+                            print_bad_function_name(&script, name, &options.print);
+                            std::process::exit(1);
+                        }
+                    }
                 }
+                exit_if_failed(run_chain(&script, &functions, config, history, options)?);
             } else {
-                // No function, display a list of what's available
-                script.pretty_print();
+                exit_if_failed(run(
+                    &script,
+                    function,
+                    args.params,
+                    config,
+                    history,
+                    options,
+                )?);
             }
         } else {
-            print_bad_script_name(&script, executables);
+            print_bad_function_name(&script, &function, &options.print);
+            std::process::exit(1);
         }
     } else {
-        // No executable, display a list of what's available
-        executables.pretty_print();
+        // No function, display a list of what's available
+        script.pretty_print(&options.print);
     }
     Ok(())
 }
 
-/// Convert the scripts we find to the 'item' required for fuzzy find.
-fn scripts_to_item(scripts: &[Script]) -> Vec<Item<(&Script, &Function)>> {
-    let mut fuzzy_functions: Vec<Item<(&Script, &Function)>> = Vec::new();
+/// Runs lk in `--loop` mode: lists the script's functions and runs the one the user picks,
+/// again and again, until they quit. Then we exit with the last function's exit code.
+fn run_loop(
+    executable: &Executable,
+    mut script: Script,
+    config: &config::Config,
+    history: &mut History,
+    options: &Options,
+) -> Result<()> {
+    let mut last_status = None;
+    // Whether to list the functions before asking. A bad name lists them already.
+    let mut list_functions = true;
+    loop {
+        if list_functions {
+            script.pretty_print(&options.print);
+        }
+        list_functions = true;
+        let Some(answer) = prompt_for_function()? else {
+            break;
+        };
+        let mut words = answer.split_whitespace().map(String::from);
+        // An empty answer just lists the functions again.
+        let Some(name) = words.next() else {
+            continue;
+        };
+        match script.get_by_name_or_number(&name) {
+            Some(function) => {
+                let status = run(&script, function, words.collect(), config, history, options)?;
+                last_status = status.or(last_status);
+            }
+            None => {
+                print_bad_function_name(&script, &name, &options.print);
+                list_functions = false;
+            }
+        }
+        // The function might have changed the script, so we list what's there now.
+        script = match Script::new(executable, &options.parse) {
+            Ok(script) => script,
+            Err(err) => {
+                print_script_error(&err);
+                std::process::exit(1);
+            }
+        };
+    }
+    match last_status {
+        // A function killed by a signal has no exit code.
+        Some(status) if !status.success() => std::process::exit(status.code().unwrap_or(1)),
+        _ => Ok(()),
+    }
+}
+
+/// Runs lk in 'list' mode, listing the scripts, or the script the user asked for, again
+/// whenever they change.
+fn watch_list(
+    roots: &[String],
+    ignores: &Ignores,
+    config_ignores: &[String],
+    is_candidate: &IsCandidate,
+    args: &Cli,
+    options: &Options,
+    lk_dir: &str,
+) -> Result<()> {
+    // The pager would stop us from watching.
+    let print_options = PrintOptions {
+        pager: false,
+        ..options.print.clone()
+    };
+    let root_ignores: Vec<RootIgnores> = roots
+        .iter()
+        .map(|root| RootIgnores::new(root, ignores, config_ignores))
+        .collect();
+    // lk logs to its own directory while it renders, so if that's under a root then a change
+    // there mustn't render again, or we'd never stop.
+    let lk_dir = std::fs::canonicalize(lk_dir).unwrap_or_else(|_| PathBuf::from(lk_dir));
+    let is_ignored = |path: &Path| {
+        root_ignores.iter().any(|root| root.is_ignored(path))
+            || std::fs::canonicalize(path)
+                .unwrap_or_else(|_| path.to_owned())
+                .starts_with(&lk_dir)
+    };
+    watch::watch(roots, is_ignored, || {
+        let mut executables = Executables::from_roots(
+            roots,
+            ignores,
+            config_ignores,
+            is_candidate,
+            args.follow_symlinks,
+            |_| {},
+        );
+        if let Some(order) = args.sort {
+            executables.sort(order);
+        }
+        match &args.script {
+            Some(name) => match executables.get(name) {
+                Lookup::Found(executable) => match Script::new(executable, &options.parse) {
+                    Ok(script) => script.pretty_print(&print_options),
+                    Err(err) => print_script_error(&err),
+                },
+                Lookup::Ambiguous(candidates) => {
+                    print_ambiguous_script_name(name, &candidates, print_options.absolute_paths)
+                }
+                Lookup::Missing => print_bad_script_name(name, executables, &print_options)?,
+            },
+            None => {
+                if print_options.hide_empty_scripts {
+                    let empty_scripts: Vec<Script> = executables
+                        .executables
+                        .iter()
+                        .filter_map(|executable| Script::new(executable, &options.parse).ok())
+                        .filter(|script| script.functions.is_empty())
+                        .collect();
+                    executables.hide_empty(&empty_scripts);
+                }
+                if executables.is_empty() {
+                    print_no_executables_found(&roots.join(", "));
+                } else {
+                    executables.pretty_print(&print_options)?
+                }
+            }
+        }
+        print_watching();
+        Ok(())
+    })
+}
+
+/// Convert the scripts we find to the 'item' required for fuzzy find. Each script comes
+/// before its functions.
+fn scripts_to_item<'a>(
+    scripts: &'a [Script],
+    format: &LabelFormat,
+    options: &PrintOptions,
+) -> Vec<Item<Selection<'a>>> {
+    let directory = label_directory(scripts, options);
+    let mut fuzzy_items: Vec<Item<Selection>> = Vec::new();
     scripts.iter().for_each(|script| {
-        script.functions.iter().for_each(|function| {
-            fuzzy_functions.push(Item::new(
-                format!(
-                    "{}/{} - {}",
-                    script.path(),
-                    script.file_name(),
-                    function.name
-                ),
-                (script, function),
-            ))
-        })
+        fuzzy_items.push(Item::with_fields(
+            label_fields(
+                format.script_parts(),
+                script,
+                None,
+                options.absolute_paths,
+                directory.as_deref(),
+            ),
+            Selection::Script(script),
+        ));
+        fuzzy_items.extend(functions_to_items(
+            script,
+            format,
+            options,
+            directory.as_deref(),
+        ));
     });
-    fuzzy_functions
+    fuzzy_items
+}
+
+/// The directory to label `scripts` within, with `--strip-common-prefix`. It's only left
+/// out of the labels, so the scripts are still run from where they are.
+fn label_directory(scripts: &[Script], options: &PrintOptions) -> Option<PathBuf> {
+    options
+        .strip_common_prefix
+        .then(|| script::common_directory(scripts, options.absolute_paths))
+}
+
+/// Convert a script's functions to the 'item' required for fuzzy find. They're scoped to the
+/// script's file name, so typing e.g. `deploy.sh>` searches just that script's functions.
+fn functions_to_items<'a>(
+    script: &'a Script,
+    format: &LabelFormat,
+    options: &PrintOptions,
+    directory: Option<&Path>,
+) -> Vec<Item<Selection<'a>>> {
+    let mut functions: Vec<&Function> = script.functions.iter().collect();
+    options
+        .function_order
+        .sort(&mut functions, |function| function);
+    functions
+        .into_iter()
+        .map(|function| {
+            let mut fields = label_fields(
+                format.parts(),
+                script,
+                Some(function),
+                options.absolute_paths,
+                directory,
+            );
+            if !function.aliases.is_empty() {
+                fields.push(Field::separator(" ["));
+                fields.push(Field::new(&function.aliases.join(", "), 100));
+                fields.push(Field::separator("]"));
+            }
+            if let Some(group) = &function.group {
+                // Like a directory, a group is context that lots of functions share.
+                fields.push(Field::separator(" ("));
+                fields.push(Field::new(group, DIRECTORY_WEIGHT));
+                fields.push(Field::separator(")"));
+            }
+            Item::with_fields(fields, Selection::Function(script, function))
+                .with_scope(&script.file_name())
+        })
+        .collect()
+}
+
+/// Fills in a label's parts, with the script's path within `directory` if there is one. The
+/// script's directory is weighted lower than its file name and the function's name.
+fn label_fields(
+    parts: &[LabelPart],
+    script: &Script,
+    function: Option<&Function>,
+    absolute_paths: bool,
+    directory: Option<&Path>,
+) -> Vec<Field> {
+    let path = match directory {
+        Some(directory) => script.display_path_in(absolute_paths, directory),
+        None => script.display_path(absolute_paths),
+    };
+    let (directory, file_name) = path.split_at(path.rfind('/').map_or(0, |i| i + 1));
+    let directory = directory.strip_suffix('/').unwrap_or(directory);
+    parts
+        .iter()
+        .map(|part| match part {
+            LabelPart::Text(text) => Field::separator(text),
+            LabelPart::Path => Field::new(directory, DIRECTORY_WEIGHT),
+            LabelPart::File => Field::new(file_name, 100),
+            LabelPart::Function => Field::new(function.map_or("", |f| f.name.as_str()), 100),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::from_iter_safe(std::iter::once("lk").chain(args.iter().copied()))
+            .unwrap()
+            .with_command()
+    }
+
+    #[test]
+    fn test_run_is_the_same_as_positional_args() {
+        for args in [
+            parse(&["run", "deploy.sh", "build", "dev"]),
+            parse(&["deploy.sh", "build", "dev"]),
+        ] {
+            assert_eq!(args.script.as_deref(), Some("deploy.sh"));
+            assert_eq!(args.function.as_deref(), Some("build"));
+            assert_eq!(args.params, vec!["dev"]);
+            assert!(args.command.is_none());
+        }
+    }
+
+    #[test]
+    fn test_run_needs_a_function() {
+        let args = ["lk", "run", "deploy.sh"];
+        assert!(Cli::from_iter_safe(args).is_err());
+    }
+
+    #[test]
+    fn test_other_commands() {
+        let args = parse(&["list", "deploy.sh"]);
+        assert!(args.list);
+        assert_eq!(args.script.as_deref(), Some("deploy.sh"));
+        let args = parse(&["list"]);
+        assert!(args.list);
+        assert!(args.script.is_none());
+        assert!(parse(&["fuzzy"]).fuzzy);
+        assert_eq!(parse(&["init"]).init.as_deref(), Some(init::DEFAULT_NAME));
+        assert_eq!(parse(&["init", "tasks"]).init.as_deref(), Some("tasks"));
+        let args = parse(&["__complete", "deploy.sh", "bu"]);
+        assert_eq!(args.script.as_deref(), Some("deploy.sh"));
+        assert_eq!(args.complete.as_deref(), Some("bu"));
+        assert_eq!(
+            parse(&["__complete", "deploy.sh"]).complete.as_deref(),
+            Some("")
+        );
+    }
+
+    #[test]
+    fn test_duplicate_functions_are_labelled_with_their_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let scripts: Vec<Script> = ["a", "b"]
+            .iter()
+            .map(|directory| {
+                let path = dir.path().join(directory).join("build.sh");
+                std::fs::create_dir(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, "#!/bin/bash\n\ndeploy() {\n}\n").unwrap();
+                let executable = Executable::from_absolute_path(&path);
+                Script::new(&executable, &ParseOptions::default()).unwrap()
+            })
+            .collect();
+        for strip_common_prefix in [false, true] {
+            let options = PrintOptions {
+                strip_common_prefix,
+                ..PrintOptions::default()
+            };
+            let labels: Vec<String> = function_labels(&scripts, &LabelFormat::default(), &options);
+            assert_eq!(labels.len(), 2);
+            assert!(labels[0].ends_with("a/build.sh - deploy"), "{}", labels[0]);
+            assert!(labels[1].ends_with("b/build.sh - deploy"), "{}", labels[1]);
+        }
+    }
+
+    fn function_labels(
+        scripts: &[Script],
+        format: &LabelFormat,
+        options: &PrintOptions,
+    ) -> Vec<String> {
+        scripts_to_item(scripts, format, options)
+            .into_iter()
+            .filter(|item| matches!(item.item, Some(Selection::Function(..))))
+            .map(|item| item.name)
+            .collect()
+    }
 }
@@ -0,0 +1,106 @@
+/// Shows long output through the user's pager.
+use anyhow::Result;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// What we page with if `$PAGER` isn't set. `-R` keeps our colours.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Prints `output`, through `$PAGER` if `enabled` and it won't fit in the terminal.
+/// If the pager won't start then we just print it.
+pub fn page(output: &str, enabled: bool) -> Result<()> {
+    if enabled && should_page(output, is_tty(), terminal_height()) {
+        let pager = std::env::var("PAGER").ok();
+        match run_pager(pager_command(pager.as_deref()), output) {
+            Ok(()) => return Ok(()),
+            Err(err) => log::warn!("Unable to run the pager: {err}"),
+        }
+    }
+    print!("{output}");
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+/// Whether `output` is too long for the terminal. We only page to a terminal.
+fn should_page(output: &str, is_tty: bool, terminal_height: Option<u16>) -> bool {
+    match terminal_height {
+        Some(height) if is_tty => output.lines().count() >= height as usize,
+        _ => false,
+    }
+}
+
+/// Builds the command for `pager`, which is split on whitespace like a shell would.
+/// `less` is told to keep our colours unless `$LESS` says otherwise.
+fn pager_command(pager: Option<&str>) -> Command {
+    let pager = match pager {
+        Some(pager) if !pager.trim().is_empty() => pager,
+        _ => DEFAULT_PAGER,
+    };
+    let mut words = pager.split_whitespace();
+    let mut command = Command::new(words.next().unwrap());
+    command.args(words);
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "R");
+    }
+    command
+}
+
+/// Runs the pager with `output` on its stdin, waiting for the user to quit it.
+fn run_pager(mut command: Command, output: &str) -> Result<()> {
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    // The user can quit the pager before reading everything, which closes the pipe.
+    if let Err(err) = stdin.write_all(output.as_bytes()) {
+        if err.kind() != std::io::ErrorKind::BrokenPipe {
+            return Err(err.into());
+        }
+    }
+    drop(stdin);
+    child.wait()?;
+    Ok(())
+}
+
+/// Whether stdout is a terminal.
+pub fn is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+fn terminal_height() -> Option<u16> {
+    termion::terminal_size().ok().map(|(_, height)| height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_words;
+
+    #[test]
+    fn test_should_page() {
+        let output = "one\ntwo\nthree\n";
+        assert!(should_page(output, true, Some(3)));
+        assert!(!should_page(output, true, Some(4)));
+        // Never when we're not writing to a terminal.
+        assert!(!should_page(output, false, Some(3)));
+        assert!(!should_page(output, true, None));
+    }
+
+    #[test]
+    fn test_pager_command() {
+        let program = |pager| command_words(&pager_command(pager));
+        assert_eq!(program(None), vec!["less", "-R"]);
+        assert_eq!(program(Some("")), vec!["less", "-R"]);
+        assert_eq!(program(Some("most")), vec!["most"]);
+        assert_eq!(
+            program(Some("bat --paging always")),
+            vec!["bat", "--paging", "always"]
+        );
+    }
+
+    #[test]
+    fn test_run_pager() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "cat > /dev/null"]);
+        run_pager(command, "some output\n").unwrap();
+        assert!(run_pager(Command::new("not-a-real-pager"), "").is_err());
+    }
+}
@@ -1,18 +1,265 @@
 /// Parses a script file and extracts comments and functions.
+use crate::bash_file::shell_quote;
 use crate::executables::Executable;
-use crate::ui::{print_no_functions_in_script_help, print_script_header};
-use anyhow::Result;
+use crate::theme::Palette;
+use crate::ui::{
+    print_no_functions_in_script_help, print_script_footer, print_script_header, PrintOptions,
+};
 use pad::{Alignment, PadStr};
-use pastel_colours::{GREEN_FG, RESET_FG};
+use pastel_colours::RESET_FG;
 use regex::bytes::Regex;
-use std::io::BufRead;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::{BufRead, ErrorKind};
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::{fs::File, path::Path};
+use thiserror::Error;
+
+/// The heading for functions that aren't in a group, when some are.
+const DEFAULT_GROUP: &str = "Other";
+
+/// How wide we assume the terminal is if we can't tell.
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+
+/// How `Script::new` reads a script.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    /// Read a `: 'docstring'` at the start of a function's body as its comment, when there
+    /// isn't one above it, as `parse_docstrings = true` in the config does. It's off by
+    /// default, because it means checking the start of every function's body.
+    pub docstrings: bool,
+}
+
+/// How to order a script's functions when they're listed, as passed to `--sort-functions`.
+/// They keep the numbers they have in the script's order, so they can be run by number
+/// however they're listed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FunctionOrder {
+    /// The order they're in in the script, which is the order the author chose.
+    #[default]
+    #[serde(rename = "none")]
+    Source,
+    /// Alphabetically, ignoring case.
+    Alpha,
+}
+
+impl FunctionOrder {
+    /// Puts `items` in this order, where `function` is each item's function.
+    pub fn sort<T>(self, items: &mut [T], function: impl Fn(&T) -> &Function) {
+        if self == FunctionOrder::Alpha {
+            items.sort_by_cached_key(|item| function(item).name.to_lowercase());
+        }
+    }
+}
+
+impl FromStr for FunctionOrder {
+    type Err = String;
+
+    fn from_str(order: &str) -> std::result::Result<Self, Self::Err> {
+        match order {
+            "none" => Ok(FunctionOrder::Source),
+            "alpha" => Ok(FunctionOrder::Alpha),
+            _ => Err(format!("expected none or alpha, not '{order}'")),
+        }
+    }
+}
+
+/// The template the fuzzy finder labels functions with, unless `label_format` says otherwise.
+pub const DEFAULT_LABEL_FORMAT: &str = "{path}/{file} - {function}";
+
+/// A piece of a fuzzy finder label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelPart {
+    /// Text that's shown as it is, like the ` - ` between the file and the function.
+    Text(String),
+    /// The script's directory, without a trailing `/`.
+    Path,
+    /// The script's file name.
+    File,
+    /// The function's name.
+    Function,
+}
+
+/// How the fuzzy finder labels functions, parsed from a template like `label_format`'s, where
+/// `{path}`, `{file}` and `{function}` are replaced with the script's directory, its file name,
+/// and the function's name. A function's aliases and group come after it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct LabelFormat {
+    template: String,
+    parts: Vec<LabelPart>,
+}
+
+impl LabelFormat {
+    /// The parts of a function's label.
+    pub fn parts(&self) -> &[LabelPart] {
+        &self.parts
+    }
+
+    /// The parts of a script's label, which is the template from its first `{path}` or `{file}`
+    /// to its last one, e.g. `{path}/{file}`.
+    pub fn script_parts(&self) -> &[LabelPart] {
+        let is_script = |part: &LabelPart| matches!(part, LabelPart::Path | LabelPart::File);
+        match (
+            self.parts.iter().position(is_script),
+            self.parts.iter().rposition(is_script),
+        ) {
+            (Some(first), Some(last)) => &self.parts[first..=last],
+            _ => &[],
+        }
+    }
+}
+
+impl Default for LabelFormat {
+    fn default() -> Self {
+        DEFAULT_LABEL_FORMAT.parse().unwrap()
+    }
+}
+
+impl FromStr for LabelFormat {
+    type Err = String;
+
+    fn from_str(template: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(LabelPart::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("'{template}' has a '{{' without a '}}'"))?;
+            parts.push(match &rest[start + 1..start + end] {
+                "path" => LabelPart::Path,
+                "file" => LabelPart::File,
+                "function" => LabelPart::Function,
+                placeholder => {
+                    return Err(format!(
+                        "'{{{placeholder}}}' isn't a placeholder. Use {{path}}, {{file}} and {{function}}"
+                    ))
+                }
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(LabelPart::Text(rest.to_string()));
+        }
+        if !parts.contains(&LabelPart::Function) {
+            return Err(format!("'{template}' needs a {{function}}"));
+        }
+        // Scripts are labelled with just these, so without them they'd be blank.
+        if !parts.contains(&LabelPart::File) && !parts.contains(&LabelPart::Path) {
+            return Err(format!(
+                "'{template}' needs a {{file}} or {{path}}, to label scripts with"
+            ));
+        }
+        Ok(LabelFormat {
+            template: template.to_string(),
+            parts,
+        })
+    }
+}
+
+impl TryFrom<String> for LabelFormat {
+    type Error = String;
+
+    fn try_from(template: String) -> std::result::Result<Self, Self::Error> {
+        template.parse()
+    }
+}
+
+impl From<LabelFormat> for String {
+    fn from(format: LabelFormat) -> Self {
+        format.template
+    }
+}
+
+/// The interpreter a shebang line names, e.g. `bash` from `#!/bin/bash -e` or
+/// `#!/usr/bin/env -S bash -e`. `None` if the line isn't a shebang.
+fn shebang_interpreter(line: &str) -> Option<String> {
+    let line = line.trim_start_matches('\u{feff}').strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let program = Path::new(words.next()?).file_name()?.to_string_lossy();
+    if program == "env" {
+        // Skip env's own options, like `-S`.
+        words.find(|word| !word.starts_with('-')).map(String::from)
+    } else {
+        Some(program.to_string())
+    }
+}
+
+/// Why a script couldn't be turned into a `Script`.
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("Permission denied opening {}", .path.display())]
+    PermissionDenied { path: PathBuf },
+    #[error("Unable to read {}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Unable to parse {}, line {line}: {message}", .path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
 
 /// Everything we need to know about a function in a script
 #[derive(PartialEq, Debug, Clone)]
 pub struct Function {
     pub name: String,
+    /// The line the function starts on, counting from 1.
+    pub line: usize,
     pub comment: Vec<String>,
+    /// The lines after the function's opening line, up to its closing `}`. For a function
+    /// that's all on one line it's what's between the braces, so it's previewed just the same.
+    pub body: Vec<String>,
+    /// The group set by the last `# lk: group "<name>"` before the function, if any.
+    pub group: Option<String>,
+    /// Other names the function can be run by, from `# lk: alias <name>, <name>` before it.
+    pub aliases: Vec<String>,
+    /// Options to `set` before the script's sourced, from `# lk: shellopts -euo pipefail`.
+    pub shellopts: Option<String>,
+    /// The shell to run the function with, rather than bash, from `# lk: interpreter zsh`.
+    pub interpreter: Option<String>,
+}
+
+impl Function {
+    /// The names of the function's params, from `@param <name>` lines in its comment, e.g.
+    /// `# @param env Where to deploy to`.
+    pub fn params(&self) -> Vec<&str> {
+        self.comment
+            .iter()
+            .filter_map(|line| line.strip_prefix("@param "))
+            .filter_map(|param| param.split_whitespace().next())
+            .collect()
+    }
+
+    /// The function's comment without its `@param` lines, which its signature shows instead.
+    pub fn description(&self) -> impl Iterator<Item = &String> {
+        self.comment
+            .iter()
+            .filter(|line| !line.starts_with("@param "))
+    }
+
+    /// The shell the function runs in: its `# lk: interpreter`, or bash.
+    pub fn interpreter(&self) -> &str {
+        self.interpreter.as_deref().unwrap_or("bash")
+    }
+
+    /// How the function is called, e.g. `deploy <env> <version>`, or just `deploy` if its
+    /// params aren't documented.
+    pub fn signature(&self) -> String {
+        std::iter::once(self.name.to_owned())
+            .chain(self.params().into_iter().map(|param| format!("<{param}>")))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -21,66 +268,81 @@ pub struct Script {
     pub absolute_path: std::path::PathBuf,
     pub comment: Vec<String>,
     pub functions: Vec<Function>,
+    /// The interpreter the script's shebang names, e.g. `bash` from `#!/usr/bin/env bash`, or
+    /// `None` if it doesn't have one.
+    pub shebang: Option<String>,
 }
 
 impl Script {
-    pub fn new(executable: &Executable) -> Result<Self> {
-        let lines = match read_lines(&executable.path) {
-            Ok(lines) => lines,
-            Err(err) => {
-                log::error!(
-                    "Unable to read executable: {}. Error was: {err}",
-                    &executable.path.to_string_lossy()
-                );
-                anyhow::bail!("Permissiond denied in opening script");
-            }
+    pub fn new(executable: &Executable, options: &ParseOptions) -> Result<Self, ScriptError> {
+        let path = &executable.path;
+        let read_error = |source: std::io::Error| match source.kind() {
+            ErrorKind::PermissionDenied => ScriptError::PermissionDenied {
+                path: path.to_owned(),
+            },
+            _ => ScriptError::Read {
+                path: path.to_owned(),
+                source,
+            },
         };
+        let lines = read_lines(path)
+            .map_err(read_error)?
+            .collect::<std::io::Result<Vec<String>>>()
+            .map_err(read_error)?;
+        let shebang = lines.first().and_then(|line| shebang_interpreter(line));
 
-        // `comments` accumulates comments until we find a function header line, and then they're cleared.
-        let mut comments: Vec<String> = Vec::new();
-        let mut included_comments: Vec<String> = Vec::new();
-        let mut included_functions: Vec<Function> = Vec::new();
-        let mut in_header_comments: bool = false;
-        for line in lines.flatten() {
-            // Find lines that are part of the same comment block
-            if line.starts_with('#') {
-                // Are we dealing with a hashbang line? If so, then we expect
-                // the next line(s) until an empty line to be script comments.
-                if line.contains("#!/") {
-                    in_header_comments = true;
-                } else if in_header_comments {
-                    let comment = clean_comment_line(&line);
-                    if included_comments.is_empty() && comment.is_empty() {
-                        // If we don't yet have any comments, and this comment has 0 length
-                        // then we're probably dealing with a spacing line between the hashbang
-                        // and the actual file header. So we'll ignore this line.
-                    } else {
-                        included_comments.push(comment);
-                    }
-                } else {
-                    comments.push(clean_comment_line(&line));
-                }
-            } else if !line.starts_with('#') {
-                // Find lines that start a function
-                if is_function_header_line(&line) {
-                    let function = get_function(line, &comments);
-                    included_functions.push(function);
-                }
-                comments.clear();
-                in_header_comments = false;
-            }
-        }
+        let (included_comments, included_functions) = parse(lines.into_iter(), options.docstrings)
+            .map_err(|(line, message)| ScriptError::Parse {
+                path: path.to_owned(),
+                line,
+                message,
+            })?;
 
         Ok(Self {
             comment: included_comments,
             functions: included_functions,
+            shebang,
             path: executable.path.to_owned(),
             absolute_path: executable.absolute_path.to_owned(),
         })
     }
 
+    /// Gets a function by name or, failing that, by one of its aliases.
     pub fn get(&self, function_name: &str) -> Option<&Function> {
-        self.functions.iter().find(|&n| n.name == function_name)
+        self.functions
+            .iter()
+            .find(|&n| n.name == function_name)
+            .or_else(|| {
+                self.functions
+                    .iter()
+                    .find(|&n| n.aliases.iter().any(|alias| alias == function_name))
+            })
+    }
+
+    /// Gets a function by name or, failing that, by its number in `pretty_print`, counting
+    /// from 1.
+    pub fn get_by_name_or_number(&self, name_or_number: &str) -> Option<&Function> {
+        self.get(name_or_number).or_else(|| {
+            let number = name_or_number.parse::<usize>().ok()?;
+            self.functions.get(number.checked_sub(1)?)
+        })
+    }
+
+    /// Why running a function with `interpreter` might not work, if it might not: the script
+    /// doesn't have a shebang, or it names another interpreter. bash runs sh scripts fine.
+    pub fn interpreter_mismatch(&self, interpreter: &str) -> Option<String> {
+        match &self.shebang {
+            Some(shebang) if shebang == interpreter => None,
+            Some(shebang) if shebang == "sh" && interpreter == "bash" => None,
+            Some(shebang) => Some(format!(
+                "{} is a {shebang} script, but lk will source it under {interpreter}. Put '# lk: interpreter {shebang}' in the function's comment to use {shebang}.",
+                self.path.display()
+            )),
+            None => Some(format!(
+                "{} doesn't have a shebang, so lk will source it under {interpreter}.",
+                self.path.display()
+            )),
+        }
     }
 
     pub fn file_name(&self) -> String {
@@ -91,20 +353,52 @@ impl Script {
         }
     }
 
-    pub fn path(&self) -> String {
-        let mut path = self.path.clone();
-        path.pop();
-        return path.as_os_str().to_string_lossy().to_string();
+    /// The path to show the user: relative to where lk is run, unless `absolute`.
+    pub fn display_path(&self, absolute: bool) -> String {
+        let path = if absolute {
+            &self.absolute_path
+        } else {
+            &self.path
+        };
+        path.to_string_lossy().to_string()
+    }
+
+    /// Like `display_path`, but within `directory`, which it's in, e.g. `./db/backup.sh` for
+    /// `./infra/db/backup.sh` in `./infra`.
+    pub fn display_path_in(&self, absolute: bool, directory: &Path) -> String {
+        let path = PathBuf::from(self.display_path(absolute));
+        match path.strip_prefix(directory) {
+            Ok(within) => Path::new(".").join(within).to_string_lossy().to_string(),
+            Err(_) => path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Adds an empty function called `name` to the end of the script, for the user to fill in.
+    pub fn add_function(&self, name: &str) -> std::io::Result<()> {
+        let mut contents = std::fs::read_to_string(&self.absolute_path)?;
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        write!(contents, "\n{name}() {{\n    echo \"TODO: {name}\"\n}}\n").unwrap();
+        std::fs::write(&self.absolute_path, contents)
+    }
+
+    /// What to call the script in an lk command, e.g. `lk ./infra/deploy.sh build`. It's the
+    /// script's path, rather than its file name, so it can't be mistaken for another script
+    /// with the same name.
+    pub fn command_name(&self, absolute: bool) -> String {
+        shell_quote(&self.display_path(absolute))
     }
 
     pub fn working_dir_absolute(&self) -> String {
         let mut path = self.absolute_path.clone();
         path.pop();
-        return path.as_os_str().to_string_lossy().to_string();
+        path.as_os_str().to_string_lossy().to_string()
     }
 
-    pub fn pretty_print(&self) {
-        print_script_header(self);
+    pub fn pretty_print(&self, options: &PrintOptions) {
+        let palette = &options.palette;
+        print_script_header(self, options.absolute_paths, palette);
         if self.functions.is_empty() {
             print_no_functions_in_script_help();
         } else {
@@ -112,62 +406,395 @@ impl Script {
                 println!("  {}", comment_line);
             });
 
-            // Get the longest function name
-            const INDENT: usize = 2;
-            let padding = self
-                .functions
-                .iter()
-                .max_by(|x, y| x.name.len().cmp(&y.name.len()))
-                .unwrap() // Will always be Some because the name String must exist.
-                .name
-                .len()
-                + INDENT;
-            for function in &self.functions {
-                // We'll pad right so everything aligns nicely.
-                // First print the function name
-                let to_print = function
-                    .name
-                    .pad_to_width_with_alignment(padding, Alignment::Right);
-                let coloured_to_print = format!("{GREEN_FG}{to_print}{RESET_FG}");
-                if !function.comment.is_empty() {
-                    print!("{coloured_to_print}");
+            let max_name_width = termion::terminal_size()
+                .map_or(DEFAULT_TERMINAL_WIDTH, |(width, _)| width as usize)
+                / 3;
+            print!(
+                "{}",
+                self.render_functions(max_name_width, options.function_order, palette)
+            );
+        }
+        print_script_footer(self, options.absolute_paths, palette);
+    }
+
+    /// Renders the numbered functions and their comments, aligned after the function names.
+    /// Names are right-aligned to the longest one, up to `max_name_width`. A name longer than
+    /// that goes on a line of its own, so it doesn't push every other comment over.
+    /// If there are any groups then each one gets a heading, in the order they first appear,
+    /// and functions keep the numbers they'd have without them.
+    fn render_functions(
+        &self,
+        max_name_width: usize,
+        order: FunctionOrder,
+        palette: &Palette,
+    ) -> String {
+        let Palette {
+            function: function_colour,
+            number: number_colour,
+            heading: heading_colour,
+            ..
+        } = palette;
+        const INDENT: usize = 2;
+        let padding = self
+            .functions
+            .iter()
+            .map(|function| function.signature().chars().count())
+            .filter(|width| *width <= max_name_width)
+            .max()
+            .unwrap_or(0)
+            + INDENT;
+        // Functions are numbered so they can be run by number.
+        let number_width = self.functions.len().to_string().len();
+        let comment_indent = "".pad_to_width(number_width + padding);
+        // Each group's name, and its functions with their numbers.
+        type Group<'a> = (Option<&'a str>, Vec<(usize, &'a Function)>);
+        let mut groups: Vec<Group> = Vec::new();
+        for (number, function) in (1..).zip(&self.functions) {
+            let group = function.group.as_deref();
+            match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, functions)) => functions.push((number, function)),
+                None => groups.push((group, vec![(number, function)])),
+            }
+        }
+        for (_, functions) in &mut groups {
+            order.sort(functions, |(_, function)| function);
+        }
+        let has_groups = groups.iter().any(|(name, _)| name.is_some());
+        let mut rendered = String::new();
+        for (index, (group, functions)) in groups.into_iter().enumerate() {
+            if has_groups {
+                if index > 0 {
+                    rendered.push('\n');
+                }
+                let heading = group.unwrap_or(DEFAULT_GROUP);
+                writeln!(rendered, "{heading_colour}{heading}{RESET_FG}").unwrap();
+            }
+            for (number, function) in functions {
+                let number = format!("{number_colour}{number:>number_width$}{RESET_FG}");
+                let mut comment = function.description();
+                let signature = function.signature();
+                if signature.chars().count() + INDENT > padding {
+                    let indent = "".pad_to_width(INDENT);
+                    writeln!(
+                        rendered,
+                        "{number}{indent}{function_colour}{signature}{RESET_FG}"
+                    )
+                    .unwrap();
                 } else {
-                    println!("{coloured_to_print}");
+                    // We'll pad right so everything aligns nicely.
+                    let name = signature.pad_to_width_with_alignment(padding, Alignment::Right);
+                    match comment.next() {
+                        Some(line) => {
+                            writeln!(rendered, "{number}{function_colour}{name}{RESET_FG} {line}")
+                        }
+                        None => writeln!(rendered, "{number}{function_colour}{name}{RESET_FG}"),
+                    }
+                    .unwrap();
+                }
+                // Then follow up with the rest of the comment lines
+                for line in comment {
+                    writeln!(rendered, "{comment_indent} {line}").unwrap();
                 }
+            }
+        }
+        rendered
+    }
+}
 
-                // Then follow up with the comment lines
-                function.comment.iter().enumerate().for_each(|(i, line)| {
-                    if i == 0 {
-                        println!(" {line}");
-                    } else {
-                        println!(
-                            "{} {line}",
-                            "".pad_to_width_with_alignment(padding, Alignment::Right)
-                        );
-                    }
+/// Finds function names that are defined in more than one script, with the scripts that
+/// define them, in the order they were found.
+pub fn duplicate_functions(scripts: &[Script]) -> BTreeMap<&str, Vec<&Script>> {
+    let mut definitions: BTreeMap<&str, Vec<&Script>> = BTreeMap::new();
+    for script in scripts {
+        for function in &script.functions {
+            definitions.entry(&function.name).or_default().push(script);
+        }
+    }
+    definitions.retain(|_, scripts| scripts.len() > 1);
+    definitions
+}
+
+/// The deepest directory that every script's `display_path` is in, e.g. `./infra` for
+/// `./infra/deploy.sh` and `./infra/db/backup.sh`.
+pub fn common_directory(scripts: &[Script], absolute: bool) -> PathBuf {
+    let mut paths = scripts
+        .iter()
+        .map(|script| PathBuf::from(script.display_path(absolute)));
+    let Some(first) = paths.next() else {
+        return PathBuf::new();
+    };
+    let mut common = first.parent().map(Path::to_path_buf).unwrap_or_default();
+    for path in paths {
+        while !path.starts_with(&common) && common.pop() {}
+    }
+    common
+}
+
+/// Parses the lines of a script, returning its header comment and its functions.
+///
+/// The header is every comment block after the hashbang and before the first line of code,
+/// e.g. a licence then a description. Scripts without a hashbang can start with a header too.
+/// A comment block directly above a function belongs to the function instead. Directives
+/// like `# shellcheck` or `# vim:` are left out of both. With `docstrings`, a function
+/// without a comment above it can start with a docstring instead, e.g. `: 'Deploys the app'`.
+/// If a line can't be parsed then we return its number, counting from 1, and what's wrong.
+fn parse(
+    lines: impl Iterator<Item = String>,
+    docstrings: bool,
+) -> Result<(Vec<String>, Vec<Function>), (usize, String)> {
+    // `comments` accumulates a block of comments until we find a line that isn't a comment,
+    // and then they're either used or cleared.
+    let mut comments: Vec<String> = Vec::new();
+    let mut included_comments: Vec<String> = Vec::new();
+    let mut included_functions: Vec<Function> = Vec::new();
+    let mut in_header_comments: bool = false;
+    // Whether we're in the body of the last function we found. It ends at a `}` at the start
+    // of a line. If the `}` is indented we stop at the next function instead.
+    let mut in_body: bool = false;
+    // The `# lk:` directives in the comment block we're in, for the next function.
+    let mut directives = Directives::default();
+    // The group from the last `# lk: group` directive, which the functions after it are in.
+    let mut group: Option<String> = None;
+    // Where we are in the docstring of the function whose body we're in, if it might have one.
+    let mut docstring = Docstring::None;
+    for (index, line) in lines.enumerate() {
+        // Scripts saved on Windows can have a byte order mark, and `\r\n` line endings.
+        let line = line
+            .trim_start_matches('\u{feff}')
+            .trim_end_matches('\r')
+            .to_owned();
+        if index == 0 {
+            in_header_comments = line.starts_with('#');
+            // Are we dealing with a hashbang line, however it's spaced? The comment blocks
+            // after it, until the first line of code, are the header.
+            if line.starts_with("#!") {
+                continue;
+            }
+        }
+        if in_body {
+            if line.starts_with('}') {
+                in_body = false;
+                docstring = Docstring::None;
+            } else if !is_function_header_line(&line) {
+                let function = included_functions.last_mut().unwrap();
+                function.body.push(line.trim_end().to_owned());
+                if let Some(comment) = docstring.read(&line) {
+                    function.comment = comment;
+                }
+            }
+        }
+        // Find lines that are part of the same comment block
+        if line.starts_with('#') {
+            if let Some(name) = clean_comment_line(&line)
+                .strip_prefix("lk: group")
+                .filter(|name| name.is_empty() || name.starts_with(char::is_whitespace))
+            {
+                // An empty group ends the last one.
+                let name = name.trim().trim_matches('"').trim();
+                group = (!name.is_empty()).then(|| name.to_string());
+            } else if directives.parse(&clean_comment_line(&line)) {
+                // It's for the next function.
+            } else if is_directive(&line) {
+                // Not for humans, so we don't want it in the header or a function's comments.
+            } else {
+                comments.push(clean_comment_line(&line));
+            }
+        } else if is_function_header_line(&line) {
+            // Find lines that start a function
+            let Directives {
+                ignore,
+                aliases,
+                shellopts,
+                interpreter,
+            } = std::mem::take(&mut directives);
+            if ignore {
+                log::info!("Ignoring the function on line {}", index + 1);
+                in_body = false;
+            } else {
+                let function = get_function(line, &comments).map_err(|err| (index + 1, err))?;
+                // Only a function that's all on one line has its body already.
+                in_body = function.body.is_empty();
+                docstring = if docstrings && in_body && function.comment.is_empty() {
+                    Docstring::Looking
+                } else {
+                    Docstring::None
+                };
+                included_functions.push(Function {
+                    line: index + 1,
+                    group: group.clone(),
+                    aliases,
+                    shellopts,
+                    interpreter,
+                    ..function
                 });
             }
+            comments.clear();
+            in_header_comments = false;
+        } else {
+            if in_header_comments {
+                add_header_block(&mut included_comments, &comments);
+                // Code ends the header, but blank lines just separate its blocks.
+                in_header_comments = line.trim().is_empty();
+            }
+            comments.clear();
+            directives = Directives::default();
         }
     }
+    if in_header_comments {
+        // The script is nothing but a header.
+        add_header_block(&mut included_comments, &comments);
+    }
+    Ok((included_comments, included_functions))
 }
 
-/// Gets a `Function` from a line that contains a function name. Uses accumulated comments.
-fn get_function(line: String, comments_found_so_far: &[String]) -> Function {
-    let name = line.split("()").next();
-    match name {
-        Some(actual_name) => Function {
-            name: String::from(actual_name.trim()),
-            comment: comments_found_so_far
-                .iter()
-                .map(|comment| comment.to_owned())
-                .collect(),
-        },
-        None => {
-            panic!("There is some kind of formatting error with the name of this function:");
+/// The `# lk:` directives that apply to the function after them.
+#[derive(Default)]
+struct Directives {
+    /// `# lk: ignore`, so we skip the function.
+    ignore: bool,
+    aliases: Vec<String>,
+    shellopts: Option<String>,
+    interpreter: Option<String>,
+}
+
+impl Directives {
+    /// Takes in `comment` if it's one of these directives, returning whether it was.
+    fn parse(&mut self, comment: &str) -> bool {
+        let Some(directive) = comment.strip_prefix("lk: ") else {
+            return false;
+        };
+        let (name, value) = directive.split_once(' ').unwrap_or((directive, ""));
+        let value = value.trim();
+        match name {
+            "ignore" => self.ignore = true,
+            "alias" => self.aliases.extend(
+                value
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|name| !name.is_empty())
+                    .map(String::from),
+            ),
+            "shellopts" if !value.is_empty() => self.shellopts = Some(value.to_string()),
+            "interpreter" if !value.is_empty() => self.interpreter = Some(value.to_string()),
+            _ => return false,
         }
+        true
     }
 }
 
+/// Reading a docstring, i.e. a string passed to the `:` no-op, from the start of a function's
+/// body, e.g. `: 'Deploys the app'`. It can span lines, like any other string.
+enum Docstring {
+    /// The function can't have one, or we've gone past where it would be.
+    None,
+    /// We haven't got to the function's first statement yet.
+    Looking,
+    /// We're in one that started with `quote`, and these are its lines so far.
+    Reading { quote: char, lines: Vec<String> },
+}
+
+impl Docstring {
+    /// Takes in the next line of the function's body, returning the docstring once it's all
+    /// been read.
+    fn read(&mut self, line: &str) -> Option<Vec<String>> {
+        let line = line.trim();
+        let (quote, mut lines, text) = match std::mem::replace(self, Docstring::None) {
+            Docstring::None => return None,
+            Docstring::Looking if line.is_empty() => {
+                *self = Docstring::Looking;
+                return None;
+            }
+            Docstring::Looking => {
+                let rest = line
+                    .strip_prefix(':')
+                    .filter(|rest| rest.starts_with(' '))?;
+                let rest = rest.trim_start();
+                let quote = rest.chars().next().filter(|&c| c == '\'' || c == '"')?;
+                (quote, vec![], &rest[1..])
+            }
+            Docstring::Reading { quote, lines } => (quote, lines, line),
+        };
+        match closing_quote(text, quote) {
+            Some(end) => {
+                lines.push(text[..end].trim().to_owned());
+                let start = lines.iter().position(|line| !line.is_empty())?;
+                let end = lines.iter().rposition(|line| !line.is_empty())?;
+                let unescape = |line: &String| match quote {
+                    '"' => line.replace("\\\"", "\""),
+                    _ => line.to_owned(),
+                };
+                Some(lines[start..=end].iter().map(unescape).collect())
+            }
+            None => {
+                lines.push(text.trim().to_owned());
+                *self = Docstring::Reading { quote, lines };
+                None
+            }
+        }
+    }
+}
+
+/// Where the string that `quote` opened ends in `text`. A `"` can be escaped, but bash
+/// doesn't let a `'` be.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if c == quote && !escaped {
+            return Some(i);
+        }
+        escaped = quote == '"' && c == '\\' && !escaped;
+    }
+    None
+}
+
+/// Adds a block of comments to the header, separated from any previous block by an empty line.
+fn add_header_block(header: &mut Vec<String>, block: &[String]) {
+    // Empty comment lines at the edges of a block are just spacing, e.g. after the hashbang.
+    let start = block.iter().position(|line| !line.is_empty());
+    let end = block.iter().rposition(|line| !line.is_empty());
+    if let (Some(start), Some(end)) = (start, end) {
+        if !header.is_empty() {
+            header.push(String::from(""));
+        }
+        header.extend_from_slice(&block[start..=end]);
+    }
+}
+
+/// Is this a comment for tools, like shellcheck or an editor, rather than for humans?
+fn is_directive(line: &str) -> bool {
+    let comment = clean_comment_line(line);
+    ["shellcheck ", "vim:", "emacs:", "-*-", "lk:"]
+        .iter()
+        .any(|directive| comment.starts_with(directive))
+}
+
+/// Gets a `Function` from a line that contains a function name. Uses accumulated comments.
+/// If there are no comments above the function, a comment after its opening brace describes
+/// it instead, e.g. `deploy() { # Deploys`. A function that's all on one line has its body too.
+fn get_function(line: String, comments_found_so_far: &[String]) -> Result<Function, String> {
+    let name = function_name(&line);
+    if name.is_empty() {
+        return Err(format!("There's a function with no name: {}", line.trim()));
+    }
+    let (code, trailing_comment) = split_trailing_comment(&line);
+    let comment = match trailing_comment {
+        Some(comment) if comments_found_so_far.is_empty() => vec![comment.to_owned()],
+        _ => comments_found_so_far.to_vec(),
+    };
+    let body = one_line_body(code)
+        .map(|body| vec![body.to_owned()])
+        .unwrap_or_default();
+    Ok(Function {
+        name: String::from(name),
+        line: 0,
+        comment,
+        body,
+        group: None,
+        aliases: vec![],
+        shellopts: None,
+        interpreter: None,
+    })
+}
+
 // The output is wrapped in a Result to allow matching on errors
 // Returns an Iterator to the Reader of the lines of the file.
 // https://doc.rust-lang.org/rust-by-example/std_misc/file/read_lines.html
@@ -179,13 +806,69 @@ where
     Ok(std::io::BufReader::new(file).lines())
 }
 
+/// Whether `name` can be a function that lk finds, e.g. from `Script::add_function`. Names
+/// starting with `_` are private, so lk leaves them out.
+pub fn is_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The name of the function that `line` starts, however it's defined: `name() {`,
+/// `name () {`, `function name() {` or `function name {`. It's empty for `() {`.
+fn function_name(line: &str) -> &str {
+    let line = line.trim();
+    let line = match line.split_once(char::is_whitespace) {
+        Some(("function", rest)) => rest,
+        _ => line,
+    };
+    // There's always something before the first `(` or `{`, even if it's nothing.
+    line.split(['(', '{']).next().unwrap_or_default().trim()
+}
+
+/// Splits a function's opening line into its code and the comment after its opening brace,
+/// if there is one, e.g. `deploy() {` and `Deploys` from `deploy() { # Deploys`. Like bash,
+/// a `#` only starts a comment at the start of a word.
+fn split_trailing_comment(line: &str) -> (&str, Option<&str>) {
+    let Some(brace) = line.find('{') else {
+        return (line, None);
+    };
+    let after_brace = &line[brace + 1..];
+    match after_brace
+        .char_indices()
+        .find(|&(i, c)| c == '#' && after_brace[..i].ends_with(char::is_whitespace))
+    {
+        Some((i, _)) => {
+            let comment = after_brace[i..].trim_start_matches('#').trim();
+            (
+                &line[..brace + 1 + i],
+                Some(comment).filter(|comment| !comment.is_empty()),
+            )
+        }
+        None => (line, None),
+    }
+}
+
+/// The body of a function that's all on one line, e.g. `echo hi;` from `hi() { echo hi; }`.
+fn one_line_body(code: &str) -> Option<&str> {
+    let code = code.trim_end().strip_suffix(';').unwrap_or(code).trim_end();
+    let (_, body) = code.strip_suffix('}')?.split_once('{')?;
+    Some(body.trim()).filter(|body| !body.is_empty())
+}
+
 fn is_function_header_line(line: &str) -> bool {
-    if line.trim().starts_with('_') {
+    if function_name(line).starts_with('_') {
         false
     } else {
-        Regex::new(r"^.*\(\).*\{$")
+        let (code, _) = split_trailing_comment(line);
+        // With parens, or with the `function` keyword and without them. The body can be on
+        // the same line, e.g. `hi() { echo hi; }`.
+        Regex::new(r"^[^{]*\(\)[^{]*\{(\s.*\})?\s*;?\s*$")
             .unwrap()
-            .is_match(line.as_bytes())
+            .is_match(code.as_bytes())
+            || Regex::new(r"^\s*function\s+[^\s(){}]+\s*\{(\s.*\})?\s*;?\s*$")
+                .unwrap()
+                .is_match(code.as_bytes())
     }
 }
 
@@ -198,6 +881,8 @@ fn clean_comment_line(line: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::executables::{Executables, Ignores};
+    use std::os::unix::fs::PermissionsExt;
 
     #[test]
     fn test_clean_comment_line() {
@@ -213,6 +898,26 @@ mod tests {
         assert_eq!(clean_comment_line("##   "), "");
     }
 
+    #[test]
+    fn test_is_directive() {
+        assert!(is_directive("# shellcheck disable=SC2086"));
+        assert!(is_directive("#shellcheck source=./lib.sh"));
+        assert!(is_directive("# vim: set ft=sh:"));
+        assert!(is_directive("# emacs: -*- mode: sh -*-"));
+        assert!(is_directive("# -*- mode: sh -*-"));
+        assert!(!is_directive("# Run shellcheck over everything"));
+        assert!(!is_directive("# vim is great"));
+        assert!(!is_directive("#"));
+    }
+
+    #[test]
+    fn test_function_comments_ignore_directives() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\n# Does things\n# shellcheck disable=SC2086\n# vim: set ft=sh:\nfoo() {\n}\n",
+        );
+        assert_eq!(functions[0].comment, vec!["Does things"]);
+    }
+
     #[test]
     fn test_get_function() {
         // Given
@@ -220,7 +925,7 @@ mod tests {
         let comments = vec![String::from("First line"), String::from("Second line")];
 
         // When
-        let function = get_function(line, &comments);
+        let function = get_function(line, &comments).unwrap();
 
         // Then
         assert_eq!(function.name, "some_function");
@@ -234,13 +939,689 @@ mod tests {
         let comments = vec![String::from("First line"), String::from("Second # line")];
 
         // When
-        let function = get_function(line, &comments);
+        let function = get_function(line, &comments).unwrap();
 
         // Then
         assert_eq!(function.name, "some_function");
         assert_eq!(function.comment, vec!["First line", "Second # line"]);
     }
 
+    /// Renders the script's functions without colours, which depend on the terminal.
+    fn render_functions(script: &str, max_name_width: usize) -> Vec<String> {
+        render_functions_in_order(script, max_name_width, FunctionOrder::Source)
+    }
+
+    fn render_functions_in_order(
+        script: &str,
+        max_name_width: usize,
+        order: FunctionOrder,
+    ) -> Vec<String> {
+        let (comment, functions) = parse_script(script);
+        let script = Script {
+            path: PathBuf::from("./deploy.sh"),
+            absolute_path: PathBuf::from("/deploy.sh"),
+            comment,
+            functions,
+            shebang: None,
+        };
+        let escape_codes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        escape_codes
+            .replace_all(
+                &script.render_functions(max_name_width, order, &Palette::default()),
+                "",
+            )
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_render_functions() {
+        let rendered = render_functions(
+            "#!/bin/bash\n\n# Builds\n# Quickly\nbuild() {\n}\n\ntest() {\n}\n",
+            26,
+        );
+        assert_eq!(
+            rendered,
+            vec!["1  build Builds", "         Quickly", "2   test"]
+        );
+    }
+
+    #[test]
+    fn test_render_functions_alphabetically() {
+        let script = "#!/bin/bash\n\ntest() {\n}\n\nDeploy() {\n}\n\n# lk: group \"Release\"\nrollback() {\n}\n\nbuild() {\n}\n";
+        // Each group is sorted, and the functions keep their numbers.
+        assert_eq!(
+            render_functions_in_order(script, 26, FunctionOrder::Alpha),
+            vec![
+                "Other",
+                "2    Deploy",
+                "1      test",
+                "",
+                "Release",
+                "4     build",
+                "3  rollback"
+            ]
+        );
+        assert_eq!(render_functions(script, 26)[1], "1      test");
+    }
+
+    #[test]
+    fn test_parse_function_order() {
+        assert_eq!("alpha".parse(), Ok(FunctionOrder::Alpha));
+        assert_eq!("none".parse(), Ok(FunctionOrder::Source));
+        assert!("size".parse::<FunctionOrder>().is_err());
+    }
+
+    #[test]
+    fn test_parse_label_format() {
+        let format: LabelFormat = "{path}/{file}::{function}!".parse().unwrap();
+        assert_eq!(
+            format.parts(),
+            [
+                LabelPart::Path,
+                LabelPart::Text("/".to_string()),
+                LabelPart::File,
+                LabelPart::Text("::".to_string()),
+                LabelPart::Function,
+                LabelPart::Text("!".to_string()),
+            ]
+        );
+        assert_eq!(
+            format.script_parts(),
+            [
+                LabelPart::Path,
+                LabelPart::Text("/".to_string()),
+                LabelPart::File
+            ]
+        );
+        assert_eq!(String::from(format), "{path}/{file}::{function}!");
+
+        let format: LabelFormat = "{function} in {file}".parse().unwrap();
+        assert_eq!(format.script_parts(), [LabelPart::File]);
+        assert_eq!(
+            LabelFormat::default().parts().last(),
+            Some(&LabelPart::Function)
+        );
+
+        assert!("{path}/{file} - {name}".parse::<LabelFormat>().is_err());
+        assert!("{file} - {function".parse::<LabelFormat>().is_err());
+        assert!("{path}/{file}".parse::<LabelFormat>().is_err());
+        assert_eq!(
+            "{function}".parse::<LabelFormat>(),
+            Err("'{function}' needs a {file} or {path}, to label scripts with".to_string())
+        );
+    }
+
+    #[test]
+    fn test_signature() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\n# Deploys\n# @param env Where to\n# @param version\ndeploy() {\n}\n\n# Builds\nbuild() {\n}\n",
+        );
+        assert_eq!(functions[0].params(), vec!["env", "version"]);
+        assert_eq!(functions[0].signature(), "deploy <env> <version>");
+        assert!(functions[1].params().is_empty());
+        assert_eq!(functions[1].signature(), "build");
+    }
+
+    #[test]
+    fn test_render_functions_with_params() {
+        let rendered = render_functions(
+            "#!/bin/bash\n\n# @param env\n# Deploys\ndeploy() {\n}\n\ntest() {\n}\n",
+            26,
+        );
+        assert_eq!(rendered, vec!["1  deploy <env> Deploys", "2          test"]);
+    }
+
+    #[test]
+    fn test_render_functions_with_a_long_name() {
+        let long_name = "a".repeat(80);
+        let rendered = render_functions(
+            &format!("#!/bin/bash\n\n# Builds\nbuild() {{\n}}\n\n# Very long\n# Really\n{long_name}() {{\n}}\n"),
+            26,
+        );
+        // The long name doesn't push the other comments over.
+        assert_eq!(
+            rendered,
+            vec![
+                "1  build Builds".to_string(),
+                format!("2  {long_name}"),
+                "         Very long".to_string(),
+                "         Really".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_groups() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\ntest() {\n}\n\n# lk: group \"Deploy\"\n\n# Builds\nbuild() {\n}\nrelease() {\n}\n# lk: group\nclean() {\n}\n",
+        );
+        let groups: Vec<(&str, Option<&str>)> = functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.group.as_deref()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("test", None),
+                ("build", Some("Deploy")),
+                ("release", Some("Deploy")),
+                ("clean", None),
+            ]
+        );
+        assert_eq!(functions[1].comment, vec!["Builds"]);
+    }
+
+    #[test]
+    fn test_group_needs_a_space() {
+        let (_, functions) = parse_script("#!/bin/bash\n\n# lk: groupDeploy\nbuild() {\n}\n");
+        assert_eq!(functions[0].group, None);
+    }
+
+    #[test]
+    fn test_render_groups() {
+        let rendered = render_functions(
+            "#!/bin/bash\n\ntest() {\n}\n# lk: group Deploy\n# Builds\nbuild() {\n}\n# lk: group\nclean() {\n}\n",
+            26,
+        );
+        assert_eq!(
+            rendered,
+            vec![
+                "Other",
+                "1   test",
+                "3  clean",
+                "",
+                "Deploy",
+                "2  build Builds",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_no_functions() {
+        assert!(render_functions("#!/bin/bash\n", 26).is_empty());
+    }
+
+    #[test]
+    fn test_get_by_name_or_number() {
+        let (comment, functions) =
+            parse_script("#!/bin/bash\n\nbuild() {\n}\n\ndeploy() {\n}\n\n2() {\n}\n");
+        let script = Script {
+            path: PathBuf::from("./deploy.sh"),
+            absolute_path: PathBuf::from("/deploy.sh"),
+            comment,
+            functions,
+            shebang: None,
+        };
+        let name = |name_or_number| {
+            script
+                .get_by_name_or_number(name_or_number)
+                .map(|function| function.name.as_str())
+        };
+        assert_eq!(name("deploy"), Some("deploy"));
+        assert_eq!(name("1"), Some("build"));
+        assert_eq!(name("3"), Some("2"));
+        // A function's name wins over its number.
+        assert_eq!(name("2"), Some("2"));
+        assert_eq!(name("0"), None);
+        assert_eq!(name("4"), None);
+        assert_eq!(name("-1"), None);
+    }
+
+    #[test]
+    fn test_shebang() {
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env bash"),
+            Some("bash".to_string())
+        );
+        assert_eq!(
+            shebang_interpreter("#!/bin/zsh -e"),
+            Some("zsh".to_string())
+        );
+        assert_eq!(
+            shebang_interpreter("#! /usr/bin/env -S bash -e"),
+            Some("bash".to_string())
+        );
+        assert_eq!(shebang_interpreter("# A comment"), None);
+        assert_eq!(shebang_interpreter("#!"), None);
+
+        let script = |shebang: Option<&str>| Script {
+            path: PathBuf::from("./deploy.sh"),
+            absolute_path: PathBuf::from("/deploy.sh"),
+            comment: vec![],
+            functions: vec![],
+            shebang: shebang.map(String::from),
+        };
+        assert_eq!(script(Some("bash")).interpreter_mismatch("bash"), None);
+        assert_eq!(script(Some("sh")).interpreter_mismatch("bash"), None);
+        assert!(script(Some("bash")).interpreter_mismatch("sh").is_some());
+        assert!(script(Some("zsh"))
+            .interpreter_mismatch("bash")
+            .unwrap()
+            .contains("# lk: interpreter zsh"));
+        assert!(script(None).interpreter_mismatch("bash").is_some());
+    }
+
+    #[test]
+    fn test_display_path() {
+        let script = Script {
+            path: PathBuf::from("./bin/deploy.sh"),
+            absolute_path: PathBuf::from("/work/api/bin/deploy.sh"),
+            comment: vec![],
+            functions: vec![],
+            shebang: None,
+        };
+        assert_eq!(script.display_path(false), "./bin/deploy.sh");
+        assert_eq!(script.display_path(true), "/work/api/bin/deploy.sh");
+        assert_eq!(script.command_name(false), "./bin/deploy.sh");
+    }
+
+    #[test]
+    fn test_common_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b/c");
+        std::fs::create_dir_all(nested.join("d")).unwrap();
+        for (path, function) in [("deploy.sh", "build"), ("d/backup.sh", "backup")] {
+            let path = nested.join(path);
+            std::fs::write(&path, format!("#!/bin/bash\n{function}() {{\n}}\n")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let root = dir.path().to_string_lossy();
+        let executables = Executables::new(&root, &Ignores::default(), &[]);
+        let scripts: Vec<Script> = executables
+            .executables
+            .iter()
+            .map(|executable| Script::new(executable, &ParseOptions::default()).unwrap())
+            .collect();
+
+        let common = common_directory(&scripts, false);
+        assert_eq!(common, nested);
+        let mut labels: Vec<String> = scripts
+            .iter()
+            .map(|script| script.display_path_in(false, &common))
+            .collect();
+        labels.sort();
+        assert_eq!(labels, vec!["./d/backup.sh", "./deploy.sh"]);
+        // Only the labels are shorter, so the scripts are still found where they are.
+        for script in &scripts {
+            assert!(script.absolute_path.starts_with(&nested));
+            assert!(script.absolute_path.is_file());
+            assert_eq!(
+                Script::new(
+                    &Executable::from_absolute_path(&script.absolute_path),
+                    &ParseOptions::default()
+                )
+                .unwrap()
+                .functions
+                .len(),
+                1
+            );
+        }
+        assert_eq!(
+            common_directory(&scripts[..1], true),
+            scripts[0].absolute_path.parent().unwrap()
+        );
+        assert_eq!(common_directory(&[], false), PathBuf::new());
+    }
+
+    #[test]
+    fn test_function_body() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\nbuild() {\n    cargo build\n\n    echo done  \n}\n\nval=1\n\n# Deploys\ndeploy() {\n  if true; then\n    echo deploying\n  fi\n  }\ntest() {\n    cargo test\n}\n",
+        );
+        assert_eq!(
+            functions[0].body,
+            vec!["    cargo build", "", "    echo done"]
+        );
+        // Without a `}` at the start of a line, the next function ends the body.
+        assert_eq!(
+            functions[1].body,
+            vec!["  if true; then", "    echo deploying", "  fi", "  }"]
+        );
+        assert_eq!(functions[2].body, vec!["    cargo test"]);
+    }
+
+    #[test]
+    fn test_add_function() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.sh");
+        // Without a newline at the end.
+        std::fs::write(&path, "#!/bin/bash\n\nbuild() {\n}").unwrap();
+        let executable = Executable::from_absolute_path(&path);
+        Script::new(&executable, &ParseOptions::default())
+            .unwrap()
+            .add_function("deploy")
+            .unwrap();
+        let script = Script::new(&executable, &ParseOptions::default()).unwrap();
+        assert_eq!(script.functions.len(), 2);
+        assert_eq!(script.functions[1].name, "deploy");
+        assert_eq!(script.functions[1].body, vec!["    echo \"TODO: deploy\""]);
+    }
+
+    #[test]
+    fn test_is_function_name() {
+        assert!(is_function_name("deploy"));
+        assert!(is_function_name("deploy_to-prod2"));
+        assert!(!is_function_name(""));
+        assert!(!is_function_name("_private"));
+        assert!(!is_function_name("2fast"));
+        assert!(!is_function_name("rm -rf"));
+    }
+
+    #[test]
+    fn test_get_function_with_the_function_keyword() {
+        for line in [
+            "function deploy {",
+            "function deploy() {",
+            "  function   deploy ()  {  ",
+            "function\tdeploy {",
+        ] {
+            assert!(is_function_header_line(line), "{line}");
+            assert_eq!(get_function(line.to_string(), &[]).unwrap().name, "deploy");
+        }
+        // Private functions are still left out.
+        assert!(!is_function_header_line("function _helper {"));
+        assert!(!is_function_header_line("function _helper() {"));
+        // Without parens it needs the keyword, or it's just a block.
+        assert!(!is_function_header_line("deploy {"));
+        assert!(!is_function_header_line("function {"));
+    }
+
+    #[test]
+    fn test_parse_function_keyword() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\n# Deploys\nfunction deploy {\n    echo deploying\n}\n\nfunction build() {\n}\n",
+        );
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["deploy", "build"]);
+        assert_eq!(functions[0].comment, vec!["Deploys"]);
+        assert_eq!(functions[0].body, vec!["    echo deploying"]);
+    }
+
+    #[test]
+    fn test_function_lines() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\n# Deploys\ndeploy() {\n    echo deploying\n}\n\n# lk: alias b\nfunction build {\n}\n",
+        );
+        let lines: Vec<usize> = functions.iter().map(|f| f.line).collect();
+        assert_eq!(lines, vec![4, 9]);
+    }
+
+    #[test]
+    fn test_trailing_comment() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\ndeploy() {   # Deploys the {app}\n    echo deploying\n}\n\n# Builds\nbuild() { # quickly\n}\n\nfunction test { #\n}\n",
+        );
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["deploy", "build", "test"]);
+        assert_eq!(functions[0].comment, vec!["Deploys the {app}"]);
+        assert_eq!(functions[0].body, vec!["    echo deploying"]);
+        // A comment above the function takes precedence.
+        assert_eq!(functions[1].comment, vec!["Builds"]);
+        assert!(functions[2].comment.is_empty());
+        // Not a comment, because it's not the start of a word.
+        assert!(!is_function_header_line("deploy() {#"));
+    }
+
+    #[test]
+    fn test_one_line_functions() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\nhi(){ echo hi; }\n\necho not a body\n\nfunction bye { echo bye; } # Says bye\n\ndeploy() {\n    echo deploying\n}\n",
+        );
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["hi", "bye", "deploy"]);
+        assert_eq!(functions[0].body, vec!["echo hi;"]);
+        assert_eq!(functions[1].body, vec!["echo bye;"]);
+        assert_eq!(functions[1].comment, vec!["Says bye"]);
+        assert_eq!(functions[2].body, vec!["    echo deploying"]);
+        assert!(is_function_header_line("f(){ echo; }"));
+        assert_eq!(one_line_body("f(){ echo; }"), Some("echo;"));
+        assert!(is_function_header_line("f() { :; }"));
+        assert_eq!(one_line_body("f() { :; }"), Some(":;"));
+        assert_eq!(function_name("f() { :; }"), "f");
+        // Bash needs a space after the `{`.
+        assert!(!is_function_header_line("f(){echo;}"));
+        // A brace group that isn't a function still isn't one.
+        assert!(!is_function_header_line("{ echo hi; }"));
+    }
+
+    #[test]
+    fn test_get_function_without_a_name() {
+        assert!(get_function(String::from("  () {"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let lines = "#!/bin/bash\n\nfoo() {\n}\n() {\n}\n"
+            .lines()
+            .map(String::from);
+        let (line, _) = parse(lines, false).unwrap_err();
+        assert_eq!(line, 5);
+    }
+
+    #[test]
+    fn test_new_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let executable = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            Executable {
+                short_name: name.to_string(),
+                root: dir.path().to_string_lossy().to_string(),
+                absolute_path: path.clone(),
+                path,
+            }
+        };
+        let not_utf8 = executable("latin1.sh", b"#!/bin/bash\n# Caf\xe9\nfoo() {\n}\n");
+        assert!(matches!(
+            Script::new(&not_utf8, &ParseOptions::default()),
+            Err(ScriptError::Read { .. })
+        ));
+        let no_name = executable("no_name.sh", b"#!/bin/bash\n() {\n}\n");
+        match Script::new(&no_name, &ParseOptions::default()) {
+            Err(err @ ScriptError::Parse { line: 2, .. }) => {
+                assert!(err
+                    .to_string()
+                    .ends_with("line 2: There's a function with no name: () {"))
+            }
+            other => panic!("Expected a parse error, got {other:?}"),
+        }
+    }
+
+    fn parse_script(script: &str) -> (Vec<String>, Vec<Function>) {
+        parse(script.lines().map(String::from), false).unwrap()
+    }
+
+    fn parse_script_with_docstrings(script: &str) -> Vec<Function> {
+        parse(script.lines().map(String::from), true).unwrap().1
+    }
+
+    #[test]
+    fn test_docstrings() {
+        let script = "#!/bin/bash\n\ndeploy() {\n    : 'Deploys the app'\n    echo deploying\n}\n\nbuild() {\n\n    : \"Builds the app.\n\n    Then tests it, with \\\"make test\\\".\n    \"\n    make\n}\n";
+        let functions = parse_script_with_docstrings(script);
+        assert_eq!(functions[0].comment, vec!["Deploys the app"]);
+        // The docstring is still part of the body.
+        assert_eq!(functions[0].body[0], "    : 'Deploys the app'");
+        assert_eq!(
+            functions[1].comment,
+            vec!["Builds the app.", "", "Then tests it, with \"make test\"."]
+        );
+        // They're opt-in.
+        let (_, functions) = parse_script(script);
+        assert!(functions.iter().all(|function| function.comment.is_empty()));
+    }
+
+    #[test]
+    fn test_new_reads_docstrings_if_asked_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.sh");
+        std::fs::write(&path, "deploy() {\n    : 'Deploys the app'\n}\n").unwrap();
+        let executable = Executable::from_absolute_path(&path);
+        let comment = |docstrings| {
+            Script::new(&executable, &ParseOptions { docstrings })
+                .unwrap()
+                .functions[0]
+                .comment
+                .to_owned()
+        };
+        assert_eq!(comment(true), vec!["Deploys the app"]);
+        assert!(comment(false).is_empty());
+    }
+
+    #[test]
+    fn test_docstrings_only_come_first() {
+        let functions = parse_script_with_docstrings(
+            "# Deploys\ndeploy() {\n    : 'Not this'\n}\nbuild() {\n    make\n    : 'Nor this'\n}\ntest() {\n    :\n}\nlint() {\n    : 'Never closed\n}\n",
+        );
+        // Comments above the function take precedence.
+        assert_eq!(functions[0].comment, vec!["Deploys"]);
+        assert!(functions[1..]
+            .iter()
+            .all(|function| function.comment.is_empty()));
+    }
+
+    #[test]
+    fn test_header() {
+        let (comment, functions) = parse_script(
+            "#!/usr/bin/env bash\n#\n# First line\n# Second line\n\nval=1\n\n# Comment\nfoo() {\n}\n",
+        );
+        assert_eq!(comment, vec!["First line", "Second line"]);
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+    }
+
+    #[test]
+    fn test_header_without_comments() {
+        let (comment, functions) = parse_script("#!/bin/bash\nset -e\n# Comment\nfoo() {\n}\n");
+        assert!(comment.is_empty());
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+
+        let (comment, functions) = parse_script("#!/bin/bash\n# Comment\nfoo() {\n}\n");
+        assert!(comment.is_empty());
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+    }
+
+    #[test]
+    fn test_header_with_many_blocks() {
+        let (comment, functions) = parse_script(
+            "#!/bin/bash\n# Copyright me\n# MIT licence\n\n#\n# Does things\n#\n\n# Comment\nfoo() {\n}\n",
+        );
+        assert_eq!(
+            comment,
+            vec!["Copyright me", "MIT licence", "", "Does things"]
+        );
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+    }
+
+    #[test]
+    fn test_header_ends_at_code() {
+        let (comment, _) = parse_script("#!/bin/bash\n# Header\nset -e\n\n# Not header\nval=1\n");
+        assert_eq!(comment, vec!["Header"]);
+    }
+
+    #[test]
+    fn test_header_ignores_directives() {
+        let (comment, _) = parse_script(
+            "#!/bin/bash\n# shellcheck disable=SC2086\n# Header\n# vim: set ft=sh:\n\nfoo() {\n}\n",
+        );
+        assert_eq!(comment, vec!["Header"]);
+    }
+
+    #[test]
+    fn test_header_after_any_hashbang() {
+        for hashbang in ["#! /bin/bash", "#!/usr/bin/env -S bash", "#!env bash"] {
+            let (comment, _) = parse_script(&format!("{hashbang}\n# Header\n\nfoo() {{\n}}\n"));
+            assert_eq!(comment, vec!["Header"], "after {hashbang}");
+        }
+    }
+
+    #[test]
+    fn test_header_without_hashbang() {
+        let (comment, functions) = parse_script("# Header\n# More\n\n# Comment\nfoo() {\n}\n");
+        assert_eq!(comment, vec!["Header", "More"]);
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+
+        // Without a gap the comment is the function's.
+        let (comment, functions) = parse_script("# Comment\nfoo() {\n}\n");
+        assert!(comment.is_empty());
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+    }
+
+    #[test]
+    fn test_windows_line_endings() {
+        let (comment, functions) =
+            parse_script("\u{feff}#!/bin/bash\r\n# Header\r\n\r\n# Comment\r\nfoo() {\r\n}\r\n");
+        assert_eq!(comment, vec!["Header"]);
+        assert_eq!(functions[0].name, "foo");
+        assert_eq!(functions[0].comment, vec!["Comment"]);
+    }
+
+    #[test]
+    fn test_ignore_directive() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\n# Internal\n# lk: ignore\nhelper() {\n    echo help\n}\n\n# Builds\nbuild() {\n    helper\n}\n",
+        );
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].name, "build");
+        assert_eq!(functions[0].comment, vec!["Builds"]);
+        assert_eq!(functions[0].body, vec!["    helper"]);
+
+        // It has to be directly above the function.
+        let (_, functions) = parse_script("#!/bin/bash\n\n# lk: ignore\n\nbuild() {\n}\n");
+        assert_eq!(functions.len(), 1);
+        assert!(functions[0].comment.is_empty());
+    }
+
+    #[test]
+    fn test_aliases() {
+        let (comment, functions) = parse_script(
+            "#!/bin/bash\n\n# Deploys\n# lk: alias dep, d\ndeploy() {\n}\n\n# lk: alias deploy\nd2() {\n}\n\nbuild() {\n}\n",
+        );
+        assert_eq!(functions[0].aliases, vec!["dep", "d"]);
+        assert_eq!(functions[0].comment, vec!["Deploys"]);
+        assert!(functions[2].aliases.is_empty());
+        let script = Script {
+            path: PathBuf::from("./deploy.sh"),
+            absolute_path: PathBuf::from("/deploy.sh"),
+            comment,
+            functions,
+            shebang: None,
+        };
+        assert_eq!(script.get("d").unwrap().name, "deploy");
+        assert_eq!(script.get("dep").unwrap().name, "deploy");
+        // A function's name beats another function's alias.
+        assert_eq!(script.get("deploy").unwrap().name, "deploy");
+        assert!(script.get("b").is_none());
+    }
+
+    #[test]
+    fn test_shellopts_and_interpreter() {
+        let (_, functions) = parse_script(
+            "#!/bin/bash\n\n# Deploys\n# lk: shellopts -euo pipefail\n# lk: interpreter zsh\ndeploy() {\n}\n\nbuild() {\n}\n",
+        );
+        assert_eq!(functions[0].shellopts.as_deref(), Some("-euo pipefail"));
+        assert_eq!(functions[0].interpreter.as_deref(), Some("zsh"));
+        assert_eq!(functions[0].comment, vec!["Deploys"]);
+        // They're only for the function straight after them.
+        assert_eq!(functions[1].shellopts, None);
+        assert_eq!(functions[1].interpreter, None);
+
+        // Without a value they're ignored, like other directives.
+        let (_, functions) =
+            parse_script("#!/bin/bash\n\n# lk: interpreter\n# Builds\nbuild() {\n}\n");
+        assert_eq!(functions[0].interpreter, None);
+        assert_eq!(functions[0].comment, vec!["Builds"]);
+    }
+
+    #[test]
+    fn test_only_header() {
+        let (comment, functions) =
+            parse_script("#!/usr/bin/env bash\n#\n# No functions in this script");
+        assert_eq!(comment, vec!["No functions in this script"]);
+        assert!(functions.is_empty());
+    }
+
     #[test]
     fn test_is_function_header_line() {
         assert!(is_function_header_line(&String::from("some_function(){")));
@@ -253,5 +1634,41 @@ mod tests {
         assert!(is_function_header_line(&String::from(
             "    some_function    ()     {"
         )));
+        // Trailing whitespace.
+        assert!(is_function_header_line("some_function() {  "));
+        assert!(!is_function_header_line("_private() {"));
+    }
+
+    #[test]
+    fn test_duplicate_functions() {
+        let script = |path: &str, source: &str| {
+            let (comment, functions) = parse_script(source);
+            Script {
+                path: std::path::PathBuf::from(path),
+                absolute_path: std::path::PathBuf::from(path),
+                comment,
+                functions,
+                shebang: None,
+            }
+        };
+        let scripts = vec![
+            script("./a.sh", "deploy() {\n}\nbuild() {\n}\n"),
+            script("./b.sh", "deploy() {\n}\n"),
+            script("./c/a.sh", "deploy() {\n}\ntest() {\n}\n"),
+        ];
+        let duplicates = duplicate_functions(&scripts);
+        assert_eq!(duplicates.len(), 1);
+        let paths: Vec<&std::path::Path> = duplicates["deploy"]
+            .iter()
+            .map(|script| script.path.as_path())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                std::path::Path::new("./a.sh"),
+                std::path::Path::new("./b.sh"),
+                std::path::Path::new("./c/a.sh")
+            ]
+        );
     }
 }
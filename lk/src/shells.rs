@@ -1,7 +1,54 @@
-use std::{fs::OpenOptions, io::Write, path::Path};
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    mem::ManuallyDrop,
+    os::unix::io::{FromRawFd, RawFd},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::Result;
 
+/// Where `--print-to` writes the selected command: a file descriptor the shell has opened for
+/// us, or a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrintTarget {
+    Fd(RawFd),
+    Path(PathBuf),
+}
+
+impl FromStr for PrintTarget {
+    type Err = String;
+
+    fn from_str(target: &str) -> std::result::Result<Self, Self::Err> {
+        if target.is_empty() {
+            return Err(String::from("expected a path or a file descriptor"));
+        }
+        Ok(match target.parse::<RawFd>() {
+            Ok(fd) if fd < 0 => return Err(format!("{fd} isn't a file descriptor")),
+            Ok(fd) => PrintTarget::Fd(fd),
+            Err(_) => PrintTarget::Path(PathBuf::from(target)),
+        })
+    }
+}
+
+impl PrintTarget {
+    /// Writes `command` on a line of its own, so a shell widget can put it on the prompt.
+    pub fn write(&self, command: &str) -> Result<()> {
+        log::info!("Printing command to {:?}: {}", self, command);
+        match self {
+            PrintTarget::Fd(fd) => {
+                // Safety: the fd is the caller's, e.g. `3>&1`, and nothing else in lk uses it.
+                // It isn't ours to close, and it might be stdout, so the `File` isn't dropped.
+                let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(*fd) });
+                writeln!(file, "{}", command)?;
+            }
+            PrintTarget::Path(path) => writeln!(File::create(path)?, "{}", command)?,
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct Shell {
     locations: Vec<String>,
@@ -76,7 +123,6 @@ impl UserShell {
         log::info!("History file: {}", &self.history_file);
         // TODO Don't fail if the file doesn't exist
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .open(&self.history_file)
             .unwrap();
@@ -84,3 +130,48 @@ impl UserShell {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_print_target() {
+        assert_eq!("3".parse(), Ok(PrintTarget::Fd(3)));
+        assert_eq!(
+            "/tmp/lk-command".parse(),
+            Ok(PrintTarget::Path(PathBuf::from("/tmp/lk-command")))
+        );
+        assert!("".parse::<PrintTarget>().is_err());
+        assert!("-1".parse::<PrintTarget>().is_err());
+    }
+
+    #[test]
+    fn test_write_to_fd_leaves_it_open() {
+        use std::os::unix::io::AsRawFd;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("command");
+        let mut file = File::create(&path).unwrap();
+        PrintTarget::Fd(file.as_raw_fd())
+            .write("lk deploy.sh build")
+            .unwrap();
+        writeln!(file, "still open").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "lk deploy.sh build\nstill open\n"
+        );
+    }
+
+    #[test]
+    fn test_write_to_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("command");
+        PrintTarget::Path(path.clone())
+            .write("lk deploy.sh build")
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "lk deploy.sh build\n"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+/// Named sets of the colours lk prints with, chosen by `theme` in the config.
+use fuzzy_finder::colours::Colours;
+use pastel_colours::{Colour, BLUE_FG, DARK_BLUE_BG, DARK_GREEN_FG, GREEN_FG, GREY_FG};
+use serde::{Deserialize, Serialize};
+
+/// The themes `theme` can be.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Default,
+    /// Bright colours on strong backgrounds.
+    HighContrast,
+    /// Oranges and yellows, rather than the default's greens and blues, which are hard to
+    /// tell apart with most kinds of colour blindness.
+    Colorblind,
+}
+
+/// The colours a theme uses. Each one is the escape code that sets it, so they have to be
+/// resolved after `pastel_colours::set_enabled`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// The fuzzy finder's colours. `[colors]` in the config overrides these.
+    pub finder: Colours,
+    /// The names in a script's list of functions, and the commands lk suggests.
+    pub function: String,
+    /// The names in the list of scripts.
+    pub script: String,
+    /// The numbers before the function names.
+    pub number: String,
+    /// The headings of groups of functions.
+    pub heading: String,
+    /// The background of the banner before a script's functions, or a function's output.
+    pub banner: String,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::default().palette()
+    }
+}
+
+impl Theme {
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Default => Palette {
+                finder: Colours::default(),
+                function: GREEN_FG.to_string(),
+                script: DARK_GREEN_FG.to_string(),
+                number: GREY_FG.to_string(),
+                heading: BLUE_FG.to_string(),
+                banner: DARK_BLUE_BG.to_string(),
+            },
+            Theme::HighContrast => {
+                let white = Colour::parse("231").unwrap().fg();
+                let yellow = Colour::parse("226").unwrap();
+                let blue = Colour::parse("19").unwrap().bg();
+                Palette {
+                    finder: Colours {
+                        matched: blue.to_owned(),
+                        selected: Colour::parse("238").unwrap().bg(),
+                        pointer: yellow.fg(),
+                        prompt: white.to_owned(),
+                    },
+                    function: white,
+                    script: yellow.fg(),
+                    number: Colour::parse("252").unwrap().fg(),
+                    heading: yellow.fg(),
+                    banner: blue,
+                }
+            }
+            Theme::Colorblind => {
+                let orange = Colour::parse("#e69f00").unwrap();
+                let yellow = Colour::parse("#f0e442").unwrap();
+                let brown = Colour::parse("#7a4f00").unwrap().bg();
+                Palette {
+                    finder: Colours {
+                        matched: brown.to_owned(),
+                        selected: Colour::parse("dark_grey").unwrap().bg(),
+                        pointer: orange.fg(),
+                        prompt: yellow.fg(),
+                    },
+                    function: orange.fg(),
+                    script: yellow.fg(),
+                    number: GREY_FG.to_string(),
+                    heading: yellow.fg(),
+                    banner: brown,
+                }
+            }
+        }
+    }
+}
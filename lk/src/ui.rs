@@ -1,32 +1,288 @@
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::{
-    executables::Executables,
-    script::{Function, Script},
+    bash_file::shell_quote,
+    executables::{Executable, Executables},
+    script::{Function, FunctionOrder, Script, ScriptError},
+    theme::Palette,
 };
-use pastel_colours::{BLUE_FG, DARK_BLUE_BG, GREEN_FG, RED_FG, RESET_BG, RESET_FG};
+use anyhow::Result;
+use pastel_colours::{BLUE_FG, DARK_BLUE_BG, GREEN_FG, GREY_FG, RED_FG, RESET_BG, RESET_FG};
+
+/// When to use colours, as passed to `--color`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> std::result::Result<Self, Self::Err> {
+        match mode {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("expected auto, always or never, not '{mode}'")),
+        }
+    }
+}
+
+impl ColorMode {
+    /// Whether to use colours. `auto` uses them when we're writing to a terminal, unless
+    /// `NO_COLOR` is set.
+    pub fn use_colors(self, to_terminal: bool) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && to_terminal
+            }
+        }
+    }
+}
+
+/// Whether script paths are written as links. See `set_hyperlinks`.
+static HYPERLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Turns links to scripts on or off. Like colours, they're escape codes, so they should only
+/// be on when we're writing to a terminal that understands them. They're off by default.
+pub fn set_hyperlinks(enabled: bool) {
+    HYPERLINKS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the terminal seems to understand OSC 8 links. There's no way to ask it, so this
+/// goes by the variables the terminals that do set. `FORCE_HYPERLINK=1` says it does anyway.
+pub fn terminal_supports_hyperlinks() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    if let Ok(force) = std::env::var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if var("TERM") == "dumb" {
+        return false;
+    }
+    matches!(
+        var("TERM_PROGRAM").as_str(),
+        "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper"
+    ) || ["kitty", "alacritty", "foot", "wezterm"]
+        .iter()
+        .any(|terminal| var("TERM").contains(terminal))
+        || std::env::var_os("WT_SESSION").is_some()
+        || std::env::var_os("KONSOLE_VERSION").is_some()
+        // VTE terminals, like GNOME Terminal, have had them since 0.50.
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|version| version >= 5000)
+}
+
+/// `text`, as a link to the file at `path` if links are on, so clicking it opens the file.
+pub fn hyperlink(text: &str, path: &Path) -> String {
+    if !HYPERLINKS.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    link(text, path)
+}
+
+/// `text` as an OSC 8 link to the file at `path`.
+fn link(text: &str, path: &Path) -> String {
+    format!("\x1b]8;;{}\x1b\\{text}\x1b]8;;\x1b\\", file_url(path))
+}
+
+/// A `file://` URL for `path`, percent-encoding everything but the characters URLs allow
+/// as they are, so spaces and `%`s in it don't break the link.
+fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    url
+}
+
+/// How to print lists of scripts and functions, and the banners before functions' output.
+#[derive(Clone, Debug, Default)]
+pub struct PrintOptions {
+    /// Show lists too long for the terminal through the pager.
+    pub pager: bool,
+    /// Show absolute paths, rather than paths relative to where lk is run.
+    pub absolute_paths: bool,
+    /// Leave out the banner, so the function's output is all that's printed.
+    pub quiet: bool,
+    /// How to order a script's functions.
+    pub function_order: FunctionOrder,
+    /// Label the fuzzy finder's scripts by their paths within the deepest directory they're
+    /// all in, e.g. `./deploy.sh` rather than `./infra/aws/deploy.sh`.
+    pub strip_common_prefix: bool,
+    /// Leave scripts without any functions out of lists of scripts.
+    pub hide_empty_scripts: bool,
+    /// The colours to print with.
+    pub palette: Palette,
+}
 
-pub fn print_root_header() {
-    println!("{DARK_BLUE_BG}lk: ./{RESET_BG}");
+pub fn root_header(root: &str, palette: &Palette) -> String {
+    let root = root.trim_end_matches('/');
+    format!("{}lk: {root}/{RESET_BG}", palette.banner)
 }
 
-pub fn print_script_header(script: &Script) {
-    let script_path = script
-        .path
-        .to_owned()
-        .into_os_string()
-        .into_string()
-        .unwrap();
-    println!("{DARK_BLUE_BG}lk: {script_path}{RESET_BG}");
+pub fn print_script_header(script: &Script, absolute_paths: bool, palette: &Palette) {
+    let script_path = hyperlink(&script.display_path(absolute_paths), &script.absolute_path);
+    println!("{}lk: {script_path}{RESET_BG}", palette.banner);
 }
 
-pub fn print_complete_header(script: &Script, function: &Function, params: &[String]) {
+/// Follows the list of scripts, so the user knows how to go down a level.
+pub fn root_footer(palette: &Palette) -> String {
+    format!(
+        "\nRun {}lk <script>{RESET_FG} to see a script's functions.",
+        palette.function
+    )
+}
+
+/// Follows a script's functions, so the user knows how to run one, or go back up a level.
+pub fn print_script_footer(script: &Script, absolute_paths: bool, palette: &Palette) {
+    let command = &palette.function;
     println!(
-        "{DARK_BLUE_BG}lk: {} -> {} ({}){RESET_BG}",
-        script.path.as_os_str().to_string_lossy(),
-        function.name,
+        "\nRun {command}lk {} <function>{RESET_FG} to run a function, by name or number, or {command}lk --list{RESET_FG} to see all scripts.",
+        script.command_name(absolute_paths)
+    );
+}
+
+/// The banner before a function's output. `functions` is its name, or the names of the
+/// functions being run one after the other.
+pub fn print_complete_header(
+    script: &Script,
+    functions: &str,
+    params: &[String],
+    absolute_paths: bool,
+    palette: &Palette,
+) {
+    println!(
+        "{}lk: {} -> {} ({}){RESET_BG}",
+        palette.banner,
+        hyperlink(&script.display_path(absolute_paths), &script.absolute_path),
+        functions,
         params.join(" ")
     );
 }
 
+/// Says that `function` failed, when running all of a script's functions, and whether we're
+/// carrying on with the rest.
+pub fn print_function_failed(function: &Function, status: ExitStatus, continuing: bool) {
+    let next = if continuing {
+        "Running the rest anyway."
+    } else {
+        "Not running the rest. Pass --continue-on-error to run them anyway."
+    };
+    eprintln!(
+        "{RED_FG}{} failed ({status}).{RESET_FG} {next}",
+        function.name
+    );
+}
+
+/// Asks the user whether to run `functions`, on stderr. Anything but yes is a no.
+/// By the time we get here the fuzzy finder has dropped its raw terminal, which puts the
+/// terminal back in cooked mode, so the answer is read a line at a time and echoed.
+pub fn confirm_run(functions: &str) -> Result<bool> {
+    let confirmed = ask(&format!("Run {BLUE_FG}{functions}{RESET_FG}?"))?;
+    if !confirmed {
+        eprintln!("Not running {functions}.");
+    }
+    Ok(confirmed)
+}
+
+/// Nothing in `script` matched what the user searched for, so we ask whether to add a function
+/// called that, like `confirm_run` asks.
+pub fn confirm_add_function(function: &str, script: &Script) -> Result<bool> {
+    ask(&format!(
+        "No function matched '{function}'. Add {GREEN_FG}{function}(){RESET_FG} to {}?",
+        script.path.display()
+    ))
+}
+
+/// Asks the user a yes or no question, on stderr. Anything but yes is a no.
+fn ask(question: &str) -> Result<bool> {
+    let mut stderr = std::io::stderr();
+    write!(stderr, "{question} [y/N] ")?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// `--env-file` was given a path that isn't a file, so nothing's sourced.
+pub fn print_missing_env_file(path: &Path) {
+    eprintln!(
+        "{RED_FG}There's no env file at {}, so it won't be sourced.{RESET_FG}",
+        path.display()
+    );
+}
+
+/// For when nothing matched the fuzzy search, and there's no one script to add a function to.
+pub fn print_no_match(search: &str) {
+    eprintln!("No function matched '{search}'.");
+}
+
+pub fn print_function_added(function: &str, script: &Script) {
+    println!(
+        "Added {GREEN_FG}{function}(){RESET_FG} to the end of {}.",
+        script.path.display()
+    );
+}
+
+/// For when the user's editor exits with an error after `--edit`, so we don't run the function.
+pub fn print_edit_failed(function: &Function) {
+    eprintln!(
+        "{RED_FG}Your editor exited with an error, so {} wasn't run.{RESET_FG}",
+        function.name
+    );
+}
+
+/// For when the function the user was editing isn't in the script once they're done.
+pub fn print_function_no_longer_in_script(function: &str, script: &Script) {
+    eprintln!(
+        "{RED_FG}{function} isn't in {} any more, so it wasn't run.{RESET_FG}",
+        script.file_name()
+    );
+}
+
+/// Says `lk init` has written a starter script, and how to see it.
+pub fn print_script_created(path: &Path) {
+    println!(
+        "Created {GREEN_FG}{}{RESET_FG}. Run {GREEN_FG}lk {}{RESET_FG} to see its functions.",
+        path.display(),
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+}
+
+/// Asks which function to run next in `--loop` mode. Returns `None` if the user quits, with
+/// `q` or ctrl-d.
+pub fn prompt_for_function() -> Result<Option<String>> {
+    let mut stderr = std::io::stderr();
+    write!(
+        stderr,
+        "Run which function? Type its name or number, with any params, or q to quit: "
+    )?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer)? == 0 {
+        // ctrl-d, so the shell's prompt starts on a line of its own.
+        eprintln!();
+        return Ok(None);
+    }
+    match answer.trim() {
+        "q" | "quit" | "exit" => Ok(None),
+        answer => Ok(Some(answer.to_string())),
+    }
+}
+
 pub fn print_no_functions_in_script_help() {
     println!("Could not find any functions! Why not add some. They look like this:");
     let example_function = r#"# Some great comment
@@ -37,12 +293,184 @@ blow_mind() {
     println!("{GREEN_FG}{example_function}{RESET_FG}");
 }
 
-pub fn print_bad_script_name(script: &str, executables: Executables) {
-    println!("{RED_FG}Didn't find a script with name {BLUE_FG}{script}!{RESET_FG}\n");
-    executables.pretty_print();
+/// Explains why lk couldn't use a script, and what the user might do about it.
+pub fn print_script_error(err: &ScriptError) {
+    match script_error_hint(err) {
+        Some(hint) => eprintln!("{RED_FG}{err}{RESET_FG}\n{hint}"),
+        None => eprintln!("{RED_FG}{err}{RESET_FG}"),
+    }
+}
+
+/// What the user might do about `err`, if we know.
+fn script_error_hint(err: &ScriptError) -> Option<String> {
+    match err {
+        ScriptError::PermissionDenied { path } => Some(format!(
+            "Try {GREEN_FG}chmod u+r {}{RESET_FG}.",
+            path.to_string_lossy()
+        )),
+        // Other errors, like the file being removed, aren't about what's in it.
+        ScriptError::Read { source, .. } if source.kind() == ErrorKind::InvalidData => Some(
+            String::from("lk can only read scripts saved as UTF-8."),
+        ),
+        ScriptError::Read { .. } => None,
+        ScriptError::Parse { .. } => Some(String::from(
+            "If that's valid bash then please raise a bug at https://github.com/jamescoleuk/lk/issues.",
+        )),
+    }
+}
+
+/// Lists the scripts lk couldn't use, and why, for `--verbose`. On stderr, so it isn't mixed
+/// up with a list that's being piped somewhere.
+pub fn print_skipped_scripts(skipped: &[ScriptError]) {
+    if skipped.is_empty() {
+        return;
+    }
+    eprintln!(
+        "{DARK_BLUE_BG}lk: skipped {} script(s){RESET_BG}",
+        skipped.len()
+    );
+    for err in skipped {
+        eprintln!("  {RED_FG}{err}{RESET_FG}");
+    }
+}
+
+pub fn print_no_executables_found(root: &str) {
+    println!(
+        "{RED_FG}No executable scripts found under {BLUE_FG}{root}{RED_FG}!{RESET_FG} Try {GREEN_FG}chmod +x{RESET_FG} on your scripts, or check what you're passing to {GREEN_FG}--ignore{RESET_FG}."
+    );
+}
+
+pub fn print_no_recent_functions() {
+    println!("You haven't run any of the functions here yet. Try {GREEN_FG}lk --fuzzy{RESET_FG} or {GREEN_FG}lk --list{RESET_FG}.");
+}
+
+/// Follows the list in `--watch` mode, so the user knows it'll change, and how to stop it.
+pub fn print_watching() {
+    println!("\n{GREY_FG}Watching for changes. Press ctrl-c to stop.{RESET_FG}");
 }
 
-pub fn print_bad_function_name(script: &Script, function: &str) {
-    println!("{RED_FG}Didn't find a function with name {BLUE_FG}{function}{RESET_FG}!\n");
-    script.pretty_print();
+pub fn print_no_last_function() {
+    println!("You haven't run any functions with lk yet, so there's nothing to run again. Try {GREEN_FG}lk --fuzzy{RESET_FG} or {GREEN_FG}lk --list{RESET_FG}.");
+}
+
+pub fn print_bad_script_name(
+    script: &str,
+    executables: Executables,
+    options: &PrintOptions,
+) -> Result<()> {
+    eprintln!("{RED_FG}Didn't find a script with name {BLUE_FG}{script}!{RESET_FG}\n");
+    if executables.is_empty() {
+        print_no_executables_found(&executables.roots.join(", "));
+        return Ok(());
+    }
+    executables.pretty_print(options)
+}
+
+/// Lists the scripts called `script`, when there's more than one, so the user can tell
+/// which they meant.
+pub fn print_ambiguous_script_name(script: &str, candidates: &[&Executable], absolute_paths: bool) {
+    eprintln!(
+        "{RED_FG}Found more than one script with name {BLUE_FG}{script}{RED_FG}!{RESET_FG}\n"
+    );
+    for candidate in candidates {
+        let path = if absolute_paths {
+            &candidate.absolute_path
+        } else {
+            &candidate.path
+        };
+        eprintln!("  {}", path.display());
+    }
+    eprintln!(
+        "\nPass the path of the one you mean instead, e.g. {GREEN_FG}lk {}{RESET_FG}.",
+        shell_quote(&candidates[0].absolute_path.to_string_lossy())
+    );
+}
+
+pub fn print_bad_function_name(script: &Script, function: &str, options: &PrintOptions) {
+    if function.parse::<usize>().is_ok() {
+        eprintln!(
+            "{RED_FG}Didn't find a function with name or number {BLUE_FG}{function}{RED_FG}! {} has {} function(s).{RESET_FG}\n",
+            script.file_name(),
+            script.functions.len()
+        );
+    } else {
+        eprintln!("{RED_FG}Didn't find a function with name {BLUE_FG}{function}{RESET_FG}!\n");
+    }
+    script.pretty_print(options);
+}
+
+/// A longer guide than `--help`, for `lk --examples`.
+pub fn print_examples() {
+    println!(
+        r#"{DARK_BLUE_BG}lk: a guide{RESET_BG}
+
+lk finds the executable scripts in the current directory and its sub-directories,
+and the functions in them. There are two ways to explore them.
+
+{BLUE_FG}List mode{RESET_FG} is hierarchical. Start at the top and work down:
+  {GREEN_FG}lk --list{RESET_FG}                 List the scripts
+  {GREEN_FG}lk deploy.sh{RESET_FG}              List the functions in deploy.sh, with their comments
+  {GREEN_FG}lk deploy.sh build{RESET_FG}        Run the build function
+  {GREEN_FG}lk deploy.sh build dev{RESET_FG}    Anything after the function is passed to it
+  {GREEN_FG}lk deploy.sh build test{RESET_FG}   Unless it's another function: run build, then test
+
+{BLUE_FG}Fuzzy mode{RESET_FG} searches every script and function at once:
+  {GREEN_FG}lk --fuzzy{RESET_FG}                Type to search, then enter to run the selection
+  {GREEN_FG}lk --recent{RESET_FG}               Search just the functions you've run before
+  Selecting a script searches just its functions. Tab completes the search from the
+  selection, and escape or ctrl-c cancels. The command is added to your shell history.
+
+{BLUE_FG}Plain lk{RESET_FG} uses your default mode, which starts as list:
+  {GREEN_FG}lk --default fuzzy{RESET_FG}        Make plain lk fuzzy search from now on
+  {GREEN_FG}lk --default list{RESET_FG}         And back again
+
+{BLUE_FG}Other options{RESET_FG}:
+  {GREEN_FG}lk --path ~/scripts{RESET_FG}       Search another directory too
+  {GREEN_FG}lk --ignore old{RESET_FG}           Ignore a path, or use a .lkignore file
+  {GREEN_FG}lk --env ENV=staging{RESET_FG}      Set an environment variable for the function
+  {GREEN_FG}lk --timeout 60{RESET_FG}           Kill the function if it takes longer than a minute
+  {GREEN_FG}lk --check{RESET_FG}                Check every script has functions, e.g. in CI
+  {GREEN_FG}lk --lint{RESET_FG}                 Find functions with the same name in different scripts
+
+lk keeps its config in ~/.config/lk/lk.toml."#
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_script_error_hint() {
+        let read = |kind| ScriptError::Read {
+            path: PathBuf::from("deploy.sh"),
+            source: std::io::Error::from(kind),
+        };
+        assert_eq!(
+            script_error_hint(&read(ErrorKind::InvalidData)),
+            Some(String::from("lk can only read scripts saved as UTF-8."))
+        );
+        assert_eq!(script_error_hint(&read(ErrorKind::NotFound)), None);
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let path = Path::new("/home/me/deploy.sh");
+        // Links are off unless they're turned on.
+        assert_eq!(hyperlink("deploy.sh", path), "deploy.sh");
+        assert_eq!(
+            link("deploy.sh", path),
+            "\x1b]8;;file:///home/me/deploy.sh\x1b\\deploy.sh\x1b]8;;\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_file_url() {
+        assert_eq!(
+            file_url(Path::new("/home/me/my scripts/100%/deploy.sh")),
+            "file:///home/me/my%20scripts/100%25/deploy.sh"
+        );
+        assert_eq!(file_url(Path::new("/tmp/é")), "file:///tmp/%C3%A9");
+    }
 }
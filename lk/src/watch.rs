@@ -0,0 +1,118 @@
+/// Re-renders whenever the scripts change, for `--watch`.
+use anyhow::Result;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+/// How long to wait for more changes before re-rendering, so that saving a file, or checking
+/// out a branch, renders once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Calls `render` on a clear screen straight away, and again whenever anything under `roots`
+/// changes, apart from the paths `is_ignored` says to ignore. It keeps going until lk is
+/// stopped, e.g. with ctrl-c.
+pub fn watch(
+    roots: &[String],
+    is_ignored: impl Fn(&Path) -> bool,
+    mut render: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for root in roots {
+        watcher.watch(Path::new(root), RecursiveMode::Recursive)?;
+    }
+    loop {
+        print!("{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
+        render()?;
+        std::io::stdout().flush()?;
+        if !wait_for_change(&events, &is_ignored) {
+            return Ok(());
+        }
+    }
+}
+
+/// Waits for a change, and then for the changes to stop. Returns false if there'll be no
+/// more, because the watcher's gone.
+fn wait_for_change(
+    events: &Receiver<notify::Result<Event>>,
+    is_ignored: &dyn Fn(&Path) -> bool,
+) -> bool {
+    loop {
+        match events.recv() {
+            Ok(Ok(event)) if is_change(&event, is_ignored) => break,
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => log::warn!("Error watching for changes: {err}"),
+            Err(_) => return false,
+        }
+    }
+    // Only more changes put off rendering, so a build writing to `target` can't.
+    let mut deadline = Instant::now() + DEBOUNCE;
+    while let Ok(event) = events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if matches!(event, Ok(event) if is_change(&event, is_ignored)) {
+            deadline = Instant::now() + DEBOUNCE;
+        }
+    }
+    true
+}
+
+/// Whether the event changes what we'd render. Reading files doesn't, and we read every
+/// script when we render, so counting reads would re-render forever. Nor do changes to
+/// ignored paths, like lk's own log.
+fn is_change(event: &Event, is_ignored: &dyn Fn(&Path) -> bool) -> bool {
+    !matches!(event.kind, EventKind::Access(_) | EventKind::Other)
+        && (event.paths.is_empty() || event.paths.iter().any(|path| !is_ignored(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+    use std::path::PathBuf;
+
+    fn nothing_ignored(_: &Path) -> bool {
+        false
+    }
+
+    #[test]
+    fn test_is_change() {
+        let is_change = |event| is_change(&event, &nothing_ignored);
+        assert!(is_change(Event::new(EventKind::Create(CreateKind::File))));
+        assert!(is_change(Event::new(EventKind::Modify(ModifyKind::Any))));
+        assert!(!is_change(Event::new(EventKind::Access(AccessKind::Any))));
+    }
+
+    #[test]
+    fn test_ignored_paths_are_not_changes() {
+        let is_ignored = |path: &Path| path.starts_with("target");
+        let modified = |path: &str| {
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path(PathBuf::from(path))
+        };
+        assert!(!is_change(&modified("target/debug/lk"), &is_ignored));
+        assert!(is_change(&modified("deploy.sh"), &is_ignored));
+        assert!(is_change(
+            &modified("target/debug/lk").add_path(PathBuf::from("deploy.sh")),
+            &is_ignored
+        ));
+    }
+
+    #[test]
+    fn test_wait_for_change() {
+        let (sender, events) = channel();
+        sender
+            .send(Ok(Event::new(EventKind::Access(AccessKind::Any))))
+            .unwrap();
+        sender
+            .send(Ok(Event::new(EventKind::Modify(ModifyKind::Any))))
+            .unwrap();
+        sender
+            .send(Ok(Event::new(EventKind::Modify(ModifyKind::Any))))
+            .unwrap();
+        assert!(wait_for_change(&events, &nothing_ignored));
+        // The second change was part of the first.
+        assert!(events.try_recv().is_err());
+        drop(sender);
+        assert!(!wait_for_change(&events, &nothing_ignored));
+    }
+}
@@ -0,0 +1,263 @@
+/// Tests lk's behaviour as a command, by running it in a directory of scripts.
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A directory of scripts to run lk in, with a home of its own, so the user's config and
+/// history aren't used.
+struct Project {
+    dir: TempDir,
+    home: TempDir,
+}
+
+impl Project {
+    fn new() -> Self {
+        Project {
+            dir: tempfile::tempdir().unwrap(),
+            home: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    /// Adds an executable script called `name`.
+    fn script(&self, name: &str, contents: &str) -> &Self {
+        let path = self.dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        self
+    }
+
+    /// Writes lk's config, in the project's home.
+    fn config(&self, contents: &str) -> &Self {
+        let lk_dir = self.home.path().join(".config/lk");
+        std::fs::create_dir_all(&lk_dir).unwrap();
+        std::fs::write(lk_dir.join("lk.toml"), contents).unwrap();
+        self
+    }
+
+    fn lk(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_lk"))
+            .current_dir(self.dir.path())
+            .env("HOME", self.home.path())
+            .env("NO_COLOR", "1")
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+const BUILD: &str = "#!/usr/bin/env bash\n\nboom() {\n    return 3\n}\n\nok() {\n    echo ok\n}\n";
+
+#[test]
+fn test_exit_status_is_the_functions() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+
+    assert_eq!(
+        project.lk(&["-q", "build.sh", "boom"]).status.code(),
+        Some(3)
+    );
+    assert_eq!(
+        project.lk(&["-q", "run", "build.sh", "boom"]).status.code(),
+        Some(3)
+    );
+    let output = project.lk(&["-q", "build.sh", "ok"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "ok\n");
+}
+
+#[test]
+fn test_exit_status_of_the_last_function() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+    project.lk(&["-q", "build.sh", "boom"]);
+    assert_eq!(project.lk(&["-q", "--last"]).status.code(), Some(3));
+}
+
+#[test]
+fn test_no_spinner_when_piped() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+    let output = project.lk(&["build.sh", "ok", "--show-runner"]);
+    assert!(stdout(&output).starts_with("#!/usr/bin/env bash\n"));
+    assert!(!stdout(&project.lk(&["--list"])).contains('\x1b'));
+}
+
+#[test]
+fn test_commands_use_the_scripts_path() {
+    // Both scripts are called build.sh, so the file name alone would be ambiguous.
+    let project = Project::new();
+    project
+        .script("a/build.sh", BUILD)
+        .script("b/build.sh", BUILD);
+    let listing = stdout(&project.lk(&["a/build.sh"]));
+    assert!(listing.contains("Run lk ./a/build.sh <function>"));
+    let output = project.lk(&["-q", "./a/build.sh", "ok"]);
+    assert_eq!(stdout(&output), "ok\n");
+}
+
+#[test]
+fn test_ambiguous_names_suggest_an_absolute_path() {
+    let project = Project::new();
+    project
+        .script("a/build.sh", BUILD)
+        .script("b/build.sh", BUILD);
+    let output = project.lk(&["build.sh"]);
+    let root = project.dir.path().canonicalize().unwrap();
+    let suggestions = [root.join("a/build.sh"), root.join("b/build.sh")]
+        .map(|path| format!("e.g. lk {}.", path.display()));
+    let text = format!(
+        "{}{}",
+        stdout(&output),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(suggestions
+        .iter()
+        .any(|suggestion| text.contains(suggestion)));
+}
+
+#[test]
+fn test_functions_the_user_declines_are_not_remembered() {
+    let project = Project::new();
+    project
+        .script("build.sh", BUILD)
+        .config("default_mode = \"list\"\nconfirm_patterns = [\"^boom\"]\n");
+    // There's no stdin, so the user doesn't confirm it.
+    let output = project.lk(&["-q", "build.sh", "boom"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Not running boom."));
+    assert!(stdout(&project.lk(&["--last"])).contains("nothing to run again"));
+}
+
+#[test]
+fn test_bad_names_fail() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+    for args in [
+        &["-q", "build.sh", "nope"][..],
+        &["-q", "nope.sh", "ok"],
+        &["-q", "run", "build.sh", "nope"],
+        &["-q", "run", "nope.sh", "ok"],
+    ] {
+        let output = project.lk(args);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Didn't find a"));
+    }
+}
+
+#[test]
+fn test_functions_are_only_chained_with_chain() {
+    let project = Project::new();
+    project.script(
+        "build.sh",
+        "#!/usr/bin/env bash\n\nsay() {\n    echo \"$@\"\n}\n\nok() {\n    echo ok\n}\n",
+    );
+    // Without --chain, a param that's a function's name is still a param.
+    assert_eq!(
+        stdout(&project.lk(&["-q", "build.sh", "say", "ok"])),
+        "ok\n"
+    );
+    let output = project.lk(&["-q", "--chain", "build.sh", "say", "ok"]);
+    assert_eq!(stdout(&output), "\nok\n");
+    let output = project.lk(&["-q", "--chain", "build.sh", "say", "nope"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_count() {
+    let project = Project::new();
+    assert_eq!(
+        stdout(&project.lk(&["--count"])),
+        "0 scripts, 0 functions\n"
+    );
+    project.script("ok.sh", "#!/usr/bin/env bash\n\nok() {\n    echo ok\n}\n");
+    assert_eq!(stdout(&project.lk(&["--count"])), "1 script, 1 function\n");
+    project.script("build.sh", BUILD);
+    assert_eq!(
+        stdout(&project.lk(&["--count"])),
+        "2 scripts, 3 functions\n"
+    );
+}
+
+#[test]
+fn test_functions_only() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+    let output = project.lk(&["--functions-only", "build.sh"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "boom\nok\n");
+    let output = project.lk(&["--functions-only", "nope.sh"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn test_print_path() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+    let path = project.dir.path().canonicalize().unwrap().join("build.sh");
+    let output = project.lk(&["--print-path", "build.sh"]);
+    assert_eq!(stdout(&output), format!("{}\n", path.display()));
+    let output = project.lk(&["--print-path", "build.sh", "ok"]);
+    assert_eq!(stdout(&output), format!("{}:7\n", path.display()));
+    for args in [
+        &["--print-path", "build.sh", "nope"][..],
+        &["--print-path", "nope.sh"],
+    ] {
+        let output = project.lk(args);
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(stdout(&output), "");
+    }
+}
+
+#[test]
+fn test_complete() {
+    let project = Project::new();
+    project.script("build.sh", BUILD);
+    assert_eq!(
+        stdout(&project.lk(&["__complete", "build.sh", "bo"])),
+        "boom\n"
+    );
+    assert_eq!(
+        stdout(&project.lk(&["__complete", "build.sh"])),
+        "boom\nok\n"
+    );
+    assert_eq!(stdout(&project.lk(&["__complete", "build.sh", "x"])), "");
+    assert_eq!(stdout(&project.lk(&["__complete", "nope.sh", "b"])), "");
+}
+
+#[test]
+fn test_check_fails_without_scripts() {
+    let project = Project::new();
+    let output = project.lk(&["--check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("no scripts found to check"));
+    project.script("build.sh", BUILD);
+    assert!(project.lk(&["--check"]).status.success());
+}
+
+#[test]
+fn test_bad_config_is_an_error() {
+    let project = Project::new();
+    project
+        .script("build.sh", BUILD)
+        .config("default_mode = \"list\"\nlabel_format = \"{nope}\"\n");
+    let output = project.lk(&["--list"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unable to parse"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn test_bad_settings_are_reported_on_stderr() {
+    let project = Project::new();
+    project.config("default_mode = \"list\"\n");
+    let output = project.lk(&["--set", "nope=1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown setting 'nope'"));
+}
@@ -1,5 +1,35 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use termion::color::{self, Rgb};
 
+/// Whether colours are written. See `set_enabled`.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns all the colours in this crate on or off, e.g. for `NO_COLOR`. When they're off the
+/// constants below, and `Colour::fg` and `Colour::bg`, are empty. Colours are on by default.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether colours are written.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// An escape code that's only written if colours are enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct Paint<T>(T);
+
+impl<T: fmt::Display> fmt::Display for Paint<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            self.0.fmt(f)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 const BLUE: Rgb = Rgb(113, 190, 242);
 // const DARK_BLUE: Rgb = Rgb(17, 117, 185);
 const DARK_BLUE: Rgb = Rgb(13, 88, 138);
@@ -12,26 +42,88 @@ const PINK: Rgb = Rgb(210, 144, 228);
 const RED: Rgb = Rgb(232, 131, 136);
 const TEAL: Rgb = Rgb(102, 194, 205);
 
-pub const BLUE_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(BLUE);
-pub const BLUE_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(BLUE);
-pub const DARK_BLUE_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(DARK_BLUE);
-pub const DARK_BLUE_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(DARK_BLUE);
-pub const DARK_GREEN_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(DARK_GREEN);
-pub const DARK_GREEN_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(DARK_GREEN);
-pub const DARK_GREY_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(DARK_GREY);
-pub const DARK_GREY_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(DARK_GREY);
-pub const GREEN_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(GREEN);
-pub const GREEN_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(GREEN);
-pub const GREY_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(GREY);
-pub const GREY_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(GREY);
-pub const ORANGE_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(ORANGE);
-pub const ORANGE_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(ORANGE);
-pub const PINK_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(PINK);
-pub const PINK_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(PINK);
-pub const RED_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(RED);
-pub const RED_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(RED);
-pub const TEAL_BG: termion::color::Bg<termion::color::Rgb> = color::Bg(TEAL);
-pub const TEAL_FG: termion::color::Fg<termion::color::Rgb> = color::Fg(TEAL);
-
-pub const RESET_BG: termion::color::Bg<termion::color::Reset> = color::Bg(color::Reset);
-pub const RESET_FG: termion::color::Fg<termion::color::Reset> = color::Fg(color::Reset);
+pub const BLUE_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(BLUE));
+pub const BLUE_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(BLUE));
+pub const DARK_BLUE_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(DARK_BLUE));
+pub const DARK_BLUE_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(DARK_BLUE));
+pub const DARK_GREEN_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(DARK_GREEN));
+pub const DARK_GREEN_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(DARK_GREEN));
+pub const DARK_GREY_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(DARK_GREY));
+pub const DARK_GREY_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(DARK_GREY));
+pub const GREEN_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(GREEN));
+pub const GREEN_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(GREEN));
+pub const GREY_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(GREY));
+pub const GREY_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(GREY));
+pub const ORANGE_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(ORANGE));
+pub const ORANGE_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(ORANGE));
+pub const PINK_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(PINK));
+pub const PINK_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(PINK));
+pub const RED_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(RED));
+pub const RED_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(RED));
+pub const TEAL_BG: Paint<color::Bg<Rgb>> = Paint(color::Bg(TEAL));
+pub const TEAL_FG: Paint<color::Fg<Rgb>> = Paint(color::Fg(TEAL));
+
+pub const RESET_BG: Paint<color::Bg<color::Reset>> = Paint(color::Bg(color::Reset));
+pub const RESET_FG: Paint<color::Fg<color::Reset>> = Paint(color::Fg(color::Reset));
+
+/// A colour that can be used in the foreground or the background.
+#[derive(Clone, Copy, Debug)]
+pub enum Colour {
+    Rgb(Rgb),
+    Ansi(color::AnsiValue),
+}
+
+impl Colour {
+    /// Parses one of the pastel colour names, e.g. `dark_blue`, an ANSI colour code
+    /// from 0 to 255, or a hex colour like `#71bef2`.
+    pub fn parse(colour: &str) -> Option<Self> {
+        let colour = colour.trim();
+        let rgb = match colour.to_lowercase().replace('-', "_").as_str() {
+            "blue" => Some(BLUE),
+            "dark_blue" => Some(DARK_BLUE),
+            "dark_green" => Some(DARK_GREEN),
+            "dark_grey" => Some(DARK_GREY),
+            "green" => Some(GREEN),
+            "grey" => Some(GREY),
+            "orange" => Some(ORANGE),
+            "pink" => Some(PINK),
+            "red" => Some(RED),
+            "teal" => Some(TEAL),
+            _ => None,
+        };
+        if let Some(rgb) = rgb {
+            Some(Colour::Rgb(rgb))
+        } else if let Ok(code) = colour.parse::<u8>() {
+            Some(Colour::Ansi(color::AnsiValue(code)))
+        } else {
+            let hex = colour.strip_prefix('#')?;
+            if hex.len() != 6 {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            Some(Colour::Rgb(Rgb(channel(0)?, channel(2)?, channel(4)?)))
+        }
+    }
+
+    /// The escape code that sets this as the foreground colour.
+    pub fn fg(&self) -> String {
+        if !enabled() {
+            return String::new();
+        }
+        match self {
+            Colour::Rgb(rgb) => color::Fg(*rgb).to_string(),
+            Colour::Ansi(ansi) => color::Fg(*ansi).to_string(),
+        }
+    }
+
+    /// The escape code that sets this as the background colour.
+    pub fn bg(&self) -> String {
+        if !enabled() {
+            return String::new();
+        }
+        match self {
+            Colour::Rgb(rgb) => color::Bg(*rgb).to_string(),
+            Colour::Ansi(ansi) => color::Bg(*ansi).to_string(),
+        }
+    }
+}